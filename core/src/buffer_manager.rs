@@ -1,18 +1,82 @@
+use std::ops::Range;
+
 use log::info;
 use serde::Deserialize;
 use web_sys::js_sys::Math::abs;
 use wgpu::{
     BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
-    BindGroupLayoutEntry, BindingType, Buffer, BufferUsages, Device, ShaderStages,
+    BindGroupLayoutEntry, BindingType, Buffer, BufferUsages, Device, Queue, ShaderStages,
     util::{BufferInitDescriptor, DeviceExt},
 };
 
+use crate::{
+    camera::CameraUniform,
+    error::{Error, Result},
+    mesh::MeshData,
+};
+
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
-    pos: [f32; 3],
-    color: [f32; 3],
-    tex_pos: [f32; 2],
+    pub pos: [f32; 3],
+    pub color: [f32; 3],
+    pub tex_pos: [f32; 2],
+    pub normal: [f32; 3],
+    pub tangent: [f32; 4],
+    pub tex_pos_1: [f32; 2],
+}
+
+/// Number of floats per instance in the typed arrays sent from JS: a column major
+/// 4x4 model matrix, an RGBA color and the texture index.
+pub const INSTANCE_FLOATS: usize = 21;
+
+/// Texture index of instances that only use their color.
+pub const NO_TEXTURE: u32 = u32::MAX;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct InstanceRaw {
+    pub model: [[f32; 4]; 4],
+    pub color: [f32; 4],
+    pub tex_index: u32,
+    _padding: [u32; 3],
+}
+
+impl Default for InstanceRaw {
+    fn default() -> Self {
+        Self {
+            model: glam::Mat4::IDENTITY.to_cols_array_2d(),
+            color: [1.0, 1.0, 1.0, 1.0],
+            tex_index: 0,
+            _padding: [0; 3],
+        }
+    }
+}
+
+impl InstanceRaw {
+    /// Reads one instance laid out as described by `INSTANCE_FLOATS`.
+    /// A negative texture index disables texturing.
+    pub fn from_floats(data: &[f32]) -> Self {
+        let tex_index = data[20];
+        Self {
+            model: std::array::from_fn(|column| std::array::from_fn(|row| data[column * 4 + row])),
+            color: [data[16], data[17], data[18], data[19]],
+            tex_index: if tex_index < 0.0 {
+                NO_TEXTURE
+            } else {
+                tex_index as u32
+            },
+            _padding: [0; 3],
+        }
+    }
+
+    /// The same instance placed relative to `parent` instead of the origin.
+    pub fn transformed(&self, parent: glam::Mat4) -> Self {
+        Self {
+            model: (parent * glam::Mat4::from_cols_array_2d(&self.model)).to_cols_array_2d(),
+            ..*self
+        }
+    }
 }
 
 #[repr(C)]
@@ -22,6 +86,10 @@ pub struct MousePos {
     y: f32,
 }
 impl MousePos {
+    pub fn new(x: f32, y: f32) -> Self {
+        MousePos { x, y }
+    }
+
     fn zero() -> Self {
         MousePos { x: 0.0, y: 0.0 }
     }
@@ -42,18 +110,39 @@ pub struct PerFrameUniform {
     mouse_pos: MousePos,
 }
 
+impl PerFrameUniform {
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn delta_time(&self) -> f32 {
+        self.delta_time
+    }
+
+    pub fn mouse(&self) -> [f32; 2] {
+        [self.mouse_pos.x, self.mouse_pos.y]
+    }
+}
+
 // uniforms
 pub struct UniformManager {
     pub program_uniform_data: ProgramUniform,
     pub program_uniform_buffer: Buffer,
     pub per_frame_uniform_data: PerFrameUniform,
     pub per_frame_uniform_buffer: Buffer,
+    pub camera_uniform_data: CameraUniform,
+    pub camera_uniform_buffer: Buffer,
     pub bind_group_layout: BindGroupLayout,
     pub bind_group: BindGroup,
 }
 
 impl UniformManager {
-    pub fn new(device: &Device, width: u32, height: u32) -> Self {
+    pub fn new(
+        device: &Device,
+        width: u32,
+        height: u32,
+        camera_uniform_data: CameraUniform,
+    ) -> Self {
         let program_uniform_data = ProgramUniform {
             screen_width: width as f32,
             screen_height: height as f32,
@@ -73,13 +162,18 @@ impl UniformManager {
             contents: bytemuck::bytes_of(&per_frame_uniform_data),
             usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
         });
+        let camera_uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Camera uniform buffer"),
+            contents: bytemuck::bytes_of(&camera_uniform_data),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
 
         let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: Some("Program uniforms bind group layout"),
             entries: &[
                 BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: ShaderStages::FRAGMENT,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
                     ty: BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
@@ -97,6 +191,16 @@ impl UniformManager {
                     },
                     count: None,
                 },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         });
 
@@ -112,6 +216,10 @@ impl UniformManager {
                     binding: 1,
                     resource: per_frame_uniform_buffer.as_entire_binding(),
                 },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: camera_uniform_buffer.as_entire_binding(),
+                },
             ],
         });
         Self {
@@ -119,6 +227,8 @@ impl UniformManager {
             program_uniform_buffer,
             per_frame_uniform_data,
             per_frame_uniform_buffer,
+            camera_uniform_data,
+            camera_uniform_buffer,
             bind_group_layout,
             bind_group,
         }
@@ -153,59 +263,184 @@ impl UniformManager {
         };
         self.per_frame_uniform_data = per_frame_uniform_data;
     }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.program_uniform_data = ProgramUniform {
+            screen_width: width as f32,
+            screen_height: height as f32,
+        };
+    }
 }
 
 // Buffers
-pub struct BufferManager {
+pub struct Mesh {
     pub vertex_buffer: Buffer,
     pub index_buffer: Buffer,
     pub index_length: u32,
-    pub uniform_manager: UniformManager,
+    /// Material of the nodes using this mesh that don't set their own.
+    pub material: Option<usize>,
+    /// Instances drawn for every scene node using this mesh, relative to the node.
+    pub instances: Vec<InstanceRaw>,
+    // uploaded again when the device is lost
+    data: MeshData,
 }
 
-impl BufferManager {
-    pub fn new(device: &Device, width: u32, height: u32) -> Self {
-        info!("Creating vertex buffer");
-        let vertex_data: [Vertex; 4] = [
-            Vertex {
-                pos: [-1.0, -1.0, 0.0],
-                color: [1.0, 0.0, 0.0],
-                tex_pos: [0.0, 1.0],
-            },
-            Vertex {
-                pos: [-1.0, 1.0, 0.0],
-                color: [0.0, 1.0, 0.0],
-                tex_pos: [0.0, 0.0],
-            },
-            Vertex {
-                pos: [1.0, 1.0, 0.0],
-                color: [0.0, 0.0, 1.0],
-                tex_pos: [1.0, 0.0],
-            },
-            Vertex {
-                pos: [1.0, -1.0, 0.0],
-                color: [1.0, 1.0, 1.0],
-                tex_pos: [1.0, 1.0],
-            },
-        ];
-        let index_data: [[u16; 3]; 2] = [[0, 1, 2], [0, 2, 3]];
-        let index_length = index_data.len() as u32 * 3; // flattened size
+impl Mesh {
+    pub fn new(device: &Device, data: &MeshData) -> Self {
+        let label = data.name.as_deref().unwrap_or("Mesh");
         let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::bytes_of(&vertex_data),
+            label: Some(&format!("{label} Vertex Buffer")),
+            contents: bytemuck::cast_slice(&data.vertices),
             usage: BufferUsages::VERTEX,
         });
         let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::bytes_of(&index_data),
+            label: Some(&format!("{label} Index Buffer")),
+            contents: bytemuck::cast_slice(&data.indices),
             usage: BufferUsages::INDEX,
         });
-        let uniform_manager = UniformManager::new(&device, width, height);
         Self {
             vertex_buffer,
             index_buffer,
-            index_length,
+            index_length: data.indices.len() as u32,
+            material: data.material,
+            // a single untransformed instance until JS supplies its own
+            instances: vec![InstanceRaw::default()],
+            data: data.clone(),
+        }
+    }
+
+    fn destroy(&self) {
+        self.vertex_buffer.destroy();
+        self.index_buffer.destroy();
+    }
+}
+
+/// Per-instance vertex buffer rewritten every frame, grown when the instances no longer fit.
+pub struct InstanceBuffer {
+    pub buffer: Buffer,
+    capacity: usize,
+}
+
+impl InstanceBuffer {
+    pub fn new(device: &Device, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instance Buffer"),
+            size: (capacity * std::mem::size_of::<InstanceRaw>()) as u64,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self { buffer, capacity }
+    }
+
+    pub fn write(&mut self, device: &Device, queue: &Queue, instances: &[InstanceRaw]) {
+        if instances.len() > self.capacity {
+            *self = Self::new(device, instances.len().next_power_of_two());
+        }
+        if !instances.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(instances));
+        }
+    }
+}
+
+pub struct BufferManager {
+    /// By handle, `None` for removed meshes so later handles stay valid.
+    pub meshes: Vec<Option<Mesh>>,
+    pub uniform_manager: UniformManager,
+}
+
+impl BufferManager {
+    pub fn new(
+        device: &Device,
+        width: u32,
+        height: u32,
+        camera_uniform_data: CameraUniform,
+    ) -> Self {
+        info!("Creating vertex buffer");
+        let quad = Mesh::new(device, &MeshData::quad());
+        let uniform_manager = UniformManager::new(device, width, height, camera_uniform_data);
+        Self {
+            meshes: vec![Some(quad)],
             uniform_manager,
         }
     }
+
+    /// Uploads the meshes and uniforms again on another device, keeping instances,
+    /// mesh materials and uniform values.
+    pub fn recreate(&self, device: &Device, queue: &Queue) -> Self {
+        let uniforms = &self.uniform_manager;
+        let mut uniform_manager = UniformManager::new(device, 1, 1, uniforms.camera_uniform_data);
+        uniform_manager.program_uniform_data = uniforms.program_uniform_data;
+        uniform_manager.per_frame_uniform_data = uniforms.per_frame_uniform_data;
+        queue.write_buffer(
+            &uniform_manager.program_uniform_buffer,
+            0,
+            bytemuck::bytes_of(&uniform_manager.program_uniform_data),
+        );
+        queue.write_buffer(
+            &uniform_manager.per_frame_uniform_buffer,
+            0,
+            bytemuck::bytes_of(&uniform_manager.per_frame_uniform_data),
+        );
+        let meshes = self
+            .meshes
+            .iter()
+            .map(|mesh| {
+                mesh.as_ref().map(|mesh| Mesh {
+                    material: mesh.material,
+                    instances: mesh.instances.clone(),
+                    ..Mesh::new(device, &mesh.data)
+                })
+            })
+            .collect();
+        Self {
+            meshes,
+            uniform_manager,
+        }
+    }
+
+    /// Uploads the mesh and returns its handle. Meshes without triangles are rejected,
+    /// their buffers would be empty and can't be drawn.
+    pub fn add_mesh(&mut self, device: &Device, data: &MeshData) -> Result<usize> {
+        if data.is_empty() {
+            return Err(Error::InvalidInput(format!(
+                "Mesh {} has no triangles",
+                data.name.as_deref().unwrap_or("<unnamed>")
+            )));
+        }
+        self.meshes.push(Some(Mesh::new(device, data)));
+        Ok(self.meshes.len() - 1)
+    }
+
+    pub fn mesh(&self, mesh: usize) -> Result<&Mesh> {
+        self.meshes
+            .get(mesh)
+            .and_then(Option::as_ref)
+            .ok_or_else(|| Error::invalid_handle("mesh", mesh))
+    }
+
+    pub fn mesh_mut(&mut self, mesh: usize) -> Result<&mut Mesh> {
+        self.meshes
+            .get_mut(mesh)
+            .and_then(Option::as_mut)
+            .ok_or_else(|| Error::invalid_handle("mesh", mesh))
+    }
+
+    /// Frees the buffers of the meshes with the given handles, which become invalid.
+    pub fn remove_meshes(&mut self, meshes: Range<usize>) {
+        for mesh in self.meshes[meshes].iter_mut().filter_map(Option::take) {
+            mesh.destroy();
+        }
+    }
+
+    /// Frees the mesh and uniform buffers. The manager can't be used afterwards.
+    pub fn destroy(&mut self) {
+        for mesh in self.meshes.drain(..).flatten() {
+            mesh.destroy();
+        }
+        let uniforms = &self.uniform_manager;
+        uniforms.program_uniform_buffer.destroy();
+        uniforms.per_frame_uniform_buffer.destroy();
+        uniforms.camera_uniform_buffer.destroy();
+    }
 }
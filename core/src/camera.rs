@@ -0,0 +1,303 @@
+use std::f32::consts::FRAC_PI_2;
+
+use glam::{Mat4, Vec2, Vec3};
+use serde::Deserialize;
+
+// keeps the orbit/fly pitch away from the poles where `look_at` degenerates
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+const LEFT_BUTTON: u32 = 1;
+const RIGHT_BUTTON: u32 = 2;
+const MIDDLE_BUTTON: u32 = 4;
+
+/// Mouse state sent by JS with every update. `buttons` follows `MouseEvent.buttons`
+/// and `wheel` is the scroll amount accumulated since the previous update.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct MouseState {
+    pub x: f32,
+    pub y: f32,
+    #[serde(default)]
+    pub buttons: u32,
+    #[serde(default)]
+    pub wheel: f32,
+}
+
+impl MouseState {
+    fn pressed(&self, button: u32) -> bool {
+        self.buttons & button != 0
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Projection {
+    /// `fovy` is the vertical field of view in radians.
+    Perspective { fovy: f32, znear: f32, zfar: f32 },
+    /// The view volume is `height` units tall. Without a `width` it follows the aspect ratio.
+    Orthographic {
+        width: Option<f32>,
+        height: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+impl Projection {
+    pub fn depth_range(&self) -> (f32, f32) {
+        match *self {
+            Projection::Perspective { znear, zfar, .. }
+            | Projection::Orthographic { znear, zfar, .. } => (znear, zfar),
+        }
+    }
+
+    pub fn matrix(&self, aspect: f32) -> Mat4 {
+        match *self {
+            Projection::Perspective { fovy, znear, zfar } => {
+                Mat4::perspective_rh(fovy, aspect, znear, zfar)
+            }
+            Projection::Orthographic {
+                width,
+                height,
+                znear,
+                zfar,
+            } => {
+                let half_height = height / 2.0;
+                let half_width = width.map_or(half_height * aspect, |width| width / 2.0);
+                Mat4::orthographic_rh(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    znear,
+                    zfar,
+                )
+            }
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CameraUniform {
+    view: [[f32; 4]; 4],
+    projection: [[f32; 4]; 4],
+    view_projection: [[f32; 4]; 4],
+    position: [f32; 4],
+}
+
+#[derive(Debug, Clone)]
+pub struct Camera {
+    pub eye: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    pub aspect: f32,
+    pub projection: Projection,
+}
+
+impl Camera {
+    /// Orthographic camera looking down -Z whose view volume matches clip space,
+    /// so the fullscreen quad keeps covering the canvas.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            eye: Vec3::Z,
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            aspect: aspect_ratio(width, height),
+            projection: Projection::Orthographic {
+                width: Some(2.0),
+                height: 2.0,
+                znear: 0.1,
+                zfar: 100.0,
+            },
+        }
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.aspect = aspect_ratio(width, height);
+    }
+
+    pub fn view_matrix(&self) -> Mat4 {
+        Mat4::look_at_rh(self.eye, self.target, self.up)
+    }
+
+    pub fn projection_matrix(&self) -> Mat4 {
+        self.projection.matrix(self.aspect)
+    }
+
+    pub fn uniform(&self) -> CameraUniform {
+        let view = self.view_matrix();
+        let projection = self.projection_matrix();
+        CameraUniform {
+            view: view.to_cols_array_2d(),
+            projection: projection.to_cols_array_2d(),
+            view_projection: (projection * view).to_cols_array_2d(),
+            position: self.eye.extend(1.0).to_array(),
+        }
+    }
+
+    fn forward(&self) -> Vec3 {
+        (self.target - self.eye).normalize_or(Vec3::NEG_Z)
+    }
+}
+
+/// Rotates around a target while the left button is held, pans with the right or
+/// middle button and zooms with the wheel.
+#[derive(Debug, Clone)]
+pub struct OrbitController {
+    pub yaw: f32,
+    pub pitch: f32,
+    pub distance: f32,
+    pub rotate_speed: f32,
+    pub zoom_speed: f32,
+}
+
+impl OrbitController {
+    pub fn from_camera(camera: &Camera) -> Self {
+        let offset = camera.eye - camera.target;
+        let distance = offset.length().max(0.01);
+        Self {
+            yaw: offset.x.atan2(offset.z),
+            pitch: (offset.y / distance).clamp(-1.0, 1.0).asin(),
+            distance,
+            rotate_speed: 0.005,
+            zoom_speed: 0.001,
+        }
+    }
+
+    fn update(&mut self, camera: &mut Camera, mouse: &MouseState, mouse_delta: Vec2) {
+        if mouse.pressed(LEFT_BUTTON) {
+            self.yaw -= mouse_delta.x * self.rotate_speed;
+            self.pitch =
+                (self.pitch + mouse_delta.y * self.rotate_speed).clamp(-MAX_PITCH, MAX_PITCH);
+        } else if mouse.pressed(RIGHT_BUTTON | MIDDLE_BUTTON) {
+            // pan proportionally to the distance so the target follows the cursor
+            let right = camera.forward().cross(camera.up).normalize_or_zero();
+            let up = right.cross(camera.forward());
+            let pan = (up * mouse_delta.y - right * mouse_delta.x) * self.distance * 0.002;
+            camera.target += pan;
+        }
+        self.distance = (self.distance * (mouse.wheel * self.zoom_speed).exp()).max(0.01);
+
+        let direction = Vec3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        );
+        camera.eye = camera.target + direction * self.distance;
+    }
+}
+
+/// Looks around while the left button is held and moves with the wheel or the
+/// movement set from JS (e.g. WASD keys).
+#[derive(Debug, Clone)]
+pub struct FlyController {
+    pub yaw: f32,
+    pub pitch: f32,
+    pub speed: f32,
+    pub look_speed: f32,
+    /// Movement direction in camera space: x is right, y is up and z is forward.
+    pub movement: Vec3,
+}
+
+impl FlyController {
+    pub fn from_camera(camera: &Camera) -> Self {
+        let forward = camera.forward();
+        Self {
+            yaw: forward.x.atan2(-forward.z),
+            pitch: forward.y.clamp(-1.0, 1.0).asin(),
+            speed: 2.0,
+            look_speed: 0.003,
+            movement: Vec3::ZERO,
+        }
+    }
+
+    fn update(
+        &mut self,
+        camera: &mut Camera,
+        mouse: &MouseState,
+        mouse_delta: Vec2,
+        delta_time: f32,
+    ) {
+        if mouse.pressed(LEFT_BUTTON) {
+            self.yaw += mouse_delta.x * self.look_speed;
+            self.pitch =
+                (self.pitch - mouse_delta.y * self.look_speed).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+        let forward = Vec3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            -self.pitch.cos() * self.yaw.cos(),
+        );
+        let right = forward.cross(camera.up).normalize_or_zero();
+        let up = right.cross(forward);
+
+        let movement = right * self.movement.x + up * self.movement.y + forward * self.movement.z;
+        camera.eye += movement.normalize_or_zero() * self.speed * delta_time;
+        camera.eye -= forward * mouse.wheel * self.speed * 0.001;
+        camera.target = camera.eye + forward;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum CameraController {
+    None,
+    Orbit(OrbitController),
+    Fly(FlyController),
+}
+
+pub struct CameraManager {
+    pub camera: Camera,
+    pub controller: CameraController,
+    last_mouse: Option<MouseState>,
+}
+
+impl CameraManager {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            camera: Camera::new(width, height),
+            controller: CameraController::None,
+            last_mouse: None,
+        }
+    }
+
+    /// Accepts `"none"`, `"orbit"` or `"fly"`; the controller starts from the current view.
+    pub fn set_controller(&mut self, name: &str) -> Result<(), String> {
+        self.controller = match name {
+            "none" => CameraController::None,
+            "orbit" => CameraController::Orbit(OrbitController::from_camera(&self.camera)),
+            "fly" => CameraController::Fly(FlyController::from_camera(&self.camera)),
+            _ => return Err(format!("Unknown camera controller: {name}")),
+        };
+        Ok(())
+    }
+
+    pub fn controller_name(&self) -> &'static str {
+        match self.controller {
+            CameraController::None => "none",
+            CameraController::Orbit(_) => "orbit",
+            CameraController::Fly(_) => "fly",
+        }
+    }
+
+    pub fn update(&mut self, mouse: Option<MouseState>, delta_time: f32) {
+        let mouse = mouse.unwrap_or_else(|| MouseState {
+            wheel: 0.0,
+            ..self.last_mouse.unwrap_or_default()
+        });
+        let mouse_delta = self.last_mouse.map_or(Vec2::ZERO, |last| {
+            Vec2::new(mouse.x - last.x, mouse.y - last.y)
+        });
+        self.last_mouse = Some(mouse);
+
+        match &mut self.controller {
+            CameraController::None => {}
+            CameraController::Orbit(orbit) => orbit.update(&mut self.camera, &mouse, mouse_delta),
+            CameraController::Fly(fly) => {
+                fly.update(&mut self.camera, &mouse, mouse_delta, delta_time)
+            }
+        }
+    }
+}
+
+fn aspect_ratio(width: u32, height: u32) -> f32 {
+    width.max(1) as f32 / height.max(1) as f32
+}
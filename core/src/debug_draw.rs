@@ -0,0 +1,416 @@
+use std::f32::consts::TAU;
+
+use glam::{Quat, Vec3};
+use log::info;
+use serde::{Deserialize, Deserializer, Serialize, de::Error};
+use wgpu::{
+    BindGroup, BindGroupLayout, BlendState, Buffer, BufferAddress, BufferUsages, ColorTargetState,
+    ColorWrites, Device, FragmentState, MultisampleState, PipelineCompilationOptions,
+    PipelineLayout, PipelineLayoutDescriptor, PrimitiveState, PrimitiveTopology, Queue, RenderPass,
+    RenderPipeline, RenderPipelineDescriptor, ShaderModule, ShaderModuleDescriptor, TextureFormat,
+    VertexAttribute, VertexBufferLayout, VertexState,
+};
+
+// line segments of a circle
+const CIRCLE_SEGMENTS: usize = 32;
+
+// matches `DebugVertex` in debug.wgsl
+const DEBUG_ATTRIBUTES: [VertexAttribute; 2] = wgpu::vertex_attr_array![
+    0 => Float32x3,
+    1 => Float32x4,
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DebugSpace {
+    /// World units, seen through the camera.
+    #[default]
+    World,
+    /// Pixels from the top left corner of the canvas. Shapes lie in the screen plane,
+    /// so z coordinates and normals are ignored.
+    Screen,
+}
+
+/// A shape made of lines. Points are `[x, y, z]`, or `[x, y]` with z = 0.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum DebugShape {
+    Line {
+        #[serde(deserialize_with = "point")]
+        from: Vec3,
+        #[serde(deserialize_with = "point")]
+        to: Vec3,
+    },
+    /// Rectangle of `size` around `center`, in the plane facing `normal`.
+    Rect {
+        #[serde(deserialize_with = "point")]
+        center: Vec3,
+        size: [f32; 2],
+        #[serde(default = "z_axis", deserialize_with = "point")]
+        normal: Vec3,
+    },
+    Circle {
+        #[serde(deserialize_with = "point")]
+        center: Vec3,
+        radius: f32,
+        #[serde(default = "z_axis", deserialize_with = "point")]
+        normal: Vec3,
+    },
+    /// Axis aligned box between two corners.
+    Aabb {
+        #[serde(deserialize_with = "point")]
+        min: Vec3,
+        #[serde(deserialize_with = "point")]
+        max: Vec3,
+    },
+    /// Red, green and blue lines of `size` along the x, y and z axes of a transform,
+    /// whatever the color.
+    Axes {
+        #[serde(deserialize_with = "point")]
+        position: Vec3,
+        /// Quaternion as `[x, y, z, w]`.
+        #[serde(default = "identity")]
+        rotation: [f32; 4],
+        #[serde(default = "one")]
+        size: f32,
+    },
+    /// Square grid of `size` split into `divisions` cells per side, on the ground by
+    /// default.
+    Grid {
+        #[serde(deserialize_with = "point")]
+        center: Vec3,
+        size: f32,
+        divisions: u32,
+        #[serde(default = "y_axis", deserialize_with = "point")]
+        normal: Vec3,
+    },
+}
+
+/// A queued shape as described from JS, e.g. `{ type: "circle", center: [100, 100],
+/// radius: 40, space: "screen", color: [1, 0, 0, 1] }`.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DebugDraw {
+    #[serde(flatten)]
+    pub shape: DebugShape,
+    #[serde(default)]
+    pub space: DebugSpace,
+    #[serde(default = "white")]
+    pub color: [f32; 4],
+}
+
+fn point<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec3, D::Error> {
+    match Vec::<f32>::deserialize(deserializer)?[..] {
+        [x, y] => Ok(Vec3::new(x, y, 0.0)),
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        ref values => Err(D::Error::invalid_length(
+            values.len(),
+            &"2 or 3 coordinates",
+        )),
+    }
+}
+
+fn z_axis() -> Vec3 {
+    Vec3::Z
+}
+
+fn y_axis() -> Vec3 {
+    Vec3::Y
+}
+
+fn identity() -> [f32; 4] {
+    Quat::IDENTITY.to_array()
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+fn white() -> [f32; 4] {
+    [1.0, 1.0, 1.0, 1.0]
+}
+
+// two perpendicular directions spanning the plane facing `normal`
+fn plane_axes(space: DebugSpace, normal: Vec3) -> (Vec3, Vec3) {
+    let normal = match space {
+        DebugSpace::World => normal.normalize_or(Vec3::Z),
+        DebugSpace::Screen => Vec3::Z,
+    };
+    let up = if normal.y.abs() < 0.999 {
+        Vec3::Y
+    } else {
+        Vec3::Z
+    };
+    let u = up.cross(normal).normalize();
+    (u, normal.cross(u))
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct DebugVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+
+/// Draws the lines queued during a frame, world space ones through the camera and then
+/// screen space ones, without depth testing.
+pub struct DebugRenderer {
+    world_pipeline: RenderPipeline,
+    screen_pipeline: RenderPipeline,
+    vertex_buffer: Buffer,
+    capacity: usize,
+    world_vertices: u32,
+    screen_vertices: u32,
+    enabled: bool,
+    // shapes queued for the next frame, already turned into lines
+    world: Vec<DebugVertex>,
+    screen: Vec<DebugVertex>,
+}
+
+impl DebugRenderer {
+    pub fn new(
+        device: &Device,
+        swapchain_format: TextureFormat,
+        uniform_layout: &BindGroupLayout,
+    ) -> Self {
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Debug Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("./shader/debug.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Debug Pipeline Layout"),
+            bind_group_layouts: &[uniform_layout],
+            push_constant_ranges: &[],
+        });
+        let world_pipeline = Self::create_pipeline(
+            device,
+            swapchain_format,
+            &pipeline_layout,
+            (&shader, "vs_world"),
+        );
+        let screen_pipeline = Self::create_pipeline(
+            device,
+            swapchain_format,
+            &pipeline_layout,
+            (&shader, "vs_screen"),
+        );
+        info!("Debug renderer created successfully!");
+        Self {
+            world_pipeline,
+            screen_pipeline,
+            vertex_buffer: Self::create_vertex_buffer(device, 1024),
+            capacity: 1024,
+            world_vertices: 0,
+            screen_vertices: 0,
+            enabled: true,
+            world: Vec::new(),
+            screen: Vec::new(),
+        }
+    }
+
+    /// Queues a shape for the next frame. Does nothing while debug drawing is disabled.
+    pub fn queue(&mut self, shape: DebugShape, space: DebugSpace, color: [f32; 4]) {
+        if !self.enabled {
+            return;
+        }
+        let vertices = match space {
+            DebugSpace::World => &mut self.world,
+            DebugSpace::Screen => &mut self.screen,
+        };
+        let mut line = |from: Vec3, to: Vec3, color: [f32; 4]| {
+            vertices.extend([from, to].map(|position| DebugVertex {
+                position: position.to_array(),
+                color,
+            }));
+        };
+        match shape {
+            DebugShape::Line { from, to } => line(from, to, color),
+            DebugShape::Rect {
+                center,
+                size,
+                normal,
+            } => {
+                let (u, v) = plane_axes(space, normal);
+                let (u, v) = (u * size[0] / 2.0, v * size[1] / 2.0);
+                let corners = [-u - v, u - v, u + v, -u + v].map(|corner| center + corner);
+                for i in 0..4 {
+                    line(corners[i], corners[(i + 1) % 4], color);
+                }
+            }
+            DebugShape::Circle {
+                center,
+                radius,
+                normal,
+            } => {
+                let (u, v) = plane_axes(space, normal);
+                let point = |i: usize| {
+                    let angle = i as f32 / CIRCLE_SEGMENTS as f32 * TAU;
+                    center + (u * angle.cos() + v * angle.sin()) * radius
+                };
+                for i in 0..CIRCLE_SEGMENTS {
+                    line(point(i), point(i + 1), color);
+                }
+            }
+            DebugShape::Aabb { min, max } => {
+                let corner = |i: usize| {
+                    Vec3::new(
+                        if i & 1 == 0 { min.x } else { max.x },
+                        if i & 2 == 0 { min.y } else { max.y },
+                        if i & 4 == 0 { min.z } else { max.z },
+                    )
+                };
+                // every edge joins two corners differing in one axis
+                for i in 0..8 {
+                    for axis in [1, 2, 4] {
+                        if i & axis == 0 {
+                            line(corner(i), corner(i | axis), color);
+                        }
+                    }
+                }
+            }
+            DebugShape::Axes {
+                position,
+                rotation,
+                size,
+            } => {
+                let rotation = Quat::from_array(rotation).normalize();
+                let axes = [
+                    (Vec3::X, [1.0, 0.0, 0.0, 1.0]),
+                    (Vec3::Y, [0.0, 1.0, 0.0, 1.0]),
+                    (Vec3::Z, [0.0, 0.0, 1.0, 1.0]),
+                ];
+                for (axis, color) in axes {
+                    line(position, position + rotation * axis * size, color);
+                }
+            }
+            DebugShape::Grid {
+                center,
+                size,
+                divisions,
+                normal,
+            } => {
+                let (u, v) = plane_axes(space, normal);
+                let divisions = divisions.max(1);
+                let half = size / 2.0;
+                for i in 0..=divisions {
+                    let offset = i as f32 / divisions as f32 * size - half;
+                    line(
+                        center + u * offset - v * half,
+                        center + u * offset + v * half,
+                        color,
+                    );
+                    line(
+                        center + v * offset - u * half,
+                        center + v * offset + u * half,
+                        color,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Turns debug drawing on or off, dropping what was queued when turned off.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.world.clear();
+            self.screen.clear();
+        }
+    }
+
+    /// Uploads the lines queued since the last frame, which starts the next one empty.
+    pub fn prepare(&mut self, device: &Device, queue: &Queue) {
+        let (world, screen) = (
+            std::mem::take(&mut self.world),
+            std::mem::take(&mut self.screen),
+        );
+        self.world_vertices = world.len() as u32;
+        self.screen_vertices = screen.len() as u32;
+        let vertices = [world, screen].concat();
+        if vertices.is_empty() {
+            return;
+        }
+        if vertices.len() > self.capacity {
+            self.capacity = vertices.len().next_power_of_two();
+            self.vertex_buffer = Self::create_vertex_buffer(device, self.capacity);
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.world_vertices == 0 && self.screen_vertices == 0
+    }
+
+    /// Frees the vertex buffer. The renderer can't be used afterwards.
+    pub fn destroy(&self) {
+        self.vertex_buffer.destroy();
+    }
+
+    /// Draws the prepared lines, `uniform_bind_group` provides the camera and screen size.
+    pub fn draw(&self, render_pass: &mut RenderPass, uniform_bind_group: &BindGroup) {
+        let screen_end = self.world_vertices + self.screen_vertices;
+        render_pass.set_bind_group(0, uniform_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        if self.world_vertices > 0 {
+            render_pass.set_pipeline(&self.world_pipeline);
+            render_pass.draw(0..self.world_vertices, 0..1);
+        }
+        if self.screen_vertices > 0 {
+            render_pass.set_pipeline(&self.screen_pipeline);
+            render_pass.draw(self.world_vertices..screen_end, 0..1);
+        }
+    }
+
+    fn create_vertex_buffer(device: &Device, capacity: usize) -> Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Debug Vertex Buffer"),
+            size: (capacity * std::mem::size_of::<DebugVertex>()) as u64,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_pipeline(
+        device: &Device,
+        swapchain_format: TextureFormat,
+        pipeline_layout: &PipelineLayout,
+        (shader, vertex_entry): (&ShaderModule, &str),
+    ) -> RenderPipeline {
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Debug Render Pipeline"),
+            layout: Some(pipeline_layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: Some(vertex_entry),
+                compilation_options: PipelineCompilationOptions::default(),
+                buffers: &[VertexBufferLayout {
+                    array_stride: std::mem::size_of::<DebugVertex>() as BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &DEBUG_ATTRIBUTES,
+                }],
+            },
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: Some("fs_main"),
+                compilation_options: PipelineCompilationOptions::default(),
+                targets: &[Some(ColorTargetState {
+                    format: swapchain_format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        })
+    }
+}
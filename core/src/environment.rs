@@ -0,0 +1,437 @@
+use half::f16;
+use image::ImageFormat;
+use log::info;
+use wgpu::{
+    BindGroupDescriptor, BindGroupEntry, BindingResource, Buffer, BufferUsages, CommandEncoder,
+    CommandEncoderDescriptor, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor,
+    Device, Extent3d, FilterMode, Queue, Sampler, SamplerDescriptor, ShaderModule,
+    ShaderModuleDescriptor, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension,
+    util::{BufferInitDescriptor, DeviceExt, TextureDataOrder},
+};
+
+use crate::error::{Error, Result};
+
+pub const ENVIRONMENT_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+/// Mips of the prefiltered map, the last one holds roughness 1.
+pub const PREFILTERED_MIPS: u32 = 5;
+
+const CUBE_SIZE: u32 = 512;
+const IRRADIANCE_SIZE: u32 = 32;
+const PREFILTERED_SIZE: u32 = 128;
+const BRDF_LUT_SIZE: u32 = 256;
+const SAMPLE_COUNT: u32 = 256;
+// matches @workgroup_size in ibl.wgsl
+const WORKGROUP_SIZE: u32 = 8;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct BakeParams {
+    roughness: f32,
+    sample_count: u32,
+    source_size: f32,
+    source_lod: f32,
+}
+
+/// Image based lighting baked from an HDR environment: the environment cube drawn as
+/// the skybox, its diffuse irradiance, the specular radiance prefiltered by roughness
+/// into the mips of another cube, and the lookup table of the split sum approximation.
+pub struct Environment {
+    pub cube_view: TextureView,
+    pub irradiance_view: TextureView,
+    pub prefiltered_view: TextureView,
+    pub brdf_lut_view: TextureView,
+    // the HDR image, baked again when the device is lost
+    hdr: Vec<u8>,
+    // behind the views, for `destroy`
+    textures: Vec<Texture>,
+}
+
+impl Environment {
+    /// Black 1x1 maps, so image based lighting adds nothing until an environment is loaded.
+    pub fn empty(device: &Device) -> Self {
+        // never baked into, so they also work without storage textures
+        let usage = TextureUsages::TEXTURE_BINDING;
+        let cube = create_cube(device, "Empty Environment", 1, 1, usage);
+        let lut = create_brdf_lut(device, 1, usage);
+        Self {
+            cube_view: cube_view(&cube),
+            irradiance_view: cube_view(&cube),
+            prefiltered_view: cube_view(&cube),
+            brdf_lut_view: lut.create_view(&TextureViewDescriptor::default()),
+            hdr: Vec::new(),
+            textures: vec![cube, lut],
+        }
+    }
+
+    /// Decodes an equirectangular Radiance HDR image and bakes the maps on the GPU.
+    pub fn from_hdr(device: &Device, queue: &Queue, data: &[u8]) -> Result<Self> {
+        let image = image::load_from_memory_with_format(data, ImageFormat::Hdr)
+            .map_err(|e| Error::TextureDecode(format!("Failed to load HDR image: {e}")))?
+            .to_rgba32f();
+        let (width, height) = image.dimensions();
+        let max_size = device.limits().max_texture_dimension_2d;
+        if width > max_size || height > max_size {
+            return Err(Error::TextureDecode(format!(
+                "HDR image of {width}x{height} exceeds the texture size limit of {max_size}"
+            )));
+        }
+        // 32 bit floats can't be filtered without an optional feature
+        let pixels: Vec<u16> = image
+            .as_raw()
+            .iter()
+            .map(|&value| f16::from_f32(value).to_bits())
+            .collect();
+        let equirect = device.create_texture_with_data(
+            queue,
+            &TextureDescriptor {
+                label: Some("Equirectangular Environment"),
+                size: Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: ENVIRONMENT_FORMAT,
+                usage: TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            TextureDataOrder::LayerMajor,
+            bytemuck::cast_slice(&pixels),
+        );
+        let equirect_view = equirect.create_view(&TextureViewDescriptor::default());
+
+        let baker = Baker::new(device);
+        let cube_mips = CUBE_SIZE.ilog2() + 1;
+        let cube = create_cube(
+            device,
+            "Environment Cube",
+            CUBE_SIZE,
+            cube_mips,
+            BAKED_USAGE,
+        );
+        let irradiance = create_cube(device, "Irradiance Cube", IRRADIANCE_SIZE, 1, BAKED_USAGE);
+        let prefiltered = create_cube(
+            device,
+            "Prefiltered Cube",
+            PREFILTERED_SIZE,
+            PREFILTERED_MIPS,
+            BAKED_USAGE,
+        );
+        let brdf_lut = create_brdf_lut(device, BRDF_LUT_SIZE, BAKED_USAGE);
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Environment Encoder"),
+        });
+
+        baker.dispatch(
+            device,
+            &mut encoder,
+            &baker.equirect_to_cube,
+            &[
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&baker.sampler),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(&equirect_view),
+                },
+                BindGroupEntry {
+                    binding: 5,
+                    resource: BindingResource::TextureView(&mip_view(&cube, 0)),
+                },
+            ],
+            [CUBE_SIZE, CUBE_SIZE, 6],
+        );
+        // the mips let the convolutions read from a resolution matching their sample density
+        for mip in 1..cube_mips {
+            let size = CUBE_SIZE >> mip;
+            baker.dispatch(
+                device,
+                &mut encoder,
+                &baker.downsample,
+                &[
+                    BindGroupEntry {
+                        binding: 4,
+                        resource: BindingResource::TextureView(&mip_view(&cube, mip - 1)),
+                    },
+                    BindGroupEntry {
+                        binding: 5,
+                        resource: BindingResource::TextureView(&mip_view(&cube, mip)),
+                    },
+                ],
+                [size, size, 6],
+            );
+        }
+
+        let source_view = cube_view(&cube);
+        let irradiance_params = Baker::params(
+            device,
+            BakeParams {
+                source_lod: (CUBE_SIZE / IRRADIANCE_SIZE).ilog2() as f32,
+                ..Default::default()
+            },
+        );
+        baker.dispatch(
+            device,
+            &mut encoder,
+            &baker.irradiance,
+            &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: irradiance_params.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&baker.sampler),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::TextureView(&source_view),
+                },
+                BindGroupEntry {
+                    binding: 5,
+                    resource: BindingResource::TextureView(&mip_view(&irradiance, 0)),
+                },
+            ],
+            [IRRADIANCE_SIZE, IRRADIANCE_SIZE, 6],
+        );
+        for mip in 0..PREFILTERED_MIPS {
+            let size = PREFILTERED_SIZE >> mip;
+            let params = Baker::params(
+                device,
+                BakeParams {
+                    roughness: mip as f32 / (PREFILTERED_MIPS - 1) as f32,
+                    sample_count: SAMPLE_COUNT,
+                    source_size: CUBE_SIZE as f32,
+                    ..Default::default()
+                },
+            );
+            baker.dispatch(
+                device,
+                &mut encoder,
+                &baker.prefilter,
+                &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: params.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Sampler(&baker.sampler),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: BindingResource::TextureView(&source_view),
+                    },
+                    BindGroupEntry {
+                        binding: 5,
+                        resource: BindingResource::TextureView(&mip_view(&prefiltered, mip)),
+                    },
+                ],
+                [size, size, 6],
+            );
+        }
+
+        let lut_params = Baker::params(
+            device,
+            BakeParams {
+                sample_count: SAMPLE_COUNT,
+                ..Default::default()
+            },
+        );
+        let brdf_lut_view = brdf_lut.create_view(&TextureViewDescriptor::default());
+        baker.dispatch(
+            device,
+            &mut encoder,
+            &baker.brdf_lut,
+            &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: lut_params.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 6,
+                    resource: BindingResource::TextureView(&brdf_lut_view),
+                },
+            ],
+            [BRDF_LUT_SIZE, BRDF_LUT_SIZE, 1],
+        );
+        queue.submit(Some(encoder.finish()));
+        info!("Environment of {width}x{height} baked");
+
+        Ok(Self {
+            cube_view: source_view,
+            irradiance_view: cube_view(&irradiance),
+            prefiltered_view: cube_view(&prefiltered),
+            brdf_lut_view,
+            hdr: data.to_vec(),
+            textures: vec![cube, irradiance, prefiltered, brdf_lut],
+        })
+    }
+
+    /// Bakes the same environment on another device.
+    pub fn recreate(&self, device: &Device, queue: &Queue) -> Result<Self> {
+        if self.hdr.is_empty() {
+            return Ok(Self::empty(device));
+        }
+        Self::from_hdr(device, queue, &self.hdr)
+    }
+
+    /// Frees the maps, the environment can't be bound afterwards.
+    pub fn destroy(&self) {
+        for texture in &self.textures {
+            texture.destroy();
+        }
+    }
+}
+
+/// The compute pipelines of `ibl.wgsl`, each with the layout derived from its entry point.
+struct Baker {
+    sampler: Sampler,
+    equirect_to_cube: ComputePipeline,
+    downsample: ComputePipeline,
+    irradiance: ComputePipeline,
+    prefilter: ComputePipeline,
+    brdf_lut: ComputePipeline,
+}
+
+impl Baker {
+    fn new(device: &Device) -> Self {
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("IBL Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("./shader/ibl.wgsl").into()),
+        });
+        let pipeline = |entry_point: &str| Self::create_pipeline(device, &module, entry_point);
+        Self {
+            // wraps around horizontally for the equirectangular image, cubes ignore it
+            sampler: device.create_sampler(&SamplerDescriptor {
+                label: Some("IBL Sampler"),
+                address_mode_u: wgpu::AddressMode::Repeat,
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                mipmap_filter: FilterMode::Linear,
+                ..Default::default()
+            }),
+            equirect_to_cube: pipeline("equirect_to_cube"),
+            downsample: pipeline("downsample"),
+            irradiance: pipeline("irradiance"),
+            prefilter: pipeline("prefilter"),
+            brdf_lut: pipeline("brdf_lut"),
+        }
+    }
+
+    fn create_pipeline(
+        device: &Device,
+        module: &ShaderModule,
+        entry_point: &str,
+    ) -> ComputePipeline {
+        device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some(&format!("IBL {entry_point} Pipeline")),
+            layout: None,
+            module,
+            entry_point: Some(entry_point),
+            compilation_options: Default::default(),
+            cache: None,
+        })
+    }
+
+    fn params(device: &Device, params: BakeParams) -> Buffer {
+        device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("IBL Params Buffer"),
+            contents: bytemuck::bytes_of(&params),
+            usage: BufferUsages::UNIFORM,
+        })
+    }
+
+    /// Runs `pipeline` once per texel of `size`, binding exactly the resources its entry
+    /// point uses.
+    fn dispatch(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        pipeline: &ComputePipeline,
+        entries: &[BindGroupEntry],
+        size: [u32; 3],
+    ) {
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("IBL Bind Group"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries,
+        });
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("IBL Pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch_workgroups(
+            size[0].div_ceil(WORKGROUP_SIZE),
+            size[1].div_ceil(WORKGROUP_SIZE),
+            size[2],
+        );
+    }
+}
+
+// maps written by the compute passes
+const BAKED_USAGE: TextureUsages =
+    TextureUsages::TEXTURE_BINDING.union(TextureUsages::STORAGE_BINDING);
+
+fn create_cube(
+    device: &Device,
+    label: &str,
+    size: u32,
+    mip_level_count: u32,
+    usage: TextureUsages,
+) -> Texture {
+    device.create_texture(&TextureDescriptor {
+        label: Some(label),
+        size: Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 6,
+        },
+        mip_level_count,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: ENVIRONMENT_FORMAT,
+        usage,
+        view_formats: &[],
+    })
+}
+
+fn create_brdf_lut(device: &Device, size: u32, usage: TextureUsages) -> Texture {
+    device.create_texture(&TextureDescriptor {
+        label: Some("BRDF LUT"),
+        size: Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: ENVIRONMENT_FORMAT,
+        usage,
+        view_formats: &[],
+    })
+}
+
+fn cube_view(texture: &Texture) -> TextureView {
+    texture.create_view(&TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::Cube),
+        ..Default::default()
+    })
+}
+
+// the six faces of one mip, as written by the compute passes
+fn mip_view(texture: &Texture, mip: u32) -> TextureView {
+    texture.create_view(&TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::D2Array),
+        base_mip_level: mip,
+        mip_level_count: Some(1),
+        ..Default::default()
+    })
+}
@@ -0,0 +1,84 @@
+use thiserror::Error;
+use wasm_bindgen::JsValue;
+#[cfg(target_arch = "wasm32")]
+use web_sys::js_sys::Reflect;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Everything that can go wrong, independent of JS. Handed to JS it becomes an `Error`
+/// whose `kind` field names the variant, e.g. `"shaderCompile"`, see `Error::kind`.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The canvas can't be used as a surface, or not configured as asked.
+    #[error("{0}")]
+    Surface(String),
+    /// No adapter fits the surface.
+    #[error("{0}")]
+    Adapter(String),
+    /// The device can't be created with the requested features and limits, or lacks a
+    /// capability something needs.
+    #[error("{0}")]
+    Device(String),
+    /// An image, HDR environment or font atlas couldn't be decoded or doesn't fit.
+    #[error("{0}")]
+    TextureDecode(String),
+    /// A custom shader failed to parse or validate.
+    #[error("{0}")]
+    ShaderCompile(String),
+    /// A frame couldn't be drawn.
+    #[error("{0}")]
+    Render(String),
+    /// A mesh, font, path or scene file couldn't be read or written.
+    #[error("{0}")]
+    Asset(String),
+    #[error("Invalid {kind} handle: {handle}")]
+    InvalidHandle { kind: &'static str, handle: usize },
+    /// An argument or description from the caller doesn't make sense.
+    #[error("{0}")]
+    InvalidInput(String),
+    /// The `App` was destroyed, nothing can be done with it anymore.
+    #[error("The App has been destroyed")]
+    Destroyed,
+    /// A failure of the bindings themselves, like converting a result for JS.
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl Error {
+    pub fn invalid_handle(kind: &'static str, handle: usize) -> Self {
+        Error::InvalidHandle { kind, handle }
+    }
+
+    /// Name of the variant as seen from JS.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Surface(_) => "surface",
+            Error::Adapter(_) => "adapter",
+            Error::Device(_) => "device",
+            Error::TextureDecode(_) => "textureDecode",
+            Error::ShaderCompile(_) => "shaderCompile",
+            Error::Render(_) => "render",
+            Error::Asset(_) => "asset",
+            Error::InvalidHandle { .. } => "invalidHandle",
+            Error::InvalidInput(_) => "invalidInput",
+            Error::Destroyed => "destroyed",
+            Error::Internal(_) => "internal",
+        }
+    }
+}
+
+impl From<Error> for JsValue {
+    #[cfg(target_arch = "wasm32")]
+    fn from(error: Error) -> Self {
+        let js_error = web_sys::js_sys::Error::new(&error.to_string());
+        // only fails for frozen objects
+        let _ = Reflect::set(&js_error, &"kind".into(), &error.kind().into());
+        js_error.into()
+    }
+
+    // the exports are compiled for native targets too, but only ever called from JS
+    #[cfg(not(target_arch = "wasm32"))]
+    fn from(error: Error) -> Self {
+        JsValue::from_str(&error.to_string())
+    }
+}
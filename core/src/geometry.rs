@@ -0,0 +1,520 @@
+use std::{collections::HashMap, f32::consts::PI};
+
+use glam::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::{buffer_manager::Vertex, mesh::MeshData};
+
+const TAU: f32 = 2.0 * PI;
+// Upper bounds for the tessellation counts coming from JS, keeping meshes well below the
+// u32 index range. Icosphere triangles grow as 20 * 4^subdivisions.
+const MAX_SEGMENTS: u32 = 512;
+const MAX_ICOSPHERE_SUBDIVISIONS: u32 = 6;
+
+/// Shape description accepted from JS, e.g. `{ type: "sphere", radius: 0.5 }`.
+/// Missing fields fall back to a unit sized shape.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Primitive {
+    Plane {
+        width: Option<f32>,
+        depth: Option<f32>,
+        subdivisions: Option<u32>,
+    },
+    Cube {
+        size: Option<f32>,
+    },
+    Sphere {
+        radius: Option<f32>,
+        sectors: Option<u32>,
+        stacks: Option<u32>,
+    },
+    Icosphere {
+        radius: Option<f32>,
+        subdivisions: Option<u32>,
+    },
+    Cylinder {
+        radius: Option<f32>,
+        height: Option<f32>,
+        segments: Option<u32>,
+    },
+    Cone {
+        radius: Option<f32>,
+        height: Option<f32>,
+        segments: Option<u32>,
+    },
+    Torus {
+        radius: Option<f32>,
+        tube_radius: Option<f32>,
+        radial_segments: Option<u32>,
+        tubular_segments: Option<u32>,
+    },
+    Capsule {
+        radius: Option<f32>,
+        height: Option<f32>,
+        segments: Option<u32>,
+        rings: Option<u32>,
+    },
+}
+
+impl Primitive {
+    pub fn mesh(&self) -> MeshData {
+        match *self {
+            Primitive::Plane {
+                width,
+                depth,
+                subdivisions,
+            } => {
+                let subdivisions = subdivisions.unwrap_or(1);
+                plane(
+                    width.unwrap_or(1.0),
+                    depth.unwrap_or(1.0),
+                    subdivisions,
+                    subdivisions,
+                )
+            }
+            Primitive::Cube { size } => cube(size.unwrap_or(1.0)),
+            Primitive::Sphere {
+                radius,
+                sectors,
+                stacks,
+            } => uv_sphere(
+                radius.unwrap_or(0.5),
+                sectors.unwrap_or(32),
+                stacks.unwrap_or(16),
+            ),
+            Primitive::Icosphere {
+                radius,
+                subdivisions,
+            } => icosphere(radius.unwrap_or(0.5), subdivisions.unwrap_or(2)),
+            Primitive::Cylinder {
+                radius,
+                height,
+                segments,
+            } => cylinder(
+                radius.unwrap_or(0.5),
+                height.unwrap_or(1.0),
+                segments.unwrap_or(32),
+            ),
+            Primitive::Cone {
+                radius,
+                height,
+                segments,
+            } => cone(
+                radius.unwrap_or(0.5),
+                height.unwrap_or(1.0),
+                segments.unwrap_or(32),
+            ),
+            Primitive::Torus {
+                radius,
+                tube_radius,
+                radial_segments,
+                tubular_segments,
+            } => torus(
+                radius.unwrap_or(0.5),
+                tube_radius.unwrap_or(0.2),
+                radial_segments.unwrap_or(32),
+                tubular_segments.unwrap_or(16),
+            ),
+            Primitive::Capsule {
+                radius,
+                height,
+                segments,
+                rings,
+            } => capsule(
+                radius.unwrap_or(0.25),
+                height.unwrap_or(0.5),
+                segments.unwrap_or(32),
+                rings.unwrap_or(8),
+            ),
+        }
+    }
+}
+
+/// Plane in the XZ plane facing +Y, centered on the origin.
+pub fn plane(width: f32, depth: f32, subdivisions_x: u32, subdivisions_z: u32) -> MeshData {
+    let (columns, rows) = (
+        subdivisions_x.clamp(1, MAX_SEGMENTS),
+        subdivisions_z.clamp(1, MAX_SEGMENTS),
+    );
+    let mut mesh = grid(columns, rows, |column, row| {
+        let u = column as f32 / columns as f32;
+        let v = row as f32 / rows as f32;
+        vertex(
+            Vec3::new((u - 0.5) * width, 0.0, (v - 0.5) * depth),
+            Vec3::Y,
+            Vec2::new(u, v),
+        )
+    });
+    mesh.name = Some("Plane".to_string());
+    mesh
+}
+
+/// Axis aligned cube with flat shaded faces, each face mapped to the full UV range.
+pub fn cube(size: f32) -> MeshData {
+    let half = size / 2.0;
+    // (normal, u axis, v axis) with u x v pointing into the face so `grid` winds it outwards
+    let faces = [
+        (Vec3::X, Vec3::NEG_Z, Vec3::NEG_Y),
+        (Vec3::NEG_X, Vec3::Z, Vec3::NEG_Y),
+        (Vec3::Y, Vec3::X, Vec3::Z),
+        (Vec3::NEG_Y, Vec3::X, Vec3::NEG_Z),
+        (Vec3::Z, Vec3::X, Vec3::NEG_Y),
+        (Vec3::NEG_Z, Vec3::NEG_X, Vec3::NEG_Y),
+    ];
+    let mut mesh = MeshData::default();
+    for (normal, u_axis, v_axis) in faces {
+        mesh.append(grid(1, 1, |column, row| {
+            let uv = Vec2::new(column as f32, row as f32);
+            let pos = (normal + u_axis * (uv.x * 2.0 - 1.0) + v_axis * (uv.y * 2.0 - 1.0)) * half;
+            vertex(pos, normal, uv)
+        }));
+    }
+    mesh.name = Some("Cube".to_string());
+    mesh
+}
+
+/// Latitude/longitude sphere. The UV seam runs along +Z.
+pub fn uv_sphere(radius: f32, sectors: u32, stacks: u32) -> MeshData {
+    let (sectors, stacks) = (
+        sectors.clamp(3, MAX_SEGMENTS),
+        stacks.clamp(2, MAX_SEGMENTS),
+    );
+    let mut mesh = grid(sectors, stacks, |column, row| {
+        let u = column as f32 / sectors as f32;
+        let v = row as f32 / stacks as f32;
+        let normal = spherical(u * TAU, v * PI);
+        vertex(normal * radius, normal, Vec2::new(u, v))
+    });
+    mesh.name = Some("Sphere".to_string());
+    mesh
+}
+
+/// Subdivided icosahedron, giving evenly sized triangles without poles.
+pub fn icosphere(radius: f32, subdivisions: u32) -> MeshData {
+    let t = (1.0 + 5.0_f32.sqrt()) / 2.0;
+    let mut positions: Vec<Vec3> = [
+        [-1.0, t, 0.0],
+        [1.0, t, 0.0],
+        [-1.0, -t, 0.0],
+        [1.0, -t, 0.0],
+        [0.0, -1.0, t],
+        [0.0, 1.0, t],
+        [0.0, -1.0, -t],
+        [0.0, 1.0, -t],
+        [t, 0.0, -1.0],
+        [t, 0.0, 1.0],
+        [-t, 0.0, -1.0],
+        [-t, 0.0, 1.0],
+    ]
+    .into_iter()
+    .map(|pos| Vec3::from(pos).normalize())
+    .collect();
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions.min(MAX_ICOSPHERE_SUBDIVISIONS) {
+        // edges are shared by two triangles, so cache their midpoints
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut midpoint = |a: u32, b: u32| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let pos = (positions[a as usize] + positions[b as usize]).normalize();
+                positions.push(pos);
+                positions.len() as u32 - 1
+            })
+        };
+        triangles = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    let vertices = positions
+        .iter()
+        .map(|&normal| {
+            let uv = Vec2::new(
+                normal.x.atan2(normal.z) / TAU + 0.5,
+                normal.y.clamp(-1.0, 1.0).acos() / PI,
+            );
+            vertex(normal * radius, normal, uv)
+        })
+        .collect();
+    let mut mesh = MeshData::new(vertices, triangles.into_flattened());
+    mesh.compute_tangents();
+    mesh.name = Some("Icosphere".to_string());
+    mesh
+}
+
+/// Capped cylinder along the Y axis, centered on the origin.
+pub fn cylinder(radius: f32, height: f32, segments: u32) -> MeshData {
+    let segments = segments.clamp(3, MAX_SEGMENTS);
+    let half = height / 2.0;
+    let mut mesh = grid(segments, 1, |column, row| {
+        let u = column as f32 / segments as f32;
+        let normal = spherical(u * TAU, PI / 2.0);
+        let y = if row == 0 { half } else { -half };
+        vertex(
+            normal * radius + Vec3::Y * y,
+            normal,
+            Vec2::new(u, row as f32),
+        )
+    });
+    mesh.append(disk(radius, half, segments, true));
+    mesh.append(disk(radius, -half, segments, false));
+    mesh.name = Some("Cylinder".to_string());
+    mesh
+}
+
+/// Cone along the Y axis with its apex at `height / 2` and a capped base.
+pub fn cone(radius: f32, height: f32, segments: u32) -> MeshData {
+    let segments = segments.clamp(3, MAX_SEGMENTS);
+    let half = height / 2.0;
+    let mut mesh = grid(segments, 1, |column, row| {
+        let u = column as f32 / segments as f32;
+        let around = spherical(u * TAU, PI / 2.0);
+        // the apex gets one vertex per segment so each keeps its own slanted normal
+        let normal = (around * height + Vec3::Y * radius).normalize();
+        let pos = if row == 0 {
+            Vec3::Y * half
+        } else {
+            around * radius - Vec3::Y * half
+        };
+        vertex(pos, normal, Vec2::new(u, row as f32))
+    });
+    mesh.append(disk(radius, -half, segments, false));
+    mesh.name = Some("Cone".to_string());
+    mesh
+}
+
+/// Torus lying in the XZ plane. `radius` goes to the center of the tube.
+pub fn torus(
+    radius: f32,
+    tube_radius: f32,
+    radial_segments: u32,
+    tubular_segments: u32,
+) -> MeshData {
+    let (radial_segments, tubular_segments) = (
+        radial_segments.clamp(3, MAX_SEGMENTS),
+        tubular_segments.clamp(3, MAX_SEGMENTS),
+    );
+    let mut mesh = grid(radial_segments, tubular_segments, |column, row| {
+        let u = column as f32 / radial_segments as f32;
+        let v = row as f32 / tubular_segments as f32;
+        let around = spherical(u * TAU, PI / 2.0);
+        // walk the tube from the outer equator over the bottom, so the faces point outwards
+        let tube_angle = -v * TAU;
+        let normal = around * tube_angle.cos() + Vec3::Y * tube_angle.sin();
+        vertex(
+            around * radius + normal * tube_radius,
+            normal,
+            Vec2::new(u, v),
+        )
+    });
+    mesh.name = Some("Torus".to_string());
+    mesh
+}
+
+/// Cylinder with hemispherical ends along the Y axis. `height` excludes the caps.
+pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> MeshData {
+    let (segments, rings) = (
+        segments.clamp(3, MAX_SEGMENTS),
+        rings.clamp(1, MAX_SEGMENTS),
+    );
+    let half = height / 2.0;
+    let length = PI * radius + height;
+    // rows 0..=rings are the top hemisphere, the remaining ones the bottom hemisphere.
+    // The quads between the two equators form the cylinder.
+    let mut mesh = grid(segments, rings * 2 + 1, |column, row| {
+        let u = column as f32 / segments as f32;
+        let (polar, offset, arc) = if row <= rings {
+            let polar = row as f32 / rings as f32 * PI / 2.0;
+            (polar, half, polar * radius)
+        } else {
+            let polar = PI / 2.0 + (row - rings - 1) as f32 / rings as f32 * PI / 2.0;
+            (polar, -half, polar * radius + height)
+        };
+        let normal = spherical(u * TAU, polar);
+        vertex(
+            normal * radius + Vec3::Y * offset,
+            normal,
+            Vec2::new(u, arc / length),
+        )
+    });
+    mesh.name = Some("Capsule".to_string());
+    mesh
+}
+
+/// Disk cap at height `y`, facing +Y when `up` is set and -Y otherwise.
+fn disk(radius: f32, y: f32, segments: u32, up: bool) -> MeshData {
+    let normal = if up { Vec3::Y } else { Vec3::NEG_Y };
+    grid(segments, 1, |column, row| {
+        let angle = column as f32 / segments as f32 * TAU;
+        // rows run center to rim on top and rim to center below, flipping the winding
+        let distance = if (row == 0) == up { 0.0 } else { radius };
+        let pos = spherical(angle, PI / 2.0) * distance + Vec3::Y * y;
+        let uv = Vec2::new(0.5 + pos.x / (2.0 * radius), 0.5 + pos.z / (2.0 * radius));
+        vertex(pos, normal, uv)
+    })
+}
+
+/// Builds a `(columns + 1) x (rows + 1)` vertex grid. Triangles are front facing
+/// (counter clockwise) on the side that `d(pos)/d(row) x d(pos)/d(column)` points to.
+fn grid(columns: u32, rows: u32, vertex_at: impl Fn(u32, u32) -> Vertex) -> MeshData {
+    let stride = columns + 1;
+    let vertices = (0..=rows)
+        .flat_map(|row| (0..=columns).map(move |column| (column, row)))
+        .map(|(column, row)| vertex_at(column, row))
+        .collect();
+    let indices = (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| (column, row)))
+        .flat_map(|(column, row)| {
+            let a = row * stride + column;
+            let (b, c) = (a + 1, a + stride);
+            let d = c + 1;
+            [a, c, b, b, c, d]
+        })
+        .collect();
+    let mut mesh = MeshData::new(vertices, indices);
+    mesh.compute_tangents();
+    mesh
+}
+
+/// Unit vector for an azimuth around +Y (0 is +Z) and a polar angle from +Y.
+fn spherical(azimuth: f32, polar: f32) -> Vec3 {
+    Vec3::new(
+        polar.sin() * azimuth.sin(),
+        polar.cos(),
+        polar.sin() * azimuth.cos(),
+    )
+}
+
+fn vertex(pos: Vec3, normal: Vec3, uv: Vec2) -> Vertex {
+    Vertex {
+        pos: pos.to_array(),
+        color: [1.0, 1.0, 1.0],
+        tex_pos: uv.to_array(),
+        normal: normal.to_array(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(mesh: &MeshData, vertex_count: usize, index_count: usize) {
+        let name = mesh.name.as_deref().unwrap_or_default();
+        assert_eq!(mesh.vertices.len(), vertex_count, "{name} vertex count");
+        assert_eq!(mesh.indices.len(), index_count, "{name} index count");
+        assert!(
+            mesh.indices
+                .iter()
+                .all(|&index| (index as usize) < mesh.vertices.len()),
+            "{name} index out of range"
+        );
+        for vertex in &mesh.vertices {
+            let length = Vec3::from(vertex.normal).length();
+            assert!((length - 1.0).abs() < 1e-4, "{name} normal length {length}");
+            assert!(
+                vertex.tex_pos.iter().all(|uv| (0.0..=1.0).contains(uv)),
+                "{name} uv {:?}",
+                vertex.tex_pos
+            );
+        }
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| &mesh.vertices[triangle[i] as usize]);
+            let [pa, pb, pc] = [a, b, c].map(|v| Vec3::from(v.pos));
+            let face = (pb - pa).cross(pc - pa);
+            // poles and apexes collapse some triangles to lines
+            if face.length() < 1e-6 {
+                continue;
+            }
+            for v in [a, b, c] {
+                assert!(
+                    face.dot(Vec3::from(v.normal)) > 0.0,
+                    "{name} triangle {triangle:?} winds inwards"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn plane() {
+        check(&super::plane(2.0, 1.0, 4, 3), 5 * 4, 4 * 3 * 6);
+    }
+
+    #[test]
+    fn cube() {
+        check(&super::cube(1.0), 6 * 4, 6 * 6);
+    }
+
+    #[test]
+    fn uv_sphere() {
+        check(&super::uv_sphere(0.5, 8, 4), 9 * 5, 8 * 4 * 6);
+    }
+
+    #[test]
+    fn icosphere() {
+        check(&super::icosphere(0.5, 0), 12, 20 * 3);
+        // 12 + 30 edge midpoints
+        check(&super::icosphere(0.5, 1), 42, 80 * 3);
+    }
+
+    #[test]
+    fn cylinder() {
+        check(&super::cylinder(0.5, 1.0, 8), 3 * 9 * 2, 3 * 8 * 6);
+    }
+
+    #[test]
+    fn cone() {
+        check(&super::cone(0.5, 1.0, 8), 2 * 9 * 2, 2 * 8 * 6);
+    }
+
+    #[test]
+    fn torus() {
+        check(&super::torus(0.5, 0.2, 8, 6), 9 * 7, 8 * 6 * 6);
+    }
+
+    #[test]
+    fn capsule() {
+        check(&super::capsule(0.25, 0.5, 8, 2), 9 * 6, 8 * 5 * 6);
+    }
+
+    #[test]
+    fn counts_are_clamped() {
+        let sphere = super::uv_sphere(0.5, u32::MAX, u32::MAX);
+        assert_eq!(sphere.vertices.len(), (MAX_SEGMENTS as usize + 1).pow(2));
+        let icosphere = super::icosphere(0.5, u32::MAX);
+        assert_eq!(
+            icosphere.indices.len(),
+            20 * 4usize.pow(MAX_ICOSPHERE_SUBDIVISIONS) * 3
+        );
+    }
+}
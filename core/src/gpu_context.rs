@@ -1,67 +1,730 @@
-use log::info;
-use wasm_bindgen::JsError;
-use web_sys::HtmlCanvasElement;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsCast;
+use web_sys::{
+    HtmlCanvasElement,
+    js_sys::{Function, Reflect},
+};
 use wgpu::{
-    Adapter, Backends, Device, DeviceDescriptor, Features, Instance, Limits, Queue,
-    RequestAdapterOptions, Surface, SurfaceTarget,
+    Adapter, Backends, CompositeAlphaMode, Device, DeviceDescriptor, DeviceLostReason,
+    DownlevelFlags, Features, Instance, Limits, Queue, RequestAdapterOptions, Surface,
+    SurfaceCapabilities, SurfaceConfiguration, SurfaceTarget, TextureFormat, TextureUsages,
 };
 
+use crate::{
+    error::{Error, Result},
+    resource_cache::ResourceCache,
+};
+
+/// Which kind of surface format to pick when the surface offers several.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FormatPreference {
+    /// The surface's preferred format.
+    #[default]
+    Auto,
+    /// A format the hardware encodes to sRGB on write.
+    Srgb,
+    /// A format storing shader output as is.
+    Linear,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PresentMode {
+    /// Vsync where supported, which is everywhere.
+    #[default]
+    AutoVsync,
+    /// Without vsync where supported, vsync otherwise.
+    AutoNoVsync,
+    Fifo,
+    FifoRelaxed,
+    Immediate,
+    Mailbox,
+}
+
+impl From<wgpu::PresentMode> for PresentMode {
+    fn from(mode: wgpu::PresentMode) -> Self {
+        match mode {
+            wgpu::PresentMode::AutoVsync => PresentMode::AutoVsync,
+            wgpu::PresentMode::AutoNoVsync => PresentMode::AutoNoVsync,
+            wgpu::PresentMode::Fifo => PresentMode::Fifo,
+            wgpu::PresentMode::FifoRelaxed => PresentMode::FifoRelaxed,
+            wgpu::PresentMode::Immediate => PresentMode::Immediate,
+            wgpu::PresentMode::Mailbox => PresentMode::Mailbox,
+        }
+    }
+}
+
+impl From<PresentMode> for wgpu::PresentMode {
+    fn from(mode: PresentMode) -> Self {
+        match mode {
+            PresentMode::AutoVsync => wgpu::PresentMode::AutoVsync,
+            PresentMode::AutoNoVsync => wgpu::PresentMode::AutoNoVsync,
+            PresentMode::Fifo => wgpu::PresentMode::Fifo,
+            PresentMode::FifoRelaxed => wgpu::PresentMode::FifoRelaxed,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+        }
+    }
+}
+
+/// How the canvas is composited with the page behind it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AlphaMode {
+    /// The first mode the surface supports, opaque on the web.
+    #[default]
+    Auto,
+    Opaque,
+    /// Colors are multiplied by alpha, for canvases blended over HTML.
+    Premultiplied,
+    PostMultiplied,
+    Inherit,
+}
+
+impl From<CompositeAlphaMode> for AlphaMode {
+    fn from(mode: CompositeAlphaMode) -> Self {
+        match mode {
+            CompositeAlphaMode::Auto => AlphaMode::Auto,
+            CompositeAlphaMode::Opaque => AlphaMode::Opaque,
+            CompositeAlphaMode::PreMultiplied => AlphaMode::Premultiplied,
+            CompositeAlphaMode::PostMultiplied => AlphaMode::PostMultiplied,
+            CompositeAlphaMode::Inherit => AlphaMode::Inherit,
+        }
+    }
+}
+
+impl From<AlphaMode> for CompositeAlphaMode {
+    fn from(mode: AlphaMode) -> Self {
+        match mode {
+            AlphaMode::Auto => CompositeAlphaMode::Auto,
+            AlphaMode::Opaque => CompositeAlphaMode::Opaque,
+            AlphaMode::Premultiplied => CompositeAlphaMode::PreMultiplied,
+            AlphaMode::PostMultiplied => CompositeAlphaMode::PostMultiplied,
+            AlphaMode::Inherit => CompositeAlphaMode::Inherit,
+        }
+    }
+}
+
+/// How the canvas surface is configured, e.g. `{ format: "srgb", presentMode: "immediate",
+/// alphaMode: "premultiplied", desiredFrameLatency: 1 }`. Missing fields keep the defaults.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SurfaceOptions {
+    pub format: FormatPreference,
+    pub present_mode: PresentMode,
+    pub alpha_mode: AlphaMode,
+    /// Frames queued ahead of the display, more smooth out hitches at the cost of latency.
+    pub desired_frame_latency: u32,
+}
+
+impl Default for SurfaceOptions {
+    fn default() -> Self {
+        Self {
+            format: FormatPreference::Auto,
+            present_mode: PresentMode::AutoVsync,
+            alpha_mode: AlphaMode::Auto,
+            desired_frame_latency: 2,
+        }
+    }
+}
+
+impl SurfaceOptions {
+    /// The surface configuration for these options. A format preference the surface
+    /// can't meet falls back to its preferred format, unsupported modes are an error.
+    pub fn configuration(
+        &self,
+        capabilities: &SurfaceCapabilities,
+        width: u32,
+        height: u32,
+    ) -> Result<SurfaceConfiguration> {
+        let preferred = *capabilities.formats.first().ok_or_else(|| {
+            Error::Surface("Surface is incompatible with the adapter".to_string())
+        })?;
+        let format = match self.format {
+            FormatPreference::Auto => None,
+            FormatPreference::Srgb => capabilities.formats.iter().find(|f| f.is_srgb()),
+            FormatPreference::Linear => capabilities.formats.iter().find(|f| !f.is_srgb()),
+        }
+        .copied()
+        .unwrap_or_else(|| {
+            if self.format != FormatPreference::Auto {
+                info!("No {:?} surface format, using {preferred:?}", self.format);
+            }
+            preferred
+        });
+        let present_mode = wgpu::PresentMode::from(self.present_mode);
+        if !matches!(
+            self.present_mode,
+            PresentMode::AutoVsync | PresentMode::AutoNoVsync
+        ) && !capabilities.present_modes.contains(&present_mode)
+        {
+            return Err(Error::Surface(format!(
+                "Unsupported present mode {:?}, the surface supports {:?}",
+                self.present_mode, capabilities.present_modes
+            )));
+        }
+        let alpha_mode = CompositeAlphaMode::from(self.alpha_mode);
+        let alpha_mode = if self.alpha_mode == AlphaMode::Auto {
+            capabilities.alpha_modes[0]
+        } else if capabilities.alpha_modes.contains(&alpha_mode) {
+            alpha_mode
+        } else {
+            return Err(Error::Surface(format!(
+                "Unsupported alpha mode {:?}, the surface supports {:?}",
+                self.alpha_mode, capabilities.alpha_modes
+            )));
+        };
+        if self.desired_frame_latency == 0 {
+            return Err(Error::InvalidInput(
+                "Desired frame latency has to be at least 1".to_string(),
+            ));
+        }
+        Ok(SurfaceConfiguration {
+            usage: TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: width.max(1),
+            height: height.max(1),
+            present_mode,
+            desired_maximum_frame_latency: self.desired_frame_latency,
+            alpha_mode,
+            view_formats: Vec::new(),
+        })
+    }
+}
+
+/// Optional device features the crate knows how to request, by their WebGPU names.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GpuFeature {
+    TimestampQuery,
+    Float32Filterable,
+    TextureCompressionBc,
+    TextureCompressionEtc2,
+    TextureCompressionAstc,
+    /// Not part of WebGPU, only available on native backends.
+    PushConstants,
+    ShaderF16,
+    DepthClipControl,
+    Depth32FloatStencil8,
+    IndirectFirstInstance,
+    Rg11b10UfloatRenderable,
+    Bgra8UnormStorage,
+    DualSourceBlending,
+}
+
+impl GpuFeature {
+    pub const ALL: [GpuFeature; 13] = [
+        GpuFeature::TimestampQuery,
+        GpuFeature::Float32Filterable,
+        GpuFeature::TextureCompressionBc,
+        GpuFeature::TextureCompressionEtc2,
+        GpuFeature::TextureCompressionAstc,
+        GpuFeature::PushConstants,
+        GpuFeature::ShaderF16,
+        GpuFeature::DepthClipControl,
+        GpuFeature::Depth32FloatStencil8,
+        GpuFeature::IndirectFirstInstance,
+        GpuFeature::Rg11b10UfloatRenderable,
+        GpuFeature::Bgra8UnormStorage,
+        GpuFeature::DualSourceBlending,
+    ];
+
+    pub fn features(self) -> Features {
+        match self {
+            GpuFeature::TimestampQuery => Features::TIMESTAMP_QUERY,
+            GpuFeature::Float32Filterable => Features::FLOAT32_FILTERABLE,
+            GpuFeature::TextureCompressionBc => Features::TEXTURE_COMPRESSION_BC,
+            GpuFeature::TextureCompressionEtc2 => Features::TEXTURE_COMPRESSION_ETC2,
+            GpuFeature::TextureCompressionAstc => Features::TEXTURE_COMPRESSION_ASTC,
+            GpuFeature::PushConstants => Features::PUSH_CONSTANTS,
+            GpuFeature::ShaderF16 => Features::SHADER_F16,
+            GpuFeature::DepthClipControl => Features::DEPTH_CLIP_CONTROL,
+            GpuFeature::Depth32FloatStencil8 => Features::DEPTH32FLOAT_STENCIL8,
+            GpuFeature::IndirectFirstInstance => Features::INDIRECT_FIRST_INSTANCE,
+            GpuFeature::Rg11b10UfloatRenderable => Features::RG11B10UFLOAT_RENDERABLE,
+            GpuFeature::Bgra8UnormStorage => Features::BGRA8UNORM_STORAGE,
+            GpuFeature::DualSourceBlending => Features::DUAL_SOURCE_BLENDING,
+        }
+    }
+}
+
+/// What the device is created with, e.g. `{ requiredFeatures: ["float32Filterable"],
+/// optionalFeatures: ["timestampQuery"], limits: { maxTextureDimension2D: 8192 } }`.
+/// Missing required features or limits fail device creation, missing optional features
+/// are left out and the subsystems using them turn themselves off.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DeviceOptions {
+    pub required_features: Vec<GpuFeature>,
+    pub optional_features: Vec<GpuFeature>,
+    /// WebGPU limits by name, overriding the downlevel defaults the crate works with.
+    pub limits: HashMap<String, u64>,
+}
+
+impl Default for DeviceOptions {
+    fn default() -> Self {
+        Self {
+            required_features: Vec::new(),
+            optional_features: vec![GpuFeature::TimestampQuery, GpuFeature::Float32Filterable],
+            limits: HashMap::new(),
+        }
+    }
+}
+
+impl DeviceOptions {
+    /// The features to request from `adapter`: the required ones, which it has to
+    /// support, and the optional ones it supports.
+    pub fn features(&self, adapter: &Adapter) -> Result<Features> {
+        let supported = adapter.features();
+        let missing: Vec<GpuFeature> = self
+            .required_features
+            .iter()
+            .copied()
+            .filter(|feature| !supported.contains(feature.features()))
+            .collect();
+        if !missing.is_empty() {
+            return Err(Error::Device(format!(
+                "The adapter doesn't support the required features {missing:?}"
+            )));
+        }
+        let mut features = Features::empty();
+        for feature in &self.required_features {
+            features |= feature.features();
+        }
+        for feature in &self.optional_features {
+            if supported.contains(feature.features()) {
+                features |= feature.features();
+            } else {
+                info!("Optional feature {feature:?} is not supported by the adapter");
+            }
+        }
+        Ok(features)
+    }
+
+    /// The downlevel defaults, or WebGL2's on the GL backend, with the requested limits,
+    /// which `adapter` has to allow.
+    pub fn limits(&self, adapter: &Adapter) -> Result<Limits> {
+        let defaults = match GpuBackend::of(adapter) {
+            GpuBackend::WebGpu => Limits::downlevel_defaults(),
+            GpuBackend::WebGl2 => Limits::downlevel_webgl2_defaults(),
+        };
+        let mut limits = serde_json::to_value(defaults)
+            .map_err(|err| Error::Internal(format!("Failed to read limits: {err}")))?;
+        for (name, &value) in &self.limits {
+            let limit = limits
+                .get_mut(name)
+                .ok_or_else(|| Error::InvalidInput(format!("Unknown limit: {name}")))?;
+            *limit = value.into();
+        }
+        let limits: Limits = serde_json::from_value(limits)
+            .map_err(|err| Error::InvalidInput(format!("Invalid limits: {err}")))?;
+        let mut exceeded = Vec::new();
+        limits.check_limits_with_fail_fn(&adapter.limits(), false, |name, requested, allowed| {
+            exceeded.push(format!("{name} {requested} (allowed {allowed})"))
+        });
+        if !exceeded.is_empty() {
+            return Err(Error::Device(format!(
+                "The adapter doesn't allow the limits {}",
+                exceeded.join(", ")
+            )));
+        }
+        Ok(limits)
+    }
+}
+
+/// The browser API the device runs on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GpuBackend {
+    #[serde(rename = "webgpu")]
+    WebGpu,
+    /// Fallback without compute shaders, storage textures or timestamp queries.
+    #[serde(rename = "webgl2")]
+    WebGl2,
+}
+
+impl GpuBackend {
+    pub fn of(adapter: &Adapter) -> Self {
+        match adapter.get_info().backend {
+            wgpu::Backend::Gl => GpuBackend::WebGl2,
+            _ => GpuBackend::WebGpu,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GpuBackend::WebGpu => "webgpu",
+            GpuBackend::WebGl2 => "webgl2",
+        }
+    }
+}
+
+/// The features and limits the device was created with.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceCapabilities {
+    pub backend: GpuBackend,
+    /// Whether compute shaders can run, which image based lighting needs.
+    pub compute: bool,
+    pub features: Vec<GpuFeature>,
+    pub limits: Limits,
+}
+
+/// What the surface is configured with.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SurfaceInfo {
+    pub format: TextureFormat,
+    pub present_mode: PresentMode,
+    pub alpha_mode: AlphaMode,
+    pub desired_frame_latency: u32,
+}
+
+/// Everything about the hardware path rendering takes, for bug reports.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GpuInfo {
+    pub name: String,
+    /// PCI vendor and device id, 0 when the browser hides them.
+    pub vendor: u32,
+    pub device: u32,
+    pub device_type: String,
+    pub driver: String,
+    pub driver_info: String,
+    pub backend: GpuBackend,
+    pub compute: bool,
+    pub features: Vec<GpuFeature>,
+    pub limits: Limits,
+    pub surface: SurfaceInfo,
+    pub surface_formats: Vec<TextureFormat>,
+    pub present_modes: Vec<PresentMode>,
+    pub alpha_modes: Vec<AlphaMode>,
+}
+
+type DeviceRequest = Rc<RefCell<Option<Result<(Adapter, Device, Queue)>>>>;
+
+/// The device of all contexts sharing it, and how to replace it when it's lost.
+struct SharedDevice {
+    adapter: Adapter,
+    device: Device,
+    queue: Queue,
+    cache: Rc<RefCell<ResourceCache>>,
+    // counts the replacement devices installed
+    generation: u32,
+    // contexts not destroyed yet
+    contexts: usize,
+    options: DeviceOptions,
+    /// Set by the device lost callback, cleared once a replacement device is installed.
+    lost: Arc<AtomicBool>,
+    // the replacement device, filled in when the request completes
+    replacement: Option<DeviceRequest>,
+}
+
+/// A canvas surface and the device drawing to it, which other canvases can share, see
+/// `share`.
 pub struct GpuContext<'window> {
     pub instance: Instance,
-    pub surface: Surface<'window>,
+    canvas: HtmlCanvasElement,
+    // shared with the request for a replacement device
+    pub surface: Rc<Surface<'window>>,
     pub device: Device,
     pub queue: Queue,
     pub adapter: Adapter,
+    pub config: SurfaceConfiguration,
+    /// Pipelines and textures of the device, shared by every canvas drawing with it.
+    pub cache: Rc<RefCell<ResourceCache>>,
+    shared: Rc<RefCell<SharedDevice>>,
+    // generation of the shared device the handles above belong to
+    generation: u32,
 }
 
 impl GpuContext<'_> {
-    pub async fn new(canvas: HtmlCanvasElement, width: u32, height: u32) -> Result<Self, JsError> {
-        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
-            backends: Backends::BROWSER_WEBGPU,
+    pub async fn new(
+        canvas: HtmlCanvasElement,
+        width: u32,
+        height: u32,
+        surface_options: &SurfaceOptions,
+        device_options: &DeviceOptions,
+    ) -> Result<Self> {
+        // WebGL2 when the browser has no WebGPU, it has to be decided before the canvas
+        // gets a context
+        let instance = wgpu::util::new_instance_with_webgpu_detection(&wgpu::InstanceDescriptor {
+            backends: Backends::BROWSER_WEBGPU | Backends::GL,
             ..Default::default()
-        });
-        let surface_target = SurfaceTarget::Canvas(canvas);
+        })
+        .await;
+        let surface_target = SurfaceTarget::Canvas(canvas.clone());
         let surface = instance.create_surface(surface_target).map_err(|err| {
-            JsError::new(&format!(
-                "Failed to use canvas as webgpu surface: {:?}",
-                err
-            ))
+            Error::Surface(format!("Failed to use canvas as webgpu surface: {:?}", err))
         })?;
+        let lost = Arc::new(AtomicBool::new(false));
+        let (adapter, device, queue) =
+            Self::request_device(&instance, &surface, device_options, lost.clone()).await?;
+        let config =
+            surface_options.configuration(&surface.get_capabilities(&adapter), width, height)?;
+        surface.configure(&device, &config);
+        info!(
+            "{} initialized successfully",
+            GpuBackend::of(&adapter).name()
+        );
+        let cache: Rc<RefCell<ResourceCache>> = Rc::default();
+        let shared = SharedDevice {
+            adapter: adapter.clone(),
+            device: device.clone(),
+            queue: queue.clone(),
+            cache: cache.clone(),
+            generation: 0,
+            contexts: 1,
+            options: device_options.clone(),
+            lost,
+            replacement: None,
+        };
+        Ok(Self {
+            instance,
+            canvas,
+            surface: Rc::new(surface),
+            adapter,
+            device,
+            queue,
+            config,
+            cache,
+            shared: Rc::new(RefCell::new(shared)),
+            generation: 0,
+        })
+    }
+
+    // an adapter for the surface and a device from it, which sets `lost` when it's lost
+    async fn request_device(
+        instance: &Instance,
+        surface: &Surface<'_>,
+        device_options: &DeviceOptions,
+        lost: Arc<AtomicBool>,
+    ) -> Result<(Adapter, Device, Queue)> {
         let adapter = instance
             .request_adapter(&RequestAdapterOptions {
                 power_preference: Default::default(),
                 force_fallback_adapter: false,
-                compatible_surface: Some(&surface),
+                compatible_surface: Some(surface),
             })
             .await
-            .map_err(|err| {
-                JsError::new(&format!(
-                    "Failed to use canvas as webgpu surface: {:?}",
-                    err
-                ))
-            })?;
+            .map_err(|err| Error::Adapter(format!("No adapter for the canvas surface: {err}")))?;
         let (device, queue) = adapter
             .request_device(&DeviceDescriptor {
                 label: Some("WGPU Device request"),
-                required_features: Features::default(),
-                required_limits: Limits::downlevel_defaults(),
+                required_features: device_options.features(&adapter)?,
+                required_limits: device_options.limits(&adapter)?,
                 memory_hints: wgpu::MemoryHints::MemoryUsage,
                 trace: wgpu::Trace::Off,
             })
             .await
-            .map_err(|err| JsError::new(&format!("Failed to create device: {:?}", err)))?;
-        let config = surface
-            .get_default_config(&adapter, width, height)
-            .ok_or_else(|| "Unable to get default config for surface")
-            .map_err(|err| JsError::new(err))?;
-        surface.configure(&device, &config);
-        info!("WebGpu initialized successfully");
+            .map_err(|err| Error::Device(format!("Failed to create device: {:?}", err)))?;
+        device.set_device_lost_callback(move |reason, message| {
+            // destroying the device on purpose is not a loss to recover from
+            if reason == DeviceLostReason::Unknown {
+                warn!("Device lost: {message}");
+                lost.store(true, Ordering::Relaxed);
+            }
+        });
+        Ok((adapter, device, queue))
+    }
+
+    /// Whether the device is lost and no replacement is installed yet, or this context
+    /// hasn't switched to the replacement another context installed.
+    pub fn is_lost(&self) -> bool {
+        let shared = self.shared.borrow();
+        shared.lost.load(Ordering::Relaxed) || shared.generation != self.generation
+    }
+
+    /// Whether the device was created with `feature`.
+    pub fn has_feature(&self, feature: GpuFeature) -> bool {
+        self.device.features().contains(feature.features())
+    }
+
+    pub fn backend(&self) -> GpuBackend {
+        GpuBackend::of(&self.adapter)
+    }
+
+    /// Whether compute shaders and storage textures are available, which they aren't
+    /// on WebGL2.
+    pub fn supports_compute(&self) -> bool {
+        self.adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(DownlevelFlags::COMPUTE_SHADERS)
+    }
+
+    pub fn capabilities(&self) -> DeviceCapabilities {
+        DeviceCapabilities {
+            backend: self.backend(),
+            compute: self.supports_compute(),
+            features: GpuFeature::ALL
+                .into_iter()
+                .filter(|&feature| self.has_feature(feature))
+                .collect(),
+            limits: self.device.limits(),
+        }
+    }
+
+    pub fn info(&self) -> GpuInfo {
+        let adapter = self.adapter.get_info();
+        let surface = self.surface.get_capabilities(&self.adapter);
+        let DeviceCapabilities {
+            backend,
+            compute,
+            features,
+            limits,
+        } = self.capabilities();
+        GpuInfo {
+            name: adapter.name,
+            vendor: adapter.vendor,
+            device: adapter.device,
+            device_type: format!("{:?}", adapter.device_type),
+            driver: adapter.driver,
+            driver_info: adapter.driver_info,
+            backend,
+            compute,
+            features,
+            limits,
+            surface: SurfaceInfo {
+                format: self.config.format,
+                present_mode: self.config.present_mode.into(),
+                alpha_mode: self.config.alpha_mode.into(),
+                desired_frame_latency: self.config.desired_maximum_frame_latency,
+            },
+            surface_formats: surface.formats,
+            present_modes: surface.present_modes.into_iter().map(Into::into).collect(),
+            alpha_modes: surface.alpha_modes.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.config.width = width.max(1);
+        self.config.height = height.max(1);
+        self.surface.configure(&self.device, &self.config);
+    }
+
+    /// Unconfigures the canvas and forgets the cached resources no other canvas uses. The
+    /// last context sharing the device drops the cache and destroys the device, which frees
+    /// everything still created on it.
+    pub fn destroy(&mut self) {
+        // wgpu can't unconfigure a surface, its canvas context can. A WebGL2 canvas
+        // has no WebGPU context
+        if let Ok(Some(context)) = self.canvas.get_context("webgpu")
+            && let Ok(unconfigure) = Reflect::get(&context, &"unconfigure".into())
+            && let Some(unconfigure) = unconfigure.dyn_ref::<Function>()
+        {
+            let _ = unconfigure.call0(&context);
+        }
+        let mut shared = self.shared.borrow_mut();
+        shared.contexts -= 1;
+        if shared.contexts == 0 {
+            shared.cache.take();
+            // not reported as a loss, see `request_device`
+            shared.device.destroy();
+        } else {
+            // what only this canvas used went with its managers
+            shared.cache.borrow_mut().prune();
+        }
+    }
+}
+
+impl GpuContext<'static> {
+    /// A context for another canvas, drawing with the same device, queue and caches.
+    /// Its surface is configured by `surface_options` on its own. Only WebGPU devices
+    /// can present to several canvases.
+    pub fn share(
+        &self,
+        canvas: HtmlCanvasElement,
+        width: u32,
+        height: u32,
+        surface_options: &SurfaceOptions,
+    ) -> Result<Self> {
+        if self.backend() == GpuBackend::WebGl2 {
+            return Err(Error::Device(
+                "Sharing a device between canvases needs WebGPU, a WebGL2 context belongs to \
+                 one canvas"
+                    .to_string(),
+            ));
+        }
+        let surface = self
+            .instance
+            .create_surface(SurfaceTarget::Canvas(canvas.clone()))
+            .map_err(|err| {
+                Error::Surface(format!("Failed to use canvas as webgpu surface: {:?}", err))
+            })?;
+        let config = surface_options.configuration(
+            &surface.get_capabilities(&self.adapter),
+            width,
+            height,
+        )?;
+        surface.configure(&self.device, &config);
+        self.shared.borrow_mut().contexts += 1;
         Ok(Self {
-            instance,
-            surface,
-            adapter,
-            device,
-            queue,
+            instance: self.instance.clone(),
+            canvas,
+            surface: Rc::new(surface),
+            device: self.device.clone(),
+            queue: self.queue.clone(),
+            adapter: self.adapter.clone(),
+            config,
+            cache: self.cache.clone(),
+            shared: self.shared.clone(),
+            generation: self.generation,
         })
     }
+
+    /// Recovers from a lost device: the first call after the loss by any context sharing
+    /// the device requests a replacement, and a later call installs it once it is there.
+    /// Returns whether this context switched to a new device, in which case every GPU
+    /// resource drawing to it has to be recreated.
+    pub fn recover(&mut self) -> Result<bool> {
+        let mut shared = self.shared.borrow_mut();
+        if shared.generation == self.generation {
+            if !shared.lost.load(Ordering::Relaxed) {
+                return Ok(false);
+            }
+            let Some(replacement) = shared.replacement.clone() else {
+                info!("Requesting a replacement for the lost device");
+                let request: DeviceRequest = Rc::default();
+                let (instance, surface) = (self.instance.clone(), self.surface.clone());
+                let (options, lost) = (shared.options.clone(), shared.lost.clone());
+                let result = request.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let device = Self::request_device(&instance, &surface, &options, lost).await;
+                    *result.borrow_mut() = Some(device);
+                });
+                shared.replacement = Some(request);
+                return Ok(false);
+            };
+            let Some(result) = replacement.borrow_mut().take() else {
+                return Ok(false);
+            };
+            // a failed request is retried on the next call
+            shared.replacement = None;
+            (shared.adapter, shared.device, shared.queue) = result?;
+            // what was cached belongs to the lost device
+            shared.cache = Rc::default();
+            shared.generation += 1;
+            shared.lost.store(false, Ordering::Relaxed);
+            info!("Replacement device installed");
+        }
+        self.adapter = shared.adapter.clone();
+        self.device = shared.device.clone();
+        self.queue = shared.queue.clone();
+        self.cache = shared.cache.clone();
+        self.generation = shared.generation;
+        self.surface.configure(&self.device, &self.config);
+        Ok(true)
+    }
 }
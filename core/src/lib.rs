@@ -1,17 +1,57 @@
 mod buffer_manager;
+mod camera;
+mod debug_draw;
+mod environment;
+mod error;
+mod geometry;
 mod gpu_context;
+mod light;
+mod logging;
+mod material;
+mod mesh;
+mod mesh_loader;
 mod pipeline_manager;
+mod profiler;
 mod renderer;
+mod resource_cache;
+mod scene;
+mod scene_file;
+mod sprite;
+mod text;
 mod texture_manager;
+mod vector_path;
 
-use buffer_manager::{BufferManager, MousePos};
-use gpu_context::GpuContext;
-use log::info;
+use buffer_manager::{BufferManager, INSTANCE_FLOATS, InstanceRaw, MousePos};
+use camera::{CameraController, CameraManager, MouseState, Projection};
+use debug_draw::{DebugDraw, DebugRenderer};
+use environment::Environment;
+pub use error::{Error, Result};
+use geometry::Primitive;
+use glam::{Quat, Vec3};
+use gpu_context::{DeviceOptions, GpuContext, GpuFeature, SurfaceOptions};
+use light::{Light, LightManager, ShadowSettings};
+use log::{Level, info};
+use logging::log_every;
+use material::{
+    BlendMode, MaterialData, MaterialFactors, MaterialImage, MaterialManager, MaterialTexture,
+};
+use mesh_loader::MeshFormat;
 use pipeline_manager::PipelineManager;
-use renderer::Renderer;
+use profiler::{Profiler, TimingSource};
+use renderer::{FrameResources, PassConfig, Renderer};
+use scene::{NodeHandle, Scene};
+use scene_file::{CameraDescription, SceneDescription, SceneFormat, UniformValues};
+use serde::de::DeserializeOwned;
+use sprite::{SPRITE_FLOATS, Sprite, SpriteBatch};
+use std::{collections::HashMap, ops::Range};
+use text::{TextRenderer, TextStyle};
 use texture_manager::TextureManager;
+use vector_path::VectorPath;
 use wasm_bindgen::prelude::*;
-use web_sys::{HtmlCanvasElement, js_sys::Array};
+use web_sys::{
+    HtmlCanvasElement,
+    js_sys::{Array, Reflect, Uint8Array},
+};
 use wgpu::wgc::device::queue;
 
 #[wasm_bindgen]
@@ -19,41 +59,93 @@ pub struct App {
     gpu: GpuContext<'static>,
     buffers: BufferManager,
     textures: TextureManager,
+    materials: MaterialManager,
+    lights: LightManager,
     pipeline: PipelineManager,
     renderer: Renderer,
+    sprites: SpriteBatch,
+    text: TextRenderer,
+    debug: DebugRenderer,
+    profiler: Profiler,
+    camera: CameraManager,
+    scene: Scene,
+    // the last loaded scene file and its named nodes, kept for `save_scene`
+    scene_description: SceneDescription,
+    scene_nodes: HashMap<String, NodeHandle>,
+    // handles of the meshes and materials the last scene file added, freed by the next one
+    scene_meshes: Range<usize>,
+    scene_materials: Range<usize>,
+    // set by `destroy`, after which every call fails
+    destroyed: bool,
 }
 
 #[wasm_bindgen]
 impl App {
+    /// Configures what gets logged to the browser console, before or after `setup`, e.g.
+    /// `App.configure_logging({ level: "warn", modules: { renderer: "debug" } })`, see
+    /// `LogConfig`. The config is shared by all `App`s, without one everything from info
+    /// up is logged.
     #[wasm_bindgen]
-    pub async fn setup(canvas: HtmlCanvasElement, textures_data: JsValue) -> Result<App, JsError> {
-        console_log::init()
-            .map_err(|err| JsError::new(&format!("Could not init logger: {:?}", err)))?;
+    pub fn configure_logging(config: JsValue) -> Result<()> {
+        logging::configure(options_or_default(config, "log config")?);
+        Ok(())
+    }
+
+    /// Initializes WebGPU on `canvas`, or WebGL2 when the browser lacks WebGPU, see
+    /// `backend`. `surface_options` configures the canvas surface,
+    /// e.g. `{ format: "srgb", presentMode: "autoNoVsync", alphaMode: "premultiplied" }`,
+    /// see `SurfaceOptions`. Without options the surface's preferred format and vsync
+    /// are used. `device_options` lists the features and limits to request, see
+    /// `DeviceOptions`.
+    #[wasm_bindgen]
+    pub async fn setup(
+        canvas: HtmlCanvasElement,
+        textures_data: JsValue,
+        surface_options: JsValue,
+        device_options: JsValue,
+    ) -> Result<App> {
+        logging::init();
         info!("Setting up webgpu!!!");
 
         let width = canvas.width();
         let height = canvas.height();
         info!("window::width={width}, window::height={height}");
 
-        let gpu = GpuContext::new(canvas, width, height).await?;
-        let buffer_manager = BufferManager::new(&gpu.device, width, height);
-        let texture_manager = TextureManager::new(&gpu.device, &gpu.queue, Array::from(&textures_data))?;
-        let swapchain_capabilities = gpu.surface.get_capabilities(&gpu.adapter);
-        let swapchain_format = swapchain_capabilities.formats[0]; // should be Bgra8Unorm generally
-        let pipeline_manager = PipelineManager::new(
+        let surface_options: SurfaceOptions =
+            options_or_default(surface_options, "surface options")?;
+        let device_options: DeviceOptions = options_or_default(device_options, "device options")?;
+        let gpu = GpuContext::new(canvas, width, height, &surface_options, &device_options).await?;
+        let textures = TextureManager::new(
             &gpu.device,
-            swapchain_format,
-            &buffer_manager,
-            &texture_manager,
-        );
-        let renderer = Renderer::new();
-        Ok(App {
-            gpu,
-            buffers: buffer_manager,
-            textures: texture_manager,
-            pipeline: pipeline_manager,
-            renderer,
-        })
+            &gpu.queue,
+            &gpu.cache,
+            Array::from(&textures_data),
+        )?;
+        App::assemble(gpu, textures)
+    }
+
+    /// Starts rendering to another canvas with the device of this `App`, returning an
+    /// `App` for it with its own scene, camera, materials, lights, shaders and surface
+    /// configured by `surface_options`, see `setup`. It starts out with the textures of
+    /// this `App`. Pipelines and decoded textures are built once and shared by all
+    /// canvases on the device, e.g.
+    /// `const minimap = app.add_canvas(document.getElementById("minimap"), undefined)`.
+    /// Needs WebGPU, on WebGL2 every canvas needs its own `setup`.
+    #[wasm_bindgen]
+    pub fn add_canvas(&self, canvas: HtmlCanvasElement, surface_options: JsValue) -> Result<App> {
+        self.ensure_alive()?;
+        let (width, height) = (canvas.width(), canvas.height());
+        info!("Adding canvas, width={width}, height={height}");
+        let surface_options: SurfaceOptions =
+            options_or_default(surface_options, "surface options")?;
+        let gpu = self.gpu.share(canvas, width, height, &surface_options)?;
+        let textures = TextureManager::from_sources(
+            &gpu.device,
+            &gpu.queue,
+            &gpu.cache,
+            self.textures.sources(),
+        )?;
+        App::assemble(gpu, textures)
     }
 
     #[wasm_bindgen]
@@ -62,11 +154,16 @@ impl App {
         time: Option<f32>,
         delta_time: Option<f32>,
         mouse: JsValue,
-    ) -> Result<(), JsError> {
-        let mouse_pos = if !mouse.is_null() && !mouse.is_undefined() {
-            serde_wasm_bindgen::from_value::<MousePos>(mouse).ok()
+    ) -> Result<()> {
+        self.ensure_alive()?;
+        let start = profiler::now();
+        let (mouse_pos, mouse_state) = if !mouse.is_null() && !mouse.is_undefined() {
+            (
+                serde_wasm_bindgen::from_value::<MousePos>(mouse.clone()).ok(),
+                serde_wasm_bindgen::from_value::<MouseState>(mouse).ok(),
+            )
         } else {
-            None
+            (None, None)
         };
         self.buffers
             .uniform_manager
@@ -76,12 +173,861 @@ impl App {
             0,
             bytemuck::bytes_of(&self.buffers.uniform_manager.per_frame_uniform_data),
         );
+        self.camera.update(mouse_state, delta_time.unwrap_or(0.0));
+        self.write_camera_uniform();
+        let elapsed = (profiler::now() - start) as f32;
+        self.profiler.record("update", TimingSource::Cpu, elapsed);
+        Ok(())
+    }
+
+    /// Replaces the instances drawn for every node using a mesh. `data` holds
+    /// `INSTANCE_FLOATS` (21) floats per instance: a column major model matrix, an RGBA
+    /// color and the texture index (negative for untextured).
+    #[wasm_bindgen]
+    pub fn set_instances(&mut self, mesh: u32, data: &[f32]) -> Result<()> {
+        self.ensure_alive()?;
+        if !data.len().is_multiple_of(INSTANCE_FLOATS) {
+            return Err(Error::InvalidInput(format!(
+                "Instance data length {} is not a multiple of {INSTANCE_FLOATS}",
+                data.len()
+            )));
+        }
+        let mesh = self.buffers.mesh_mut(mesh as usize)?;
+        mesh.instances = data
+            .chunks_exact(INSTANCE_FLOATS)
+            .map(InstanceRaw::from_floats)
+            .collect();
         Ok(())
     }
 
+    /// Call when the canvas size changes, with the new drawing buffer size.
+    #[wasm_bindgen]
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<()> {
+        self.ensure_alive()?;
+        log_every!(
+            250.0,
+            Level::Info,
+            "Resizing to width={width}, height={height}"
+        );
+        self.gpu.resize(width, height);
+        self.renderer.resize(&self.gpu.device, width, height);
+        self.buffers.uniform_manager.resize(width, height);
+        self.gpu.queue.write_buffer(
+            &self.buffers.uniform_manager.program_uniform_buffer,
+            0,
+            bytemuck::bytes_of(&self.buffers.uniform_manager.program_uniform_data),
+        );
+        self.camera.camera.resize(width, height);
+        self.write_camera_uniform();
+        Ok(())
+    }
+
+    /// The browser API rendering runs on, `"webgpu"` or `"webgl2"` when the browser has
+    /// no WebGPU.
+    #[wasm_bindgen]
+    pub fn backend(&self) -> Result<String> {
+        self.ensure_alive()?;
+        Ok(self.gpu.backend().name().to_string())
+    }
+
+    /// The features and limits the device was created with, as
+    /// `{ backend: "webgpu", compute: true, features: ["timestampQuery"],
+    /// limits: { maxTextureDimension2D: 8192, ... } }`.
+    #[wasm_bindgen]
+    pub fn device_capabilities(&self) -> Result<JsValue> {
+        self.ensure_alive()?;
+        serde_wasm_bindgen::to_value(&self.gpu.capabilities())
+            .map_err(|err| Error::Internal(format!("Failed to convert capabilities: {err}")))
+    }
+
+    /// Describes the adapter, driver, device and surface for bug reports, as
+    /// `{ name, vendor, device, deviceType, driver, driverInfo, backend, compute, features,
+    /// limits, surface: { format, presentMode, alphaMode, desiredFrameLatency },
+    /// surfaceFormats, presentModes, alphaModes }`.
+    #[wasm_bindgen]
+    pub fn gpu_info(&self) -> Result<JsValue> {
+        self.ensure_alive()?;
+        serde_wasm_bindgen::to_value(&self.gpu.info())
+            .map_err(|err| Error::Internal(format!("Failed to convert GPU info: {err}")))
+    }
+
+    /// Sets the samples per pixel of the scene passes, 4 to smooth geometry edges or 1
+    /// to turn multisampling off. Fails when the adapter can't multisample the surface.
     #[wasm_bindgen]
-    pub fn render(&self) -> Result<(), JsError> {
+    pub fn set_msaa_samples(&mut self, samples: u32) -> Result<()> {
+        self.ensure_alive()?;
+        let format = self.pipeline.swapchain_format;
+        if !renderer::supports_sample_count(&self.gpu.adapter, format, samples) {
+            return Err(Error::InvalidInput(format!(
+                "Unsupported sample count {samples} for {format:?}, use one of {:?}",
+                renderer::SAMPLE_COUNTS
+            )));
+        }
+        let (width, height) = (self.gpu.config.width, self.gpu.config.height);
         self.renderer
-            .render(&self.gpu, &self.buffers, &self.textures, &self.pipeline)
+            .set_sample_count(&self.gpu.device, samples, width, height);
+        self.pipeline.set_sample_count(&self.gpu.device, samples);
+        info!("Rendering with {samples} samples per pixel");
+        Ok(())
+    }
+
+    /// `fovy` is the vertical field of view in degrees.
+    #[wasm_bindgen]
+    pub fn set_camera_perspective(&mut self, fovy: f32, znear: f32, zfar: f32) -> Result<()> {
+        self.ensure_alive()?;
+        self.camera.camera.projection = Projection::Perspective {
+            fovy: fovy.to_radians(),
+            znear,
+            zfar,
+        };
+        self.write_camera_uniform();
+        Ok(())
+    }
+
+    /// Orthographic projection `height` units tall, with the width following the aspect ratio.
+    #[wasm_bindgen]
+    pub fn set_camera_orthographic(&mut self, height: f32, znear: f32, zfar: f32) -> Result<()> {
+        self.ensure_alive()?;
+        self.camera.camera.projection = Projection::Orthographic {
+            width: None,
+            height,
+            znear,
+            zfar,
+        };
+        self.write_camera_uniform();
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn set_camera_look_at(&mut self, eye: Vec<f32>, target: Vec<f32>) -> Result<()> {
+        self.ensure_alive()?;
+        let (eye, target) = match (eye.as_slice(), target.as_slice()) {
+            (&[ex, ey, ez], &[tx, ty, tz]) => (Vec3::new(ex, ey, ez), Vec3::new(tx, ty, tz)),
+            _ => {
+                return Err(Error::InvalidInput(
+                    "Camera eye and target need 3 components each".to_string(),
+                ));
+            }
+        };
+        self.camera.camera.eye = eye;
+        self.camera.camera.target = target;
+        // restart the controller from the new view
+        self.set_camera_controller(self.camera.controller_name())?;
+        self.write_camera_uniform();
+        Ok(())
+    }
+
+    /// One of `"none"`, `"orbit"` or `"fly"`, driven by the mouse passed to `update`.
+    #[wasm_bindgen]
+    pub fn set_camera_controller(&mut self, controller: &str) -> Result<()> {
+        self.ensure_alive()?;
+        self.camera
+            .set_controller(controller)
+            .map_err(Error::InvalidInput)
+    }
+
+    /// Movement of the fly controller in camera space (right, up, forward), each in [-1, 1].
+    #[wasm_bindgen]
+    pub fn set_camera_movement(&mut self, right: f32, up: f32, forward: f32) -> Result<()> {
+        self.ensure_alive()?;
+        if let CameraController::Fly(fly) = &mut self.camera.controller {
+            fly.movement = Vec3::new(right, up, forward);
+        }
+        Ok(())
+    }
+
+    /// Loads an OBJ or glTF file (`format` is its extension) and uploads one mesh
+    /// per model/primitive along with the file's materials, which become the meshes'
+    /// materials. `material_data` is the MTL file referenced by an OBJ.
+    /// Returns the handles of the new meshes.
+    #[wasm_bindgen]
+    pub fn load_mesh(
+        &mut self,
+        data: &[u8],
+        format: &str,
+        material_data: Option<Vec<u8>>,
+    ) -> Result<Vec<u32>> {
+        self.ensure_alive()?;
+        let format = MeshFormat::from_extension(format)
+            .ok_or_else(|| Error::InvalidInput(format!("Unsupported mesh format: {format}")))?;
+        let model = mesh_loader::load_mesh(format, data, material_data.as_deref())?;
+        let first_material = self.materials.materials.len();
+        for material in &model.materials {
+            self.materials
+                .add_material(&self.gpu.device, &self.gpu.queue, material);
+        }
+        model
+            .meshes
+            .into_iter()
+            .map(|mut mesh| {
+                mesh.material = mesh.material.map(|material| first_material + material);
+                Ok(self.buffers.add_mesh(&self.gpu.device, &mesh)? as u32)
+            })
+            .collect()
+    }
+
+    /// Creates a metallic-roughness material and returns its handle. `factors` is an
+    /// object like `{ baseColor: [1, 0, 0, 1], metallic: 0, roughness: 0.5 }`, and the
+    /// optional `textures` object maps `baseColor`, `metallicRoughness`, `normal`,
+    /// `occlusion` and `emissive` to encoded images as `Uint8Array`s.
+    #[wasm_bindgen]
+    pub fn create_material(&mut self, factors: JsValue, textures: JsValue) -> Result<u32> {
+        self.ensure_alive()?;
+        let factors = serde_wasm_bindgen::from_value::<MaterialFactors>(factors)
+            .map_err(|err| Error::InvalidInput(format!("Invalid material factors: {err}")))?;
+        let texture = |name: &str| -> Result<Option<MaterialTexture>> {
+            if textures.is_null() || textures.is_undefined() {
+                return Ok(None);
+            }
+            let value = Reflect::get(&textures, &JsValue::from_str(name)).map_err(|_| {
+                Error::InvalidInput(format!("Could not read material texture {name}"))
+            })?;
+            if value.is_null() || value.is_undefined() {
+                return Ok(None);
+            }
+            let image = MaterialImage::decode(&Uint8Array::new(&value).to_vec())?;
+            Ok(Some(MaterialTexture::new(image)))
+        };
+        let data = MaterialData {
+            factors,
+            base_color_texture: texture("baseColor")?,
+            metallic_roughness_texture: texture("metallicRoughness")?,
+            normal_texture: texture("normal")?,
+            occlusion_texture: texture("occlusion")?,
+            emissive_texture: texture("emissive")?,
+            ..Default::default()
+        };
+        Ok(self
+            .materials
+            .add_material(&self.gpu.device, &self.gpu.queue, &data) as u32)
+    }
+
+    /// Replaces the factors of a material, keeping its textures.
+    #[wasm_bindgen]
+    pub fn set_material_factors(&mut self, material: u32, factors: JsValue) -> Result<()> {
+        self.ensure_alive()?;
+        let factors = serde_wasm_bindgen::from_value::<MaterialFactors>(factors)
+            .map_err(|err| Error::InvalidInput(format!("Invalid material factors: {err}")))?;
+        self.materials
+            .set_factors(&self.gpu.queue, material as usize, factors)
+    }
+
+    /// Sets how a material blends with what is behind it: `"opaque"`, `"alpha"`,
+    /// `"premultiplied"`, `"additive"`, `"multiply"` or `"screen"`. Nodes with a
+    /// transparent material are drawn after the opaque ones, sorted back to front.
+    #[wasm_bindgen]
+    pub fn set_material_blend_mode(&mut self, material: u32, mode: JsValue) -> Result<()> {
+        self.ensure_alive()?;
+        let mode = serde_wasm_bindgen::from_value::<BlendMode>(mode)
+            .map_err(|err| Error::InvalidInput(format!("Invalid blend mode: {err}")))?;
+        self.materials.set_blend_mode(material as usize, mode)
+    }
+
+    /// Queues a sprite for the next frame, described by an object like
+    /// `{ texture: 0, position: [100, 50], rotation: 0.5, scale: [2, 2], layer: 1 }`.
+    #[wasm_bindgen]
+    pub fn draw_sprite(&mut self, sprite: JsValue) -> Result<()> {
+        self.ensure_alive()?;
+        let sprite = serde_wasm_bindgen::from_value::<Sprite>(sprite)
+            .map_err(|err| Error::InvalidInput(format!("Invalid sprite description: {err}")))?;
+        self.sprites.add(sprite)
+    }
+
+    /// Queues many sprites of one texture for the next frame, `SPRITE_FLOATS` floats
+    /// each: x, y, rotation, scale x and y, UV rect x, y, width and height, RGBA tint
+    /// and layer. Untextured sprites pass no texture.
+    #[wasm_bindgen]
+    pub fn draw_sprites(&mut self, texture: Option<u32>, data: &[f32]) -> Result<()> {
+        self.ensure_alive()?;
+        if !data.len().is_multiple_of(SPRITE_FLOATS) {
+            return Err(Error::InvalidInput(format!(
+                "Sprite data length {} is not a multiple of {SPRITE_FLOATS}",
+                data.len()
+            )));
+        }
+        for sprite in data.chunks_exact(SPRITE_FLOATS) {
+            self.sprites.add(Sprite::from_floats(texture, sprite))?;
+        }
+        Ok(())
+    }
+
+    /// Loads a TrueType or OpenType font and returns its handle for `draw_text`.
+    #[wasm_bindgen]
+    pub fn load_font(&mut self, data: &[u8]) -> Result<u32> {
+        self.ensure_alive()?;
+        let font = self
+            .text
+            .load_font(&self.gpu.device, &self.gpu.queue, data)?;
+        Ok(font as u32)
+    }
+
+    /// Queues a string for the next frame, styled by an object like
+    /// `{ position: [20, 20], size: 24, maxWidth: 300, align: "center", outlineWidth: 2,
+    /// shadowColor: [0, 0, 0, 0.5] }`. Newlines start new lines.
+    #[wasm_bindgen]
+    pub fn draw_text(&mut self, font: u32, text: &str, style: JsValue) -> Result<()> {
+        self.ensure_alive()?;
+        let style: TextStyle = options_or_default(style, "text style")?;
+        self.text.add(font as usize, text, &style)
+    }
+
+    /// Returns the width and height in pixels `draw_text` would lay the string out in.
+    #[wasm_bindgen]
+    pub fn measure_text(&self, font: u32, text: &str, style: JsValue) -> Result<Vec<f32>> {
+        self.ensure_alive()?;
+        let style: TextStyle = options_or_default(style, "text style")?;
+        Ok(self
+            .text
+            .font(font as usize)?
+            .measure(text, &style)
+            .to_vec())
+    }
+
+    /// Queues a debug shape for the next frame, described by an object like
+    /// `{ type: "aabb", min: [-1, 0, -1], max: [1, 2, 1], color: [0, 1, 0, 1] }` or
+    /// `{ type: "rect", center: [100, 100], size: [50, 20], space: "screen" }`. Shape
+    /// types are `line`, `rect`, `circle`, `aabb`, `axes` and `grid`.
+    #[wasm_bindgen]
+    pub fn debug_draw(&mut self, shape: JsValue) -> Result<()> {
+        self.ensure_alive()?;
+        let draw = serde_wasm_bindgen::from_value::<DebugDraw>(shape)
+            .map_err(|err| Error::InvalidInput(format!("Invalid debug shape: {err}")))?;
+        self.debug.queue(draw.shape, draw.space, draw.color);
+        Ok(())
+    }
+
+    /// Turns debug drawing on or off, shapes queued while off are ignored.
+    #[wasm_bindgen]
+    pub fn set_debug_draw_enabled(&mut self, enabled: bool) -> Result<()> {
+        self.ensure_alive()?;
+        self.debug.set_enabled(enabled);
+        Ok(())
+    }
+
+    /// Turns the profiler on or off, it starts out off. While on, `update` and `render`
+    /// are timed on the CPU, and every render pass on the GPU when the device has the
+    /// `timestampQuery` feature. Turning it on forgets earlier samples.
+    #[wasm_bindgen]
+    pub fn set_profiler_enabled(&mut self, enabled: bool) -> Result<()> {
+        self.ensure_alive()?;
+        if enabled && !self.profiler.enabled {
+            self.profiler.reset();
+        }
+        self.profiler.enabled = enabled;
+        Ok(())
+    }
+
+    /// Rolling statistics of the last 120 frames, in milliseconds, as an array like
+    /// `[{ name: "render", source: "cpu", average, p50, p95, p99, max, samples }]`.
+    /// GPU timings are named after their pass and arrive a few frames late.
+    #[wasm_bindgen]
+    pub fn profiler_stats(&self) -> Result<JsValue> {
+        self.ensure_alive()?;
+        serde_wasm_bindgen::to_value(&self.profiler.stats())
+            .map_err(|err| Error::Internal(format!("Failed to convert profiler stats: {err}")))
+    }
+
+    /// Shows the profiler stats on the canvas as bars of their average with a mark at
+    /// the 95th percentile, labeled in `font` when given.
+    #[wasm_bindgen]
+    pub fn set_profiler_overlay(&mut self, enabled: bool, font: Option<u32>) -> Result<()> {
+        self.ensure_alive()?;
+        if let Some(font) = font {
+            self.text.font(font as usize)?;
+        }
+        self.profiler.overlay = enabled.then_some(font.map(|font| font as usize));
+        Ok(())
+    }
+
+    /// Adds a light and returns its handle. `light` is an object like
+    /// `{ type: "spot", position: [0, 4, 0], direction: [0, -1, 0], castShadows: true }`,
+    /// with `type` one of `directional`, `point` and `spot`.
+    #[wasm_bindgen]
+    pub fn add_light(&mut self, light: JsValue) -> Result<u32> {
+        self.ensure_alive()?;
+        let light = serde_wasm_bindgen::from_value::<Light>(light)
+            .map_err(|err| Error::InvalidInput(format!("Invalid light description: {err}")))?;
+        Ok(self.lights.add_light(light)? as u32)
+    }
+
+    /// Replaces all parameters of a light, including its type.
+    #[wasm_bindgen]
+    pub fn set_light(&mut self, light: u32, description: JsValue) -> Result<()> {
+        self.ensure_alive()?;
+        let description = serde_wasm_bindgen::from_value::<Light>(description)
+            .map_err(|err| Error::InvalidInput(format!("Invalid light description: {err}")))?;
+        *self.lights.light_mut(light as usize)? = description;
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn remove_light(&mut self, light: u32) -> Result<()> {
+        self.ensure_alive()?;
+        self.lights.remove_light(light as usize)
+    }
+
+    /// Sets the constant light added to every surface, in linear RGB.
+    #[wasm_bindgen]
+    pub fn set_ambient_light(&mut self, r: f32, g: f32, b: f32) -> Result<()> {
+        self.ensure_alive()?;
+        self.lights.ambient = [r, g, b];
+        Ok(())
+    }
+
+    /// Configures the shadow maps with an object like
+    /// `{ mapSize: 2048, cascades: 3, distance: 50, pcfRadius: 1 }`; missing fields keep
+    /// their defaults.
+    #[wasm_bindgen]
+    pub fn set_shadow_settings(&mut self, settings: JsValue) -> Result<()> {
+        self.ensure_alive()?;
+        let settings = serde_wasm_bindgen::from_value::<ShadowSettings>(settings)
+            .map_err(|err| Error::InvalidInput(format!("Invalid shadow settings: {err}")))?;
+        self.lights.set_shadow_settings(&self.gpu.device, settings);
+        Ok(())
+    }
+
+    /// Bakes image based lighting from an equirectangular Radiance `.hdr` image, which
+    /// is also drawn as the skybox.
+    #[wasm_bindgen]
+    pub fn load_environment(&mut self, data: &[u8]) -> Result<()> {
+        self.ensure_alive()?;
+        if !self.gpu.supports_compute() {
+            return Err(Error::Device(format!(
+                "Environment lighting needs compute shaders, which {} doesn't have",
+                self.gpu.backend().name()
+            )));
+        }
+        let environment = Environment::from_hdr(&self.gpu.device, &self.gpu.queue, data)?;
+        self.lights
+            .set_environment(&self.gpu.device, Some(environment));
+        Ok(())
+    }
+
+    /// Removes the environment lighting and the skybox.
+    #[wasm_bindgen]
+    pub fn clear_environment(&mut self) -> Result<()> {
+        self.ensure_alive()?;
+        self.lights.set_environment(&self.gpu.device, None);
+        Ok(())
+    }
+
+    /// Scales the light coming from the environment.
+    #[wasm_bindgen]
+    pub fn set_environment_intensity(&mut self, intensity: f32) -> Result<()> {
+        self.ensure_alive()?;
+        self.lights.environment_intensity = intensity;
+        Ok(())
+    }
+
+    /// Generates a primitive shape described by a JS object such as
+    /// `{ type: "torus", radius: 1.0, tubeRadius: 0.25 }` and returns its mesh handle.
+    #[wasm_bindgen]
+    pub fn create_primitive(&mut self, primitive: JsValue) -> Result<u32> {
+        self.ensure_alive()?;
+        let primitive = serde_wasm_bindgen::from_value::<Primitive>(primitive)
+            .map_err(|err| Error::InvalidInput(format!("Invalid primitive description: {err}")))?;
+        Ok(self.buffers.add_mesh(&self.gpu.device, &primitive.mesh())? as u32)
+    }
+
+    /// Tessellates a 2D vector shape into a flat mesh and returns its mesh handle. The
+    /// shape is described by an object like `{ type: "path", data: "M 0 0 L 2 0 L 1 2 Z",
+    /// fill: [1, 0, 0], stroke: { color: [0, 0, 0], width: 0.05 } }`, with `type` one of
+    /// `path`, `polygon`, `rect` and `ellipse`.
+    #[wasm_bindgen]
+    pub fn create_path(&mut self, path: JsValue) -> Result<u32> {
+        self.ensure_alive()?;
+        let path = serde_wasm_bindgen::from_value::<VectorPath>(path)
+            .map_err(|err| Error::InvalidInput(format!("Invalid path description: {err}")))?;
+        Ok(self.buffers.add_mesh(&self.gpu.device, &path.mesh()?)? as u32)
+    }
+
+    /// Creates an empty scene node, at the root when `parent` is not given.
+    #[wasm_bindgen]
+    pub fn create_node(&mut self, parent: Option<NodeHandle>) -> Result<NodeHandle> {
+        self.ensure_alive()?;
+        self.scene.add_node(parent)
+    }
+
+    /// Removes the node and all of its descendants.
+    #[wasm_bindgen]
+    pub fn remove_node(&mut self, node: NodeHandle) -> Result<()> {
+        self.ensure_alive()?;
+        self.scene.remove_node(node)
+    }
+
+    #[wasm_bindgen]
+    pub fn set_node_parent(&mut self, node: NodeHandle, parent: Option<NodeHandle>) -> Result<()> {
+        self.ensure_alive()?;
+        self.scene.set_parent(node, parent)
+    }
+
+    /// Local transform relative to the parent: a translation, a rotation quaternion
+    /// (x, y, z, w) and a scale.
+    #[wasm_bindgen]
+    pub fn set_node_transform(
+        &mut self,
+        node: NodeHandle,
+        translation: &[f32],
+        rotation: &[f32],
+        scale: &[f32],
+    ) -> Result<()> {
+        self.ensure_alive()?;
+        let (translation, rotation, scale) = match (translation, rotation, scale) {
+            (&[tx, ty, tz], &[rx, ry, rz, rw], &[sx, sy, sz]) => (
+                Vec3::new(tx, ty, tz),
+                Quat::from_xyzw(rx, ry, rz, rw).normalize(),
+                Vec3::new(sx, sy, sz),
+            ),
+            _ => {
+                return Err(Error::InvalidInput(
+                    "Node transform needs a 3 component translation and scale and a 4 component \
+                     rotation"
+                        .to_string(),
+                ));
+            }
+        };
+        let local = &mut self.scene.node_mut(node)?.local;
+        local.translation = translation;
+        local.rotation = rotation;
+        local.scale = scale;
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn set_node_mesh(&mut self, node: NodeHandle, mesh: Option<u32>) -> Result<()> {
+        self.ensure_alive()?;
+        if let Some(mesh) = mesh {
+            self.buffers.mesh(mesh as usize)?;
+        }
+        self.scene.node_mut(node)?.mesh = mesh.map(|mesh| mesh as usize);
+        Ok(())
+    }
+
+    /// Overrides the material of the node's mesh, `None` goes back to the mesh material.
+    #[wasm_bindgen]
+    pub fn set_node_material(&mut self, node: NodeHandle, material: Option<u32>) -> Result<()> {
+        self.ensure_alive()?;
+        if let Some(material) = material {
+            self.materials.get(Some(material as usize))?;
+        }
+        self.scene.node_mut(node)?.material = material.map(|material| material as usize);
+        Ok(())
+    }
+
+    /// Hidden nodes are skipped together with their children.
+    #[wasm_bindgen]
+    pub fn set_node_visible(&mut self, node: NodeHandle, visible: bool) -> Result<()> {
+        self.ensure_alive()?;
+        self.scene.node_mut(node)?.visible = visible;
+        Ok(())
+    }
+
+    /// Column major world matrix of the node as of the last update or render.
+    #[wasm_bindgen]
+    pub fn node_world_matrix(&mut self, node: NodeHandle) -> Result<Vec<f32>> {
+        self.ensure_alive()?;
+        self.scene.update_world_transforms();
+        Ok(self.scene.node(node)?.world.to_cols_array().to_vec())
+    }
+
+    /// Replaces shaders, textures, nodes, passes, camera and uniforms with the ones of a JSON
+    /// or RON scene file (`format` is its extension). `assets` maps the paths used in the
+    /// file to their contents as `Uint8Array`s. The scene's meshes and materials are added
+    /// after the existing ones, so handles returned earlier keep pointing at the same
+    /// meshes and materials. Those the previously loaded scene added are freed, using
+    /// their handles afterwards fails with an `invalidHandle` error.
+    #[wasm_bindgen]
+    pub fn load_scene(&mut self, text: &str, format: &str, assets: JsValue) -> Result<()> {
+        self.ensure_alive()?;
+        let format = SceneFormat::from_extension(format)
+            .ok_or_else(|| Error::InvalidInput(format!("Unsupported scene format: {format}")))?;
+        let description = SceneDescription::parse(text, format)?;
+        let first = (self.buffers.meshes.len(), self.materials.materials.len());
+        let loaded = description.load(first, |path| {
+            let value = Reflect::get(&assets, &JsValue::from_str(path)).ok()?;
+            if value.is_null() || value.is_undefined() {
+                return None;
+            }
+            Some(Uint8Array::new(&value).to_vec())
+        })?;
+        for (name, code) in &loaded.shaders {
+            PipelineManager::validate_shader(name, code, loaded.textures.len())?;
+        }
+
+        let gpu = &self.gpu;
+        let textures =
+            TextureManager::from_sources(&gpu.device, &gpu.queue, &gpu.cache, &loaded.textures)?;
+        self.buffers.remove_meshes(self.scene_meshes.clone());
+        self.materials
+            .remove_materials(self.scene_materials.clone());
+        for material in &loaded.materials {
+            self.materials
+                .add_material(&self.gpu.device, &self.gpu.queue, material);
+        }
+        for mesh in &loaded.meshes {
+            self.buffers.add_mesh(&self.gpu.device, mesh)?;
+        }
+        self.scene_meshes = first.0..self.buffers.meshes.len();
+        self.scene_materials = first.1..self.materials.materials.len();
+        let mut pipeline = PipelineManager::new(
+            &self.gpu,
+            self.pipeline.swapchain_format,
+            self.pipeline.sample_count,
+            &self.buffers,
+            &textures,
+            &self.materials,
+            &self.lights,
+        );
+        for (name, code) in &loaded.shaders {
+            pipeline.add_shader(&self.gpu.device, name, code);
+        }
+        self.sprites.set_textures(&self.gpu.device, &textures);
+        self.textures = textures;
+        self.pipeline = pipeline;
+        self.scene = loaded.scene;
+        self.renderer.passes = if loaded.passes.is_empty() {
+            vec![PassConfig::default()]
+        } else {
+            loaded.passes
+        };
+
+        if let Some(camera) = &description.camera {
+            camera.apply(&mut self.camera)?;
+        }
+        self.write_camera_uniform();
+        let uniforms = description.uniforms;
+        self.buffers.uniform_manager.update(
+            Some(uniforms.time),
+            Some(uniforms.delta_time),
+            Some(MousePos::new(uniforms.mouse[0], uniforms.mouse[1])),
+        );
+        self.gpu.queue.write_buffer(
+            &self.buffers.uniform_manager.per_frame_uniform_buffer,
+            0,
+            bytemuck::bytes_of(&self.buffers.uniform_manager.per_frame_uniform_data),
+        );
+        self.scene_description = description;
+        self.scene_nodes = loaded.node_handles;
+        Ok(())
+    }
+
+    /// Serializes the last loaded scene as JSON or RON, with the current node
+    /// transforms, camera and uniform values.
+    #[wasm_bindgen]
+    pub fn save_scene(&self, format: &str) -> Result<String> {
+        self.ensure_alive()?;
+        let format = SceneFormat::from_extension(format)
+            .ok_or_else(|| Error::InvalidInput(format!("Unsupported scene format: {format}")))?;
+        let mut description = self.scene_description.clone();
+        description.update_nodes(&self.scene, &self.scene_nodes);
+        description.camera = Some(CameraDescription::from_camera(&self.camera));
+        let uniforms = &self.buffers.uniform_manager.per_frame_uniform_data;
+        description.uniforms = UniformValues {
+            time: uniforms.time(),
+            delta_time: uniforms.delta_time(),
+            mouse: uniforms.mouse(),
+        };
+        description.serialize(format)
+    }
+
+    /// Draws a frame. While the device is lost frames are skipped, until a replacement
+    /// device is there and everything has been uploaded to it again.
+    #[wasm_bindgen]
+    pub fn render(&mut self) -> Result<()> {
+        self.ensure_alive()?;
+        let start = profiler::now();
+        let result = self.render_frame();
+        let elapsed = (profiler::now() - start) as f32;
+        self.profiler.record("render", TimingSource::Cpu, elapsed);
+        result
+    }
+
+    /// Frees what the `App` holds on the GPU when its canvas goes away: unconfigures the
+    /// canvas, destroys buffers and textures and drops pipelines. The device goes with
+    /// the last `App` drawing with it, see `add_canvas`. Every later call fails with a
+    /// `destroyed` error, and `free` releases the `App` itself, e.g.
+    /// `app.destroy(); app.free();` when unmounting. Destroying twice does nothing.
+    #[wasm_bindgen]
+    pub fn destroy(&mut self) {
+        if self.destroyed {
+            return;
+        }
+        self.destroyed = true;
+        self.buffers.destroy();
+        self.textures.destroy();
+        self.materials.destroy();
+        self.lights.destroy();
+        self.pipeline.destroy();
+        self.renderer.destroy();
+        self.sprites.destroy();
+        self.text.destroy();
+        self.debug.destroy();
+        self.profiler.destroy();
+        self.scene = Scene::new();
+        self.scene_nodes.clear();
+        self.gpu.destroy();
+        info!("App destroyed");
+    }
+}
+
+impl App {
+    // everything but the device and textures is per canvas
+    fn assemble(gpu: GpuContext<'static>, textures: TextureManager) -> Result<App> {
+        let (width, height) = (gpu.config.width, gpu.config.height);
+        let camera = CameraManager::new(width, height);
+        let buffer_manager =
+            BufferManager::new(&gpu.device, width, height, camera.camera.uniform());
+        let material_manager = MaterialManager::new(&gpu.device, &gpu.queue);
+        let light_manager = LightManager::new(&gpu.device);
+        let swapchain_format = gpu.config.format;
+        // multisampled unless the adapter can't
+        let sample_count = if renderer::supports_sample_count(&gpu.adapter, swapchain_format, 4) {
+            4
+        } else {
+            1
+        };
+        let pipeline_manager = PipelineManager::new(
+            &gpu,
+            swapchain_format,
+            sample_count,
+            &buffer_manager,
+            &textures,
+            &material_manager,
+            &light_manager,
+        );
+        let renderer = Renderer::new(&gpu.device, swapchain_format, sample_count, (width, height));
+        let sprites = SpriteBatch::new(
+            &gpu.device,
+            &gpu.queue,
+            swapchain_format,
+            &buffer_manager.uniform_manager.bind_group_layout,
+            &textures,
+        );
+        let text = TextRenderer::new(
+            &gpu.device,
+            swapchain_format,
+            &buffer_manager.uniform_manager.bind_group_layout,
+        );
+        let debug = DebugRenderer::new(
+            &gpu.device,
+            swapchain_format,
+            &buffer_manager.uniform_manager.bind_group_layout,
+        );
+        let timestamps = gpu.has_feature(GpuFeature::TimestampQuery);
+        let profiler = Profiler::new(&gpu.device, &gpu.queue, timestamps);
+        // keep drawing the fullscreen quad until JS builds its own scene
+        let mut scene = Scene::new();
+        let quad_node = scene.add_node(None)?;
+        scene.node_mut(quad_node)?.mesh = Some(0);
+        Ok(App {
+            gpu,
+            buffers: buffer_manager,
+            textures,
+            materials: material_manager,
+            lights: light_manager,
+            pipeline: pipeline_manager,
+            renderer,
+            sprites,
+            text,
+            debug,
+            profiler,
+            camera,
+            scene,
+            scene_description: SceneDescription::default(),
+            scene_nodes: HashMap::new(),
+            scene_meshes: 0..0,
+            scene_materials: 0..0,
+            destroyed: false,
+        })
+    }
+
+    fn render_frame(&mut self) -> Result<()> {
+        if self.gpu.recover()? {
+            self.recreate_resources()?;
+        }
+        if self.gpu.is_lost() {
+            return Ok(());
+        }
+        self.scene.update_world_transforms();
+        self.lights
+            .update(&self.gpu.device, &self.gpu.queue, &self.camera.camera);
+        self.profiler.begin_frame();
+        self.profiler
+            .draw_overlay(&mut self.sprites, &mut self.text);
+        self.sprites.prepare(&self.gpu.device, &self.gpu.queue);
+        self.text.prepare(&self.gpu.device, &self.gpu.queue);
+        self.debug.prepare(&self.gpu.device, &self.gpu.queue);
+        for mode in self.materials.blend_modes() {
+            self.pipeline.prepare_blend_mode(&self.gpu.device, mode);
+        }
+        let resources = FrameResources {
+            buffers: &self.buffers,
+            textures: &self.textures,
+            materials: &self.materials,
+            lights: &self.lights,
+            pipeline: &self.pipeline,
+            camera: &self.camera.camera,
+            sprites: &self.sprites,
+            text: &self.text,
+            debug: &self.debug,
+        };
+        let profiler = &mut self.profiler;
+        self.renderer
+            .render(&self.gpu, &resources, &self.scene, profiler)
+    }
+
+    // uploads everything again after the device was replaced, from the CPU side copies
+    // the managers keep
+    fn recreate_resources(&mut self) -> Result<()> {
+        let (device, queue) = (&self.gpu.device, &self.gpu.queue);
+        let format = self.pipeline.swapchain_format;
+        self.buffers = self.buffers.recreate(device, queue);
+        self.textures = self.textures.recreate(device, queue, &self.gpu.cache)?;
+        self.materials = self.materials.recreate(device, queue);
+        self.lights = self.lights.recreate(device, queue)?;
+        self.pipeline = self.pipeline.recreate(
+            &self.gpu,
+            &self.buffers,
+            &self.textures,
+            &self.materials,
+            &self.lights,
+        );
+        let uniform_layout = &self.buffers.uniform_manager.bind_group_layout;
+        let size = (self.gpu.config.width, self.gpu.config.height);
+        let passes = std::mem::take(&mut self.renderer.passes);
+        self.renderer = Renderer::new(device, format, self.pipeline.sample_count, size);
+        self.renderer.passes = passes;
+        self.sprites = SpriteBatch::new(device, queue, format, uniform_layout, &self.textures);
+        self.text = self.text.recreate(device, queue, format, uniform_layout);
+        self.debug = DebugRenderer::new(device, format, uniform_layout);
+        let timestamps = self.gpu.has_feature(GpuFeature::TimestampQuery);
+        self.profiler = self.profiler.recreate(device, queue, timestamps);
+        info!("Resources recreated on the replacement device");
+        Ok(())
+    }
+
+    fn ensure_alive(&self) -> Result<()> {
+        if self.destroyed {
+            return Err(Error::Destroyed);
+        }
+        Ok(())
+    }
+
+    fn write_camera_uniform(&mut self) {
+        self.buffers.uniform_manager.camera_uniform_data = self.camera.camera.uniform();
+        self.gpu.queue.write_buffer(
+            &self.buffers.uniform_manager.camera_uniform_buffer,
+            0,
+            bytemuck::bytes_of(&self.buffers.uniform_manager.camera_uniform_data),
+        );
+    }
+}
+
+// missing options take the defaults
+fn options_or_default<T: DeserializeOwned + Default>(value: JsValue, name: &str) -> Result<T> {
+    if value.is_undefined() {
+        return Ok(T::default());
     }
+    serde_wasm_bindgen::from_value::<T>(value)
+        .map_err(|err| Error::InvalidInput(format!("Invalid {name}: {err}")))
 }
@@ -0,0 +1,802 @@
+use glam::{Mat4, Vec3, Vec4Swizzles};
+use log::info;
+use serde::{Deserialize, Serialize};
+use wgpu::{
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer,
+    BufferBinding, BufferBindingType, BufferUsages, CompareFunction, Device, Extent3d, FilterMode,
+    Queue, Sampler, SamplerBindingType, SamplerDescriptor, ShaderStages, Texture,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+    TextureView, TextureViewDescriptor, TextureViewDimension,
+    util::{BufferInitDescriptor, DeviceExt},
+};
+
+use crate::{
+    camera::Camera,
+    environment::{Environment, PREFILTERED_MIPS},
+    error::{Error, Result},
+};
+
+pub const MAX_LIGHTS: usize = 16;
+pub const MAX_CASCADES: usize = 4;
+pub const MAX_SPOT_SHADOWS: usize = 4;
+/// Most shadow map layers in use. The directional light cascades come first, then one
+/// layer per shadowed spot light.
+pub const SHADOW_LAYERS: usize = MAX_CASCADES + MAX_SPOT_SHADOWS;
+pub const SHADOW_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+// uniform buffers bound with a dynamic offset have to be aligned to this
+const SHADOW_VIEW_STRIDE: u64 = 256;
+
+const DIRECTIONAL: u32 = 0;
+const POINT: u32 = 1;
+const SPOT: u32 = 2;
+
+fn white() -> [f32; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+fn default_inner_angle() -> f32 {
+    30.0
+}
+
+fn default_outer_angle() -> f32 {
+    45.0
+}
+
+/// A light as described from JS, e.g. `{ type: "spot", position: [0, 4, 0], direction:
+/// [0, -1, 0], outerAngle: 30, castShadows: true }`. Directions point the way the light
+/// travels, `range` 0 means unlimited and spot angles are half angles in degrees.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Light {
+    #[serde(rename_all = "camelCase")]
+    Directional {
+        direction: [f32; 3],
+        #[serde(default = "white")]
+        color: [f32; 3],
+        #[serde(default = "one")]
+        intensity: f32,
+        /// Only the first shadowed directional light gets shadow cascades.
+        #[serde(default)]
+        cast_shadows: bool,
+    },
+    #[serde(rename_all = "camelCase")]
+    Point {
+        position: [f32; 3],
+        #[serde(default = "white")]
+        color: [f32; 3],
+        #[serde(default = "one")]
+        intensity: f32,
+        #[serde(default)]
+        range: f32,
+    },
+    #[serde(rename_all = "camelCase")]
+    Spot {
+        position: [f32; 3],
+        direction: [f32; 3],
+        #[serde(default = "white")]
+        color: [f32; 3],
+        #[serde(default = "one")]
+        intensity: f32,
+        #[serde(default)]
+        range: f32,
+        #[serde(default = "default_inner_angle")]
+        inner_angle: f32,
+        #[serde(default = "default_outer_angle")]
+        outer_angle: f32,
+        #[serde(default)]
+        cast_shadows: bool,
+    },
+}
+
+impl Light {
+    fn raw(&self, shadow_layer: Option<usize>) -> LightRaw {
+        let shadow_layer = shadow_layer.map_or(-1, |layer| layer as i32);
+        match *self {
+            Light::Directional {
+                direction,
+                color,
+                intensity,
+                ..
+            } => LightRaw {
+                direction: normalize(direction),
+                kind: DIRECTIONAL,
+                color,
+                intensity,
+                shadow_layer,
+                ..Default::default()
+            },
+            Light::Point {
+                position,
+                color,
+                intensity,
+                range,
+            } => LightRaw {
+                position,
+                range,
+                kind: POINT,
+                color,
+                intensity,
+                shadow_layer,
+                ..Default::default()
+            },
+            Light::Spot {
+                position,
+                direction,
+                color,
+                intensity,
+                range,
+                inner_angle,
+                outer_angle,
+                ..
+            } => LightRaw {
+                position,
+                range,
+                direction: normalize(direction),
+                kind: SPOT,
+                color,
+                intensity,
+                inner_cos: inner_angle.min(outer_angle).to_radians().cos(),
+                outer_cos: outer_angle.to_radians().cos(),
+                shadow_layer,
+                _padding: 0,
+            },
+        }
+    }
+}
+
+fn normalize(direction: [f32; 3]) -> [f32; 3] {
+    Vec3::from(direction).normalize_or(Vec3::NEG_Y).to_array()
+}
+
+/// Shadow map resolution and filtering, e.g. `{ mapSize: 1024, cascades: 2 }`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ShadowSettings {
+    /// Width and height of every shadow map layer.
+    pub map_size: u32,
+    /// Cascades of the directional light, at most `MAX_CASCADES`.
+    pub cascades: u32,
+    /// Distance from the camera the directional shadows reach.
+    pub distance: f32,
+    /// Subtracted from the depth compared against the shadow map.
+    pub depth_bias: f32,
+    /// The receiving point is moved this far along its normal before the lookup.
+    pub normal_bias: f32,
+    /// PCF kernel radius in texels, 0 for hard shadows.
+    pub pcf_radius: u32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            map_size: 2048,
+            cascades: 3,
+            distance: 50.0,
+            depth_bias: 0.0005,
+            normal_bias: 0.02,
+            pcf_radius: 1,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightRaw {
+    position: [f32; 3],
+    range: f32,
+    direction: [f32; 3],
+    kind: u32,
+    color: [f32; 3],
+    intensity: f32,
+    inner_cos: f32,
+    outer_cos: f32,
+    // first shadow map layer, -1 without shadows
+    shadow_layer: i32,
+    _padding: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightsUniform {
+    lights: [LightRaw; MAX_LIGHTS],
+    shadow_matrices: [[[f32; 4]; 4]; SHADOW_LAYERS],
+    // view space distance at which each cascade ends
+    cascade_splits: [f32; 4],
+    ambient: [f32; 3],
+    count: u32,
+    cascade_count: u32,
+    depth_bias: f32,
+    normal_bias: f32,
+    pcf_radius: u32,
+    environment_intensity: f32,
+    // roughness 1 lod of the prefiltered map
+    prefiltered_lod: f32,
+    _padding: [u32; 2],
+}
+
+/// Lights bound at group 3 along with the shadow maps, which are rendered from the
+/// light's view by the renderer before the scene passes, and the image based lighting
+/// of the environment.
+pub struct LightManager {
+    /// Removed lights leave a `None` so handles stay valid.
+    pub lights: Vec<Option<Light>>,
+    pub ambient: [f32; 3],
+    pub shadow_settings: ShadowSettings,
+    pub bind_group_layout: BindGroupLayout,
+    pub bind_group: BindGroup,
+    uniform_buffer: Buffer,
+    // a single texel until a light casts shadows, then as many layers as they use
+    shadow_maps: Texture,
+    shadow_view: TextureView,
+    /// One depth attachment per shadow map layer.
+    pub shadow_layer_views: Vec<TextureView>,
+    shadow_sampler: Sampler,
+    /// Also drawn as the skybox when set.
+    pub environment: Option<Environment>,
+    pub environment_intensity: f32,
+    // bound while there is no environment
+    empty_environment: Environment,
+    environment_sampler: Sampler,
+    /// Light view-projection of every layer, bound with a dynamic offset while rendering it.
+    pub shadow_view_layout: BindGroupLayout,
+    pub shadow_view_bind_group: BindGroup,
+    shadow_view_buffer: Buffer,
+    /// Layers rendered this frame with their dynamic offset into the shadow view buffer.
+    pub active_shadow_layers: Vec<(usize, u32)>,
+}
+
+impl LightManager {
+    /// Starts with a single directional light and a dim ambient term.
+    pub fn new(device: &Device) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Light Bind Group Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2Array,
+                        sample_type: TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Comparison),
+                    count: None,
+                },
+                // environment, irradiance and prefiltered cubes, then the BRDF LUT
+                environment_entry(3, TextureViewDimension::Cube),
+                environment_entry(4, TextureViewDimension::Cube),
+                environment_entry(5, TextureViewDimension::Cube),
+                environment_entry(6, TextureViewDimension::D2),
+                BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let shadow_view_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Shadow View Bind Group Layout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(64),
+                },
+                count: None,
+            }],
+        });
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light Uniform Buffer"),
+            size: std::mem::size_of::<LightsUniform>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let shadow_view_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Shadow View Buffer"),
+            contents: &vec![0; SHADOW_VIEW_STRIDE as usize * SHADOW_LAYERS],
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let shadow_view_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Shadow View Bind Group"),
+            layout: &shadow_view_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::Buffer(BufferBinding {
+                    buffer: &shadow_view_buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(64),
+                }),
+            }],
+        });
+        let shadow_sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Shadow Sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            compare: Some(CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let environment_sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Environment Sampler"),
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+        let empty_environment = Environment::empty(device);
+
+        let shadow_settings = ShadowSettings::default();
+        let (shadow_maps, shadow_view, shadow_layer_views) = Self::create_shadow_maps(device, 1, 1);
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            &uniform_buffer,
+            (&shadow_view, &shadow_sampler),
+            (&empty_environment, &environment_sampler),
+        );
+        info!("Light manager created successfully!");
+        Self {
+            // stands in for the fixed key light the PBR shader used to have
+            lights: vec![Some(Light::Directional {
+                direction: [-0.4, -1.0, -0.6],
+                color: white(),
+                intensity: 3.0,
+                cast_shadows: false,
+            })],
+            ambient: [0.03, 0.03, 0.03],
+            shadow_settings,
+            bind_group_layout,
+            bind_group,
+            uniform_buffer,
+            shadow_maps,
+            shadow_view,
+            shadow_layer_views,
+            shadow_sampler,
+            environment: None,
+            environment_intensity: 1.0,
+            empty_environment,
+            environment_sampler,
+            shadow_view_layout,
+            shadow_view_bind_group,
+            shadow_view_buffer,
+            active_shadow_layers: Vec::new(),
+        }
+    }
+
+    /// The same lights, shadow settings and environment on another device.
+    pub fn recreate(&self, device: &Device, queue: &Queue) -> Result<Self> {
+        let mut lights = Self::new(device);
+        lights.lights = self.lights.clone();
+        lights.ambient = self.ambient;
+        lights.environment_intensity = self.environment_intensity;
+        lights.set_shadow_settings(device, self.shadow_settings);
+        if let Some(environment) = &self.environment {
+            lights.set_environment(device, Some(environment.recreate(device, queue)?));
+        }
+        Ok(lights)
+    }
+
+    pub fn add_light(&mut self, light: Light) -> Result<usize> {
+        if self.lights.iter().flatten().count() >= MAX_LIGHTS {
+            return Err(Error::InvalidInput(format!(
+                "At most {MAX_LIGHTS} lights are supported"
+            )));
+        }
+        self.lights.push(Some(light));
+        Ok(self.lights.len() - 1)
+    }
+
+    pub fn light_mut(&mut self, handle: usize) -> Result<&mut Light> {
+        self.lights
+            .get_mut(handle)
+            .and_then(Option::as_mut)
+            .ok_or_else(|| Error::invalid_handle("light", handle))
+    }
+
+    pub fn remove_light(&mut self, handle: usize) -> Result<()> {
+        self.light_mut(handle)?;
+        self.lights[handle] = None;
+        Ok(())
+    }
+
+    /// The shadow maps are recreated with the new size by the next update that draws them.
+    pub fn set_shadow_settings(&mut self, device: &Device, settings: ShadowSettings) {
+        let settings = ShadowSettings {
+            map_size: settings
+                .map_size
+                .clamp(1, device.limits().max_texture_dimension_2d),
+            cascades: settings.cascades.clamp(1, MAX_CASCADES as u32),
+            ..settings
+        };
+        self.shadow_settings = settings;
+    }
+
+    /// Lights the scene with a baked environment, or with lights and ambient only for `None`.
+    pub fn set_environment(&mut self, device: &Device, environment: Option<Environment>) {
+        self.environment = environment;
+        self.update_bind_group(device);
+    }
+
+    /// Writes the lights and the shadow matrices for the current camera, and collects
+    /// the shadow layers the renderer has to draw this frame. The shadow maps grow to
+    /// the layers in use.
+    pub fn update(&mut self, device: &Device, queue: &Queue, camera: &Camera) {
+        let settings = self.shadow_settings;
+        // spot light layers follow the cascades of the first shadowed directional light
+        let cascade_layers = if self.lights.iter().flatten().take(MAX_LIGHTS).any(|light| {
+            matches!(
+                light,
+                Light::Directional {
+                    cast_shadows: true,
+                    ..
+                }
+            )
+        }) {
+            settings.cascades.clamp(1, MAX_CASCADES as u32) as usize
+        } else {
+            0
+        };
+        let mut uniform = LightsUniform {
+            lights: [LightRaw::default(); MAX_LIGHTS],
+            shadow_matrices: [Mat4::IDENTITY.to_cols_array_2d(); SHADOW_LAYERS],
+            cascade_splits: [0.0; 4],
+            ambient: self.ambient,
+            count: 0,
+            cascade_count: 0,
+            depth_bias: settings.depth_bias,
+            normal_bias: settings.normal_bias,
+            pcf_radius: settings.pcf_radius,
+            environment_intensity: self.environment_intensity,
+            prefiltered_lod: if self.environment.is_some() {
+                (PREFILTERED_MIPS - 1) as f32
+            } else {
+                0.0
+            },
+            _padding: [0; 2],
+        };
+        let mut spot_shadows = 0;
+        for light in self.lights.iter().flatten().take(MAX_LIGHTS) {
+            let shadow_layer = match *light {
+                Light::Directional {
+                    direction,
+                    cast_shadows: true,
+                    ..
+                } if uniform.cascade_count == 0 => {
+                    let (matrices, splits) =
+                        cascade_matrices(camera, Vec3::from(direction), &settings);
+                    for (i, matrix) in matrices.iter().enumerate() {
+                        uniform.shadow_matrices[i] = matrix.to_cols_array_2d();
+                    }
+                    uniform.cascade_splits = splits;
+                    uniform.cascade_count = matrices.len() as u32;
+                    Some(0)
+                }
+                Light::Spot {
+                    position,
+                    direction,
+                    range,
+                    outer_angle,
+                    cast_shadows: true,
+                    ..
+                } if spot_shadows < MAX_SPOT_SHADOWS => {
+                    let layer = cascade_layers + spot_shadows;
+                    spot_shadows += 1;
+                    uniform.shadow_matrices[layer] = spot_matrix(
+                        Vec3::from(position),
+                        Vec3::from(direction),
+                        range,
+                        outer_angle,
+                    )
+                    .to_cols_array_2d();
+                    Some(layer)
+                }
+                _ => None,
+            };
+            uniform.lights[uniform.count as usize] = light.raw(shadow_layer);
+            uniform.count += 1;
+        }
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+
+        self.reserve_shadow_layers(device, (cascade_layers + spot_shadows) as u32);
+        self.active_shadow_layers = (0..cascade_layers + spot_shadows)
+            .map(|layer| (layer, (layer as u64 * SHADOW_VIEW_STRIDE) as u32))
+            .collect();
+        for &(layer, offset) in &self.active_shadow_layers {
+            queue.write_buffer(
+                &self.shadow_view_buffer,
+                offset as u64,
+                bytemuck::cast_slice(&uniform.shadow_matrices[layer]),
+            );
+        }
+    }
+
+    /// Frees the buffers, shadow maps and environments. The manager can't be used
+    /// afterwards.
+    pub fn destroy(&self) {
+        self.uniform_buffer.destroy();
+        self.shadow_view_buffer.destroy();
+        self.shadow_maps.destroy();
+        if let Some(environment) = &self.environment {
+            environment.destroy();
+        }
+        self.empty_environment.destroy();
+    }
+
+    // keeps the maps while they are large enough, they are never shrunk
+    fn reserve_shadow_layers(&mut self, device: &Device, layers: u32) {
+        let size = self.shadow_settings.map_size;
+        if layers == 0
+            || (self.shadow_maps.width() == size
+                && self.shadow_maps.depth_or_array_layers() >= layers)
+        {
+            return;
+        }
+        let (shadow_maps, shadow_view, shadow_layer_views) =
+            Self::create_shadow_maps(device, size, layers);
+        self.shadow_maps.destroy();
+        self.shadow_maps = shadow_maps;
+        self.shadow_view = shadow_view;
+        self.shadow_layer_views = shadow_layer_views;
+        self.update_bind_group(device);
+    }
+
+    fn create_shadow_maps(
+        device: &Device,
+        size: u32,
+        layers: u32,
+    ) -> (Texture, TextureView, Vec<TextureView>) {
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("Shadow Maps"),
+            size: Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: layers,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: SHADOW_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let layer_views = (0..layers)
+            .map(|layer| {
+                texture.create_view(&TextureViewDescriptor {
+                    label: Some(&format!("Shadow Map Layer {layer}")),
+                    dimension: Some(TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        (texture, view, layer_views)
+    }
+
+    fn update_bind_group(&mut self, device: &Device) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.uniform_buffer,
+            (&self.shadow_view, &self.shadow_sampler),
+            (
+                self.environment.as_ref().unwrap_or(&self.empty_environment),
+                &self.environment_sampler,
+            ),
+        );
+    }
+
+    fn create_bind_group(
+        device: &Device,
+        layout: &BindGroupLayout,
+        uniform_buffer: &Buffer,
+        (shadow_view, shadow_sampler): (&TextureView, &Sampler),
+        (environment, environment_sampler): (&Environment, &Sampler),
+    ) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Light Bind Group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(shadow_view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Sampler(shadow_sampler),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::TextureView(&environment.cube_view),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: BindingResource::TextureView(&environment.irradiance_view),
+                },
+                BindGroupEntry {
+                    binding: 5,
+                    resource: BindingResource::TextureView(&environment.prefiltered_view),
+                },
+                BindGroupEntry {
+                    binding: 6,
+                    resource: BindingResource::TextureView(&environment.brdf_lut_view),
+                },
+                BindGroupEntry {
+                    binding: 7,
+                    resource: BindingResource::Sampler(environment_sampler),
+                },
+            ],
+        })
+    }
+}
+
+fn environment_entry(binding: u32, view_dimension: TextureViewDimension) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::FRAGMENT,
+        ty: BindingType::Texture {
+            multisampled: false,
+            view_dimension,
+            sample_type: TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+    }
+}
+
+/// Splits the camera frustum up to the shadow distance into cascades and fits an
+/// orthographic light projection around each. Returns the matrices together with
+/// the view space distance at which each cascade ends.
+fn cascade_matrices(
+    camera: &Camera,
+    direction: Vec3,
+    settings: &ShadowSettings,
+) -> (Vec<Mat4>, [f32; 4]) {
+    let direction = direction.normalize_or(Vec3::NEG_Y);
+    let (znear, zfar) = camera.projection.depth_range();
+    let shadow_far = zfar.min(znear + settings.distance);
+    let cascades = settings.cascades.clamp(1, MAX_CASCADES as u32) as usize;
+
+    // corners of the whole frustum in view space, near plane first
+    let inverse_projection = camera.projection_matrix().inverse();
+    let inverse_view = camera.view_matrix().inverse();
+    let corners: Vec<Vec3> = [0.0, 1.0]
+        .iter()
+        .flat_map(|&z| {
+            [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+                .map(|(x, y)| inverse_projection.project_point3(Vec3::new(x, y, z)))
+        })
+        .collect();
+
+    let mut splits = [0.0; 4];
+    let mut matrices = Vec::with_capacity(cascades);
+    let mut start = znear;
+    for (i, split) in splits.iter_mut().enumerate().take(cascades) {
+        // blend of logarithmic and uniform splits
+        let fraction = (i + 1) as f32 / cascades as f32;
+        let uniform = znear + (shadow_far - znear) * fraction;
+        // the logarithmic split needs a near plane in front of the camera
+        let end = if znear > 0.0 {
+            let logarithmic = znear * (shadow_far / znear).powf(fraction);
+            0.75 * logarithmic + 0.25 * uniform
+        } else {
+            uniform
+        };
+        *split = end;
+
+        // frustum edges are straight lines, so lerping by depth works for both projections
+        let t0 = (start - znear) / (zfar - znear);
+        let t1 = (end - znear) / (zfar - znear);
+        let slice: Vec<Vec3> = (0..4)
+            .flat_map(|corner| {
+                [t0, t1].map(|t| {
+                    inverse_view.transform_point3(corners[corner].lerp(corners[corner + 4], t))
+                })
+            })
+            .collect();
+        let center = slice.iter().sum::<Vec3>() / slice.len() as f32;
+        // a bounding sphere keeps the projection size constant while the camera rotates
+        let radius = slice
+            .iter()
+            .map(|corner| corner.distance(center))
+            .fold(0.0, f32::max);
+        let radius = (radius * 16.0).ceil() / 16.0;
+
+        let up = if direction.y.abs() > 0.99 {
+            Vec3::Z
+        } else {
+            Vec3::Y
+        };
+        let rotation = Mat4::look_to_rh(Vec3::ZERO, direction, up);
+        // snap to whole texels so the shadow edges don't shimmer when the camera moves
+        let texel = 2.0 * radius / settings.map_size as f32;
+        let light_center = (rotation * center.extend(1.0)).xyz();
+        let snapped_x = (light_center.x / texel).floor() * texel;
+        let snapped_y = (light_center.y / texel).floor() * texel;
+        // extend towards the light so casters outside the slice are still drawn
+        let projection = Mat4::orthographic_rh(
+            snapped_x - radius,
+            snapped_x + radius,
+            snapped_y - radius,
+            snapped_y + radius,
+            -light_center.z - radius - settings.distance,
+            -light_center.z + radius,
+        );
+        matrices.push(projection * rotation);
+        start = end;
+    }
+    (matrices, splits)
+}
+
+fn spot_matrix(position: Vec3, direction: Vec3, range: f32, outer_angle: f32) -> Mat4 {
+    let direction = direction.normalize_or(Vec3::NEG_Y);
+    let up = if direction.y.abs() > 0.99 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    };
+    let far = if range > 0.0 { range } else { 100.0 };
+    let fovy = (2.0 * outer_angle.to_radians()).clamp(0.01, 3.1);
+    Mat4::perspective_rh(fovy, 1.0, 0.05, far) * Mat4::look_to_rh(position, direction, up)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Projection;
+
+    #[test]
+    fn cascades_without_near_plane() {
+        let mut camera = Camera::new(800, 600);
+        camera.eye = Vec3::new(0.0, 2.0, 10.0);
+        camera.projection = Projection::Orthographic {
+            width: None,
+            height: 10.0,
+            znear: 0.0,
+            zfar: 100.0,
+        };
+        let settings = ShadowSettings {
+            cascades: 3,
+            ..Default::default()
+        };
+        let (matrices, splits) = cascade_matrices(&camera, Vec3::NEG_Y, &settings);
+        assert_eq!(matrices.len(), 3);
+        assert!(splits.iter().all(|split| split.is_finite()));
+        assert!(splits[..3].is_sorted() && splits[0] > 0.0);
+        assert!(matrices.iter().all(|matrix| matrix.is_finite()));
+    }
+}
@@ -0,0 +1,216 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        LazyLock, Once, RwLock,
+        atomic::{AtomicU32, AtomicU64, Ordering},
+    },
+};
+
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+
+use crate::profiler;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Off => LevelFilter::Off,
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Trace => LevelFilter::Trace,
+        }
+    }
+}
+
+/// What gets logged to the browser console, e.g.
+/// `{ level: "warn", modules: { renderer: "debug", wgpu_core: "error" } }`. Modules are
+/// matched by path, with or without the `wasm_core::` prefix, and the longest match
+/// overrides `level`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LogConfig {
+    pub level: LogLevel,
+    pub modules: HashMap<String, LogLevel>,
+}
+
+impl LogConfig {
+    fn level(&self, target: &str) -> LevelFilter {
+        let local = target.strip_prefix("wasm_core::").unwrap_or(target);
+        // length of the matched part of the full path, so `renderer` beats `wasm_core`
+        let matched = |module: &str| {
+            [target, local].iter().find_map(|path| {
+                path.strip_prefix(module)
+                    .filter(|rest| rest.is_empty() || rest.starts_with("::"))
+                    .map(|rest| target.len() - rest.len())
+            })
+        };
+        self.modules
+            .iter()
+            // on a tie the module spelled out with the prefix wins
+            .filter_map(|(module, &level)| Some(((matched(module)?, module.len()), level)))
+            .max_by_key(|&(rank, _)| rank)
+            .map_or(self.level, |(_, level)| level)
+            .into()
+    }
+
+    // the most verbose level any module logs at
+    fn max_level(&self) -> LevelFilter {
+        self.modules
+            .values()
+            .map(|&level| LevelFilter::from(level))
+            .fold(self.level.into(), Ord::max)
+    }
+}
+
+static CONFIG: LazyLock<RwLock<LogConfig>> = LazyLock::new(RwLock::default);
+static INIT: Once = Once::new();
+
+struct ConsoleLogger;
+
+impl Log for ConsoleLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let config = CONFIG.read().expect("log config poisoned");
+        metadata.level() <= config.level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            console_log::log(record);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the console logger, once for all `App`s. A logger set up by someone else
+/// before is kept.
+pub fn init() {
+    INIT.call_once(|| {
+        if log::set_logger(&ConsoleLogger).is_ok() {
+            let config = CONFIG.read().expect("log config poisoned");
+            log::set_max_level(config.max_level());
+        }
+    });
+}
+
+/// Replaces the log config, installing the console logger if needed.
+pub fn configure(config: LogConfig) {
+    init();
+    log::set_max_level(config.max_level());
+    *CONFIG.write().expect("log config poisoned") = config;
+}
+
+/// Lets a message through at most once per interval, counting the ones held back.
+pub struct RateLimiter {
+    // bits of the `profiler::now` time the last message got through
+    last: AtomicU64,
+    skipped: AtomicU32,
+}
+
+impl RateLimiter {
+    pub const fn new() -> Self {
+        Self {
+            last: AtomicU64::new(f64::NEG_INFINITY.to_bits()),
+            skipped: AtomicU32::new(0),
+        }
+    }
+
+    /// The number of messages held back since the last one, when this one may pass.
+    pub fn allow(&self, interval: f64) -> Option<u32> {
+        let now = profiler::now();
+        if now - f64::from_bits(self.last.load(Ordering::Relaxed)) < interval {
+            self.skipped.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        self.last.store(now.to_bits(), Ordering::Relaxed);
+        Some(self.skipped.swap(0, Ordering::Relaxed))
+    }
+}
+
+/// Logs only the first time the call site is reached, for messages on hot paths that
+/// are worth seeing once.
+macro_rules! log_once {
+    ($level:expr, $($arg:tt)+) => {{
+        static LOGGED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+        if !LOGGED.swap(true, std::sync::atomic::Ordering::Relaxed) {
+            log::log!($level, $($arg)+);
+        }
+    }};
+}
+
+/// Logs at most once per `interval` milliseconds from the call site, noting how many
+/// messages were held back in between.
+macro_rules! log_every {
+    ($interval:expr, $level:expr, $($arg:tt)+) => {{
+        static LIMITER: $crate::logging::RateLimiter = $crate::logging::RateLimiter::new();
+        match LIMITER.allow($interval) {
+            Some(0) => log::log!($level, $($arg)+),
+            Some(skipped) => {
+                log::log!($level, "{} ({skipped} more since)", format_args!($($arg)+))
+            }
+            None => {}
+        }
+    }};
+}
+
+pub(crate) use {log_every, log_once};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let config: LogConfig = serde_json::from_str(
+            r#"{ "level": "warn", "modules": { "renderer": "debug", "wgpu_core": "off" } }"#,
+        )
+        .unwrap();
+        assert_eq!(config.level, LogLevel::Warn);
+        assert_eq!(config.modules["renderer"], LogLevel::Debug);
+        assert_eq!(config.modules["wgpu_core"], LogLevel::Off);
+
+        let config: LogConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config, LogConfig::default());
+        assert_eq!(config.level, LogLevel::Info);
+        assert!(serde_json::from_str::<LogConfig>(r#"{ "level": "verbose" }"#).is_err());
+    }
+
+    #[test]
+    fn module_levels() {
+        let config = LogConfig {
+            level: LogLevel::Warn,
+            modules: HashMap::from([
+                ("wasm_core".to_string(), LogLevel::Info),
+                ("renderer".to_string(), LogLevel::Debug),
+                ("wasm_core::renderer::passes".to_string(), LogLevel::Trace),
+                ("wgpu_core".to_string(), LogLevel::Error),
+            ]),
+        };
+        assert_eq!(config.level("wasm_core::light"), LevelFilter::Info);
+        assert_eq!(config.level("wasm_core::renderer"), LevelFilter::Debug);
+        assert_eq!(config.level("renderer::cache"), LevelFilter::Debug);
+        assert_eq!(
+            config.level("wasm_core::renderer::passes"),
+            LevelFilter::Trace
+        );
+        assert_eq!(config.level("wgpu_core::device"), LevelFilter::Error);
+        // only whole path segments match
+        assert_eq!(config.level("wgpu_core_extra"), LevelFilter::Warn);
+        assert_eq!(config.level("naga"), LevelFilter::Warn);
+        assert_eq!(config.max_level(), LevelFilter::Trace);
+    }
+}
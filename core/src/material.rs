@@ -0,0 +1,513 @@
+use std::ops::Range;
+
+use image::GenericImageView;
+use log::info;
+use serde::{Deserialize, Serialize};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendComponent, BlendFactor,
+    BlendOperation, BlendState, Buffer, BufferBindingType, BufferUsages, Device, Extent3d, Queue,
+    SamplerBindingType, ShaderStages, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension,
+    util::{BufferInitDescriptor, DeviceExt},
+};
+
+use crate::{
+    error::{Error, Result},
+    texture_manager::SamplerSettings,
+};
+
+/// Scalar factors of a glTF style metallic-roughness material, multiplied with
+/// the material textures.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MaterialFactors {
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+    pub emissive: [f32; 3],
+}
+
+impl Default for MaterialFactors {
+    fn default() -> Self {
+        Self {
+            base_color: [1.0, 1.0, 1.0, 1.0],
+            metallic: 1.0,
+            roughness: 1.0,
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            emissive: [0.0, 0.0, 0.0],
+        }
+    }
+}
+
+/// How the fragments of a material combine with what is already drawn. Anything but
+/// `Opaque` is drawn after the opaque geometry, back to front, without writing depth.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BlendMode {
+    #[default]
+    Opaque,
+    /// Straight alpha, as in glTF's `BLEND` alpha mode.
+    Alpha,
+    /// Alpha with the color already multiplied by it.
+    Premultiplied,
+    /// Adds the color weighted by alpha, for glows and particles.
+    Additive,
+    /// Multiplies the color behind, for tints and shadows.
+    Multiply,
+    /// Inverse of multiply, brightening what is behind.
+    Screen,
+}
+
+impl BlendMode {
+    pub fn is_transparent(self) -> bool {
+        self != BlendMode::Opaque
+    }
+
+    /// The blend state of pipelines drawing with this mode, `None` to replace the color.
+    pub fn blend_state(self) -> Option<BlendState> {
+        let color = |src_factor, dst_factor| BlendState {
+            color: BlendComponent {
+                src_factor,
+                dst_factor,
+                operation: BlendOperation::Add,
+            },
+            alpha: BlendComponent::OVER,
+        };
+        match self {
+            BlendMode::Opaque => None,
+            BlendMode::Alpha => Some(BlendState::ALPHA_BLENDING),
+            BlendMode::Premultiplied => Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+            BlendMode::Additive => Some(color(BlendFactor::SrcAlpha, BlendFactor::One)),
+            BlendMode::Multiply => Some(color(BlendFactor::Dst, BlendFactor::Zero)),
+            BlendMode::Screen => Some(color(BlendFactor::OneMinusDst, BlendFactor::One)),
+        }
+    }
+}
+
+/// Decoded RGBA8 pixels of a material texture.
+#[derive(Clone, Debug)]
+pub struct MaterialImage {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+impl MaterialImage {
+    /// Decodes a PNG or JPEG file.
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let img = image::load_from_memory(data)
+            .map_err(|e| Error::TextureDecode(format!("Failed to load image: {e}")))?;
+        let (width, height) = img.dimensions();
+        Ok(Self {
+            width,
+            height,
+            rgba: img.to_rgba8().into_raw(),
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MaterialTexture {
+    pub image: MaterialImage,
+    /// 0 samples with `tex_pos`, 1 with `tex_pos_1`.
+    pub uv_set: u32,
+}
+
+impl MaterialTexture {
+    pub fn new(image: MaterialImage) -> Self {
+        Self { image, uv_set: 0 }
+    }
+}
+
+/// CPU side of a material, ready to be uploaded by `MaterialManager::add_material`.
+#[derive(Clone, Debug, Default)]
+pub struct MaterialData {
+    pub name: Option<String>,
+    pub factors: MaterialFactors,
+    /// RGB is the base color in sRGB, A the opacity.
+    pub base_color_texture: Option<MaterialTexture>,
+    /// Roughness in G and metalness in B, as in glTF.
+    pub metallic_roughness_texture: Option<MaterialTexture>,
+    /// Tangent space normal map.
+    pub normal_texture: Option<MaterialTexture>,
+    /// Ambient occlusion in R.
+    pub occlusion_texture: Option<MaterialTexture>,
+    /// Emitted color in sRGB.
+    pub emissive_texture: Option<MaterialTexture>,
+    /// Shared by all textures of the material.
+    pub sampler: SamplerSettings,
+    pub blend_mode: BlendMode,
+}
+
+impl MaterialData {
+    // in binding order, with whether the texture holds sRGB colors
+    fn textures(&self) -> [(&Option<MaterialTexture>, bool); 5] {
+        [
+            (&self.base_color_texture, true),
+            (&self.metallic_roughness_texture, false),
+            (&self.normal_texture, false),
+            (&self.occlusion_texture, false),
+            (&self.emissive_texture, true),
+        ]
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct MaterialUniform {
+    base_color: [f32; 4],
+    emissive: [f32; 3],
+    normal_scale: f32,
+    metallic: f32,
+    roughness: f32,
+    occlusion_strength: f32,
+    // bit i is set when texture i samples with the second UV set
+    uv_sets: u32,
+}
+
+impl MaterialUniform {
+    fn new(factors: &MaterialFactors, uv_sets: u32) -> Self {
+        Self {
+            base_color: factors.base_color,
+            emissive: factors.emissive,
+            normal_scale: factors.normal_scale,
+            metallic: factors.metallic,
+            roughness: factors.roughness,
+            occlusion_strength: factors.occlusion_strength,
+            uv_sets,
+        }
+    }
+}
+
+pub struct Material {
+    uv_sets: u32,
+    pub blend_mode: BlendMode,
+    // uploaded again when the device is lost
+    data: MaterialData,
+    pub uniform_buffer: Buffer,
+    /// Created once with the material and reused for every draw.
+    pub bind_group: BindGroup,
+    // the material's own images, not the shared defaults
+    textures: Vec<Texture>,
+}
+
+impl Material {
+    fn destroy(&self) {
+        self.uniform_buffer.destroy();
+        for texture in &self.textures {
+            texture.destroy();
+        }
+    }
+}
+
+/// Owns the materials bound at group 2. Textures a material doesn't have are
+/// replaced by 1x1 defaults that leave the factors unchanged.
+pub struct MaterialManager {
+    pub bind_group_layout: BindGroupLayout,
+    /// By handle, `None` for removed materials so later handles stay valid.
+    pub materials: Vec<Option<Material>>,
+    /// Used by draws whose node and mesh have no material.
+    pub default_material: Material,
+    white_view: TextureView,
+    flat_normal_view: TextureView,
+    // behind the default views, for `destroy`
+    default_textures: [Texture; 2],
+}
+
+impl MaterialManager {
+    pub fn new(device: &Device, queue: &Queue) -> Self {
+        let texture_entry = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                multisampled: false,
+                view_dimension: TextureViewDimension::D2,
+                sample_type: TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Material Bind Group Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                texture_entry(2),
+                texture_entry(3),
+                texture_entry(4),
+                texture_entry(5),
+                texture_entry(6),
+            ],
+        });
+
+        let white = MaterialImage {
+            width: 1,
+            height: 1,
+            rgba: vec![255, 255, 255, 255],
+        };
+        let flat_normal = MaterialImage {
+            width: 1,
+            height: 1,
+            rgba: vec![128, 128, 255, 255],
+        };
+        let default_textures = [white, flat_normal]
+            .map(|image| create_texture(device, queue, &image, TextureFormat::Rgba8Unorm));
+        let [white_view, flat_normal_view] = default_textures
+            .each_ref()
+            .map(|texture| texture.create_view(&TextureViewDescriptor::default()));
+
+        let default_material = create_material(
+            device,
+            queue,
+            &bind_group_layout,
+            [&white_view, &flat_normal_view],
+            &MaterialData {
+                name: Some("Default".to_string()),
+                // a plain dielectric
+                factors: MaterialFactors {
+                    metallic: 0.0,
+                    roughness: 0.5,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        info!("Material manager created successfully!");
+        Self {
+            bind_group_layout,
+            materials: Vec::new(),
+            default_material,
+            white_view,
+            flat_normal_view,
+            default_textures,
+        }
+    }
+
+    /// Frees the uniform buffers and textures of every material. The manager can't be
+    /// used afterwards.
+    pub fn destroy(&mut self) {
+        for material in self.materials.drain(..).flatten() {
+            material.destroy();
+        }
+        self.default_material.destroy();
+        for texture in &self.default_textures {
+            texture.destroy();
+        }
+    }
+
+    /// Uploads the same materials, with their current factors and blend modes, on
+    /// another device.
+    pub fn recreate(&self, device: &Device, queue: &Queue) -> Self {
+        let mut materials = Self::new(device, queue);
+        for material in &self.materials {
+            let Some(material) = material else {
+                materials.materials.push(None);
+                continue;
+            };
+            let data = MaterialData {
+                blend_mode: material.blend_mode,
+                ..material.data.clone()
+            };
+            materials.add_material(device, queue, &data);
+        }
+        materials
+    }
+
+    pub fn add_material(&mut self, device: &Device, queue: &Queue, data: &MaterialData) -> usize {
+        let material = create_material(
+            device,
+            queue,
+            &self.bind_group_layout,
+            [&self.white_view, &self.flat_normal_view],
+            data,
+        );
+        self.materials.push(Some(material));
+        self.materials.len() - 1
+    }
+
+    /// The material with the given handle, the default material for `None`.
+    pub fn get(&self, material: Option<usize>) -> Result<&Material> {
+        let Some(material) = material else {
+            return Ok(&self.default_material);
+        };
+        self.materials
+            .get(material)
+            .and_then(Option::as_ref)
+            .ok_or_else(|| Error::invalid_handle("material", material))
+    }
+
+    fn get_mut(&mut self, material: usize) -> Result<&mut Material> {
+        self.materials
+            .get_mut(material)
+            .and_then(Option::as_mut)
+            .ok_or_else(|| Error::invalid_handle("material", material))
+    }
+
+    /// Frees the materials with the given handles, which become invalid.
+    pub fn remove_materials(&mut self, materials: Range<usize>) {
+        for material in self.materials[materials]
+            .iter_mut()
+            .filter_map(Option::take)
+        {
+            material.destroy();
+        }
+    }
+
+    pub fn set_factors(
+        &mut self,
+        queue: &Queue,
+        material: usize,
+        factors: MaterialFactors,
+    ) -> Result<()> {
+        let material = self.get_mut(material)?;
+        queue.write_buffer(
+            &material.uniform_buffer,
+            0,
+            bytemuck::bytes_of(&MaterialUniform::new(&factors, material.uv_sets)),
+        );
+        material.data.factors = factors;
+        Ok(())
+    }
+
+    pub fn set_blend_mode(&mut self, material: usize, blend_mode: BlendMode) -> Result<()> {
+        self.get_mut(material)?.blend_mode = blend_mode;
+        Ok(())
+    }
+
+    /// The blend modes in use, each once.
+    pub fn blend_modes(&self) -> Vec<BlendMode> {
+        let mut blend_modes = vec![self.default_material.blend_mode];
+        for material in self.materials.iter().flatten() {
+            if !blend_modes.contains(&material.blend_mode) {
+                blend_modes.push(material.blend_mode);
+            }
+        }
+        blend_modes
+    }
+}
+
+// `default_views` are the white and flat normal textures used for missing textures
+fn create_material(
+    device: &Device,
+    queue: &Queue,
+    layout: &BindGroupLayout,
+    default_views: [&TextureView; 2],
+    data: &MaterialData,
+) -> Material {
+    let label = data.name.as_deref().unwrap_or("Material");
+    let textures = data.textures();
+    let uv_sets = textures
+        .iter()
+        .enumerate()
+        .filter(|(_, (texture, _))| texture.as_ref().is_some_and(|texture| texture.uv_set == 1))
+        .fold(0, |uv_sets, (i, _)| uv_sets | 1 << i);
+    let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some(&format!("{label} Uniform Buffer")),
+        contents: bytemuck::bytes_of(&MaterialUniform::new(&data.factors, uv_sets)),
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+    });
+    let sampler = data
+        .sampler
+        .create_sampler(device, &format!("{label} Sampler"));
+    let mut owned = Vec::new();
+    let views: Vec<TextureView> = textures
+        .iter()
+        .enumerate()
+        .map(|(i, (texture, srgb))| match texture {
+            Some(texture) => {
+                let format = if *srgb {
+                    TextureFormat::Rgba8UnormSrgb
+                } else {
+                    TextureFormat::Rgba8Unorm
+                };
+                let texture = create_texture(device, queue, &texture.image, format);
+                let view = texture.create_view(&TextureViewDescriptor::default());
+                owned.push(texture);
+                view
+            }
+            // normal map slot
+            None if i == 2 => default_views[1].clone(),
+            None => default_views[0].clone(),
+        })
+        .collect();
+
+    let mut entries = vec![
+        BindGroupEntry {
+            binding: 0,
+            resource: uniform_buffer.as_entire_binding(),
+        },
+        BindGroupEntry {
+            binding: 1,
+            resource: BindingResource::Sampler(&sampler),
+        },
+    ];
+    entries.extend(views.iter().enumerate().map(|(i, view)| BindGroupEntry {
+        binding: 2 + i as u32,
+        resource: BindingResource::TextureView(view),
+    }));
+    let bind_group = device.create_bind_group(&BindGroupDescriptor {
+        label: Some(&format!("{label} Bind Group")),
+        layout,
+        entries: &entries,
+    });
+
+    Material {
+        uv_sets,
+        blend_mode: data.blend_mode,
+        data: data.clone(),
+        uniform_buffer,
+        bind_group,
+        textures: owned,
+    }
+}
+
+fn create_texture(
+    device: &Device,
+    queue: &Queue,
+    image: &MaterialImage,
+    format: TextureFormat,
+) -> Texture {
+    let size = Extent3d {
+        width: image.width,
+        height: image.height,
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&TextureDescriptor {
+        label: Some("Material Texture"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    queue.write_texture(
+        texture.as_image_copy(),
+        &image.rgba,
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * image.width),
+            rows_per_image: Some(image.height),
+        },
+        size,
+    );
+    texture
+}
@@ -0,0 +1,160 @@
+use glam::{Vec2, Vec3};
+
+use crate::{
+    buffer_manager::Vertex,
+    error::{Error, Result},
+};
+
+/// CPU-side mesh data, ready to be uploaded by `BufferManager::add_mesh`.
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub name: Option<String>,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    // index into the materials of the file the mesh was loaded from, turned into a
+    // material handle before the mesh is added to the buffer manager
+    pub material: Option<usize>,
+}
+
+impl MeshData {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Self {
+        Self {
+            name: None,
+            vertices,
+            indices,
+            material: None,
+        }
+    }
+
+    /// Fullscreen quad used by the default shader.
+    pub fn quad() -> Self {
+        let vertices = vec![
+            Vertex {
+                pos: [-1.0, -1.0, 0.0],
+                color: [1.0, 0.0, 0.0],
+                tex_pos: [0.0, 1.0],
+                ..Default::default()
+            },
+            Vertex {
+                pos: [-1.0, 1.0, 0.0],
+                color: [0.0, 1.0, 0.0],
+                tex_pos: [0.0, 0.0],
+                ..Default::default()
+            },
+            Vertex {
+                pos: [1.0, 1.0, 0.0],
+                color: [0.0, 0.0, 1.0],
+                tex_pos: [1.0, 0.0],
+                ..Default::default()
+            },
+            Vertex {
+                pos: [1.0, -1.0, 0.0],
+                color: [1.0, 1.0, 1.0],
+                tex_pos: [1.0, 1.0],
+                ..Default::default()
+            },
+        ];
+        let indices = vec![0, 1, 2, 0, 2, 3];
+        let mut mesh = Self::new(vertices, indices);
+        for vertex in &mut mesh.vertices {
+            vertex.normal = [0.0, 0.0, 1.0];
+            vertex.tangent = [1.0, 0.0, 0.0, 1.0];
+        }
+        mesh.name = Some("Quad".to_string());
+        mesh
+    }
+
+    /// Appends the vertices and triangles of `other`, keeping this mesh's name and material.
+    pub fn append(&mut self, other: MeshData) {
+        let offset = self.vertices.len() as u32;
+        self.vertices.extend(other.vertices);
+        self.indices
+            .extend(other.indices.into_iter().map(|index| index + offset));
+    }
+
+    /// Whether there is no triangle to draw.
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty() || self.indices.len() < 3
+    }
+
+    /// Fails if an index points past the vertices, as it can in a malformed file.
+    pub fn check_indices(&self) -> Result<()> {
+        match self
+            .indices
+            .iter()
+            .find(|&&index| index as usize >= self.vertices.len())
+        {
+            Some(index) => Err(Error::Asset(format!(
+                "Mesh {} has index {index} but only {} vertices",
+                self.name.as_deref().unwrap_or("<unnamed>"),
+                self.vertices.len()
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Smooth vertex normals, area weighted by the triangles sharing each vertex.
+    pub fn compute_normals(&mut self) {
+        let mut normals = vec![Vec3::ZERO; self.vertices.len()];
+        for [a, b, c] in self.triangles() {
+            let pa = Vec3::from(self.vertices[a].pos);
+            let pb = Vec3::from(self.vertices[b].pos);
+            let pc = Vec3::from(self.vertices[c].pos);
+            // not normalized, so bigger triangles weigh more
+            let face_normal = (pb - pa).cross(pc - pa);
+            normals[a] += face_normal;
+            normals[b] += face_normal;
+            normals[c] += face_normal;
+        }
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            vertex.normal = normal.normalize_or(Vec3::Z).to_array();
+        }
+    }
+
+    /// Per-vertex tangents from positions, normals and the first UV set.
+    /// `w` holds the handedness of the bitangent, as in glTF.
+    pub fn compute_tangents(&mut self) {
+        let mut tangents = vec![Vec3::ZERO; self.vertices.len()];
+        let mut bitangents = vec![Vec3::ZERO; self.vertices.len()];
+        for [a, b, c] in self.triangles() {
+            let (va, vb, vc) = (&self.vertices[a], &self.vertices[b], &self.vertices[c]);
+            let edge_1 = Vec3::from(vb.pos) - Vec3::from(va.pos);
+            let edge_2 = Vec3::from(vc.pos) - Vec3::from(va.pos);
+            let delta_uv_1 = Vec2::from(vb.tex_pos) - Vec2::from(va.tex_pos);
+            let delta_uv_2 = Vec2::from(vc.tex_pos) - Vec2::from(va.tex_pos);
+            let det = delta_uv_1.perp_dot(delta_uv_2);
+            if det.abs() <= f32::EPSILON {
+                continue;
+            }
+            let r = 1.0 / det;
+            let tangent = (edge_1 * delta_uv_2.y - edge_2 * delta_uv_1.y) * r;
+            let bitangent = (edge_2 * delta_uv_1.x - edge_1 * delta_uv_2.x) * r;
+            for index in [a, b, c] {
+                tangents[index] += tangent;
+                bitangents[index] += bitangent;
+            }
+        }
+        for (i, vertex) in self.vertices.iter_mut().enumerate() {
+            let normal = Vec3::from(vertex.normal);
+            // Gram-Schmidt orthogonalize against the normal
+            let tangent = (tangents[i] - normal * normal.dot(tangents[i]))
+                .try_normalize()
+                .unwrap_or_else(|| normal.any_orthonormal_vector());
+            let handedness = if normal.cross(tangent).dot(bitangents[i]) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            vertex.tangent = tangent.extend(handedness).to_array();
+        }
+    }
+
+    /// Vertex indices of each triangle, skipping those with an index out of range.
+    fn triangles(&self) -> impl Iterator<Item = [usize; 3]> + use<'_> {
+        let count = self.vertices.len();
+        self.indices
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]].map(|i| i as usize))
+            .filter(move |triangle| triangle.iter().all(|&i| i < count))
+    }
+}
@@ -0,0 +1,504 @@
+use std::io::{BufReader, Cursor};
+
+use glam::{Mat3, Mat4, Vec3};
+use log::info;
+
+use crate::{
+    buffer_manager::Vertex,
+    error::{Error, Result},
+    material::{BlendMode, MaterialData, MaterialFactors, MaterialImage, MaterialTexture},
+    mesh::MeshData,
+    texture_manager::SamplerSettings,
+};
+
+const DEFAULT_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MeshFormat {
+    Obj,
+    Gltf,
+}
+
+impl MeshFormat {
+    /// Accepts a file extension or a name like `"gltf"`, with or without the leading dot.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension
+            .trim_start_matches('.')
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "obj" => Some(Self::Obj),
+            "gltf" | "glb" => Some(Self::Gltf),
+            _ => None,
+        }
+    }
+}
+
+/// Meshes of a file together with its materials. `MeshData::material` indexes `materials`.
+#[derive(Clone, Debug, Default)]
+pub struct ModelData {
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<MaterialData>,
+}
+
+pub fn load_mesh(
+    format: MeshFormat,
+    data: &[u8],
+    material_data: Option<&[u8]>,
+) -> Result<ModelData> {
+    let model = match format {
+        MeshFormat::Obj => load_obj(data, material_data)?,
+        MeshFormat::Gltf => load_gltf(data)?,
+    };
+    if let Some(mesh) = model.meshes.iter().find(|mesh| mesh.is_empty()) {
+        return Err(Error::Asset(format!(
+            "Mesh {} has no triangles",
+            mesh.name.as_deref().unwrap_or("<unnamed>")
+        )));
+    }
+    info!(
+        "Loaded {} mesh(es) and {} material(s) as {:?}",
+        model.meshes.len(),
+        model.materials.len(),
+        format
+    );
+    Ok(model)
+}
+
+/// Loads every model of an OBJ file. Materials are only resolved when the
+/// contents of the referenced MTL file are supplied, and their texture maps
+/// are ignored since only the MTL contents are available.
+pub fn load_obj(data: &[u8], material_data: Option<&[u8]>) -> Result<ModelData> {
+    let mut reader = BufReader::new(Cursor::new(data));
+    let (models, materials) =
+        tobj::load_obj_buf(
+            &mut reader,
+            &tobj::GPU_LOAD_OPTIONS,
+            |_| match material_data {
+                Some(material_data) => {
+                    tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(material_data)))
+                }
+                None => Err(tobj::LoadError::OpenFileFailed),
+            },
+        )
+        .map_err(|err| Error::Asset(format!("Failed to parse OBJ: {err}")))?;
+    // a missing material library is not fatal, the meshes just stay untinted
+    let materials = materials.unwrap_or_default();
+
+    let meshes = models
+        .into_iter()
+        .map(|model| {
+            let mesh = model.mesh;
+            let vertices = (0..mesh.positions.len() / 3)
+                .map(|i| Vertex {
+                    pos: read_array(&mesh.positions, i),
+                    color: if mesh.vertex_color.is_empty() {
+                        DEFAULT_COLOR
+                    } else {
+                        read_array(&mesh.vertex_color, i)
+                    },
+                    // OBJ puts the UV origin at the bottom left
+                    tex_pos: if mesh.texcoords.is_empty() {
+                        [0.0, 0.0]
+                    } else {
+                        let [u, v] = read_array(&mesh.texcoords, i);
+                        [u, 1.0 - v]
+                    },
+                    normal: if mesh.normals.is_empty() {
+                        [0.0, 0.0, 0.0]
+                    } else {
+                        read_array(&mesh.normals, i)
+                    },
+                    ..Default::default()
+                })
+                .collect();
+            let mut mesh_data = MeshData::new(vertices, mesh.indices);
+            mesh_data.name = Some(model.name);
+            mesh_data.material = mesh.material_id;
+            mesh_data.check_indices()?;
+            if mesh.normals.is_empty() {
+                mesh_data.compute_normals();
+            }
+            mesh_data.compute_tangents();
+            Ok(mesh_data)
+        })
+        .collect::<Result<_>>()?;
+    let materials = materials
+        .iter()
+        .map(|material| {
+            let [r, g, b] = material.diffuse.unwrap_or(DEFAULT_COLOR);
+            let opacity = material.dissolve.unwrap_or(1.0);
+            MaterialData {
+                name: Some(material.name.clone()),
+                factors: MaterialFactors {
+                    base_color: [r, g, b, opacity],
+                    metallic: 0.0,
+                    // the usual Blinn-Phong exponent to roughness mapping
+                    roughness: material
+                        .shininess
+                        .map_or(1.0, |shininess| (2.0 / (shininess.max(0.0) + 2.0)).sqrt()),
+                    ..Default::default()
+                },
+                blend_mode: if opacity < 1.0 {
+                    BlendMode::Alpha
+                } else {
+                    BlendMode::Opaque
+                },
+                ..Default::default()
+            }
+        })
+        .collect();
+    Ok(ModelData { meshes, materials })
+}
+
+/// Loads every primitive of a glTF 2.0 file (`.gltf` with embedded buffers or `.glb`)
+/// and its metallic-roughness materials. Node transforms of the default scene are
+/// baked into the vertices. Buffers and images referenced by external URIs are rejected,
+/// since only the file's own bytes are available.
+pub fn load_gltf(data: &[u8]) -> Result<ModelData> {
+    let (document, buffers, images) = gltf::import_slice(data).map_err(|err| match err {
+        gltf::Error::ExternalReferenceInSliceImport => Error::Asset(
+            "glTF references an external file, only .glb files and .gltf files with \
+             embedded data URIs are supported"
+                .to_string(),
+        ),
+        err => Error::Asset(format!("Failed to parse glTF: {err}")),
+    })?;
+
+    let mut meshes = Vec::new();
+    match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => {
+            for node in scene.nodes() {
+                load_gltf_node(&node, Mat4::IDENTITY, &buffers, &mut meshes)?;
+            }
+        }
+        // files without scenes only describe meshes
+        None => {
+            for mesh in document.meshes() {
+                load_gltf_mesh(&mesh, Mat4::IDENTITY, &buffers, &mut meshes)?;
+            }
+        }
+    }
+    let materials = document
+        .materials()
+        .map(|material| load_gltf_material(&material, &images))
+        .collect();
+    Ok(ModelData { meshes, materials })
+}
+
+fn load_gltf_material(material: &gltf::Material, images: &[gltf::image::Data]) -> MaterialData {
+    let pbr = material.pbr_metallic_roughness();
+    let texture = |texture: gltf::Texture, uv_set: u32| {
+        let image = gltf_image(&images[texture.source().index()])?;
+        Some(MaterialTexture { image, uv_set })
+    };
+    let info_texture = |info: Option<gltf::texture::Info>| {
+        info.and_then(|info| texture(info.texture(), info.tex_coord()))
+    };
+    // a single sampler serves the whole material, take the one of the most visible texture
+    let sampler = pbr
+        .base_color_texture()
+        .map(|info| info.texture())
+        .or_else(|| material.emissive_texture().map(|info| info.texture()))
+        .map_or_else(SamplerSettings::default, |texture| {
+            gltf_sampler(&texture.sampler())
+        });
+    MaterialData {
+        name: material.name().map(str::to_string),
+        factors: MaterialFactors {
+            base_color: pbr.base_color_factor(),
+            metallic: pbr.metallic_factor(),
+            roughness: pbr.roughness_factor(),
+            normal_scale: material
+                .normal_texture()
+                .map_or(1.0, |normal| normal.scale()),
+            occlusion_strength: material
+                .occlusion_texture()
+                .map_or(1.0, |occlusion| occlusion.strength()),
+            emissive: material.emissive_factor(),
+        },
+        base_color_texture: info_texture(pbr.base_color_texture()),
+        metallic_roughness_texture: info_texture(pbr.metallic_roughness_texture()),
+        normal_texture: material
+            .normal_texture()
+            .and_then(|normal| texture(normal.texture(), normal.tex_coord())),
+        occlusion_texture: material
+            .occlusion_texture()
+            .and_then(|occlusion| texture(occlusion.texture(), occlusion.tex_coord())),
+        emissive_texture: info_texture(material.emissive_texture()),
+        sampler,
+        // masked materials are drawn opaque, without their cutoff
+        blend_mode: match material.alpha_mode() {
+            gltf::material::AlphaMode::Blend => BlendMode::Alpha,
+            gltf::material::AlphaMode::Opaque | gltf::material::AlphaMode::Mask => {
+                BlendMode::Opaque
+            }
+        },
+    }
+}
+
+/// Converts a decoded glTF image to RGBA8, keeping the high byte of 16 bit channels.
+fn gltf_image(data: &gltf::image::Data) -> Option<MaterialImage> {
+    use gltf::image::Format;
+    let (channels, bytes_per_channel) = match data.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        format => {
+            info!("Skipping glTF image with unsupported format {format:?}");
+            return None;
+        }
+    };
+    let rgba = data
+        .pixels
+        .chunks_exact(channels * bytes_per_channel)
+        .flat_map(|pixel| {
+            // little endian, so the high byte comes last
+            let channel = |i: usize| pixel[i * bytes_per_channel + bytes_per_channel - 1];
+            match channels {
+                1 => [channel(0), channel(0), channel(0), 255],
+                2 => [channel(0), channel(1), 0, 255],
+                3 => [channel(0), channel(1), channel(2), 255],
+                _ => [channel(0), channel(1), channel(2), channel(3)],
+            }
+        })
+        .collect();
+    Some(MaterialImage {
+        width: data.width,
+        height: data.height,
+        rgba,
+    })
+}
+
+fn gltf_sampler(sampler: &gltf::texture::Sampler) -> SamplerSettings {
+    use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+    let address_mode = |mode| match mode {
+        WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+        WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+        WrappingMode::Repeat => wgpu::AddressMode::Repeat,
+    };
+    let mag_filter = match sampler.mag_filter() {
+        Some(MagFilter::Nearest) => wgpu::FilterMode::Nearest,
+        Some(MagFilter::Linear) | None => wgpu::FilterMode::Linear,
+    };
+    let min_filter = match sampler.min_filter() {
+        Some(
+            MinFilter::Nearest | MinFilter::NearestMipmapNearest | MinFilter::NearestMipmapLinear,
+        ) => wgpu::FilterMode::Nearest,
+        _ => wgpu::FilterMode::Linear,
+    };
+    SamplerSettings {
+        address_mode_u: address_mode(sampler.wrap_s()),
+        address_mode_v: address_mode(sampler.wrap_t()),
+        mag_filter,
+        min_filter,
+        mipmap_filter: wgpu::FilterMode::Nearest,
+    }
+}
+
+fn load_gltf_node(
+    node: &gltf::Node,
+    parent_transform: Mat4,
+    buffers: &[gltf::buffer::Data],
+    meshes: &mut Vec<MeshData>,
+) -> Result<()> {
+    let transform = parent_transform * Mat4::from_cols_array_2d(&node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        load_gltf_mesh(&mesh, transform, buffers, meshes)?;
+    }
+    for child in node.children() {
+        load_gltf_node(&child, transform, buffers, meshes)?;
+    }
+    Ok(())
+}
+
+fn load_gltf_mesh(
+    mesh: &gltf::Mesh,
+    transform: Mat4,
+    buffers: &[gltf::buffer::Data],
+    meshes: &mut Vec<MeshData>,
+) -> Result<()> {
+    let normal_matrix = Mat3::from_mat4(transform).inverse().transpose();
+    for primitive in mesh.primitives() {
+        if primitive.mode() != gltf::mesh::Mode::Triangles {
+            info!("Skipping non triangle primitive of mesh {:?}", mesh.name());
+            continue;
+        }
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let positions: Vec<[f32; 3]> = reader
+            .read_positions()
+            .ok_or_else(|| Error::Asset("glTF primitive has no positions".to_string()))?
+            .collect();
+        let mut vertices: Vec<Vertex> = positions
+            .iter()
+            .map(|pos| Vertex {
+                pos: transform.transform_point3(Vec3::from(*pos)).to_array(),
+                color: DEFAULT_COLOR,
+                ..Default::default()
+            })
+            .collect();
+        if let Some(colors) = reader.read_colors(0) {
+            for (vertex, color) in vertices.iter_mut().zip(colors.into_rgb_f32()) {
+                vertex.color = color;
+            }
+        }
+        if let Some(tex_coords) = reader.read_tex_coords(0) {
+            for (vertex, tex_pos) in vertices.iter_mut().zip(tex_coords.into_f32()) {
+                vertex.tex_pos = tex_pos;
+            }
+        }
+        if let Some(tex_coords) = reader.read_tex_coords(1) {
+            for (vertex, tex_pos) in vertices.iter_mut().zip(tex_coords.into_f32()) {
+                vertex.tex_pos_1 = tex_pos;
+            }
+        }
+        let has_normals = if let Some(normals) = reader.read_normals() {
+            for (vertex, normal) in vertices.iter_mut().zip(normals) {
+                vertex.normal = (normal_matrix * Vec3::from(normal))
+                    .normalize_or_zero()
+                    .to_array();
+            }
+            true
+        } else {
+            false
+        };
+        let has_tangents = if let Some(tangents) = reader.read_tangents() {
+            for (vertex, [x, y, z, w]) in vertices.iter_mut().zip(tangents) {
+                let tangent = transform.transform_vector3(Vec3::new(x, y, z));
+                vertex.tangent = tangent.normalize_or_zero().extend(w).to_array();
+            }
+            true
+        } else {
+            false
+        };
+        let indices = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..vertices.len() as u32).collect(),
+        };
+
+        let mut mesh_data = MeshData::new(vertices, indices);
+        mesh_data.name = mesh.name().map(str::to_string);
+        mesh_data.material = primitive.material().index();
+        mesh_data.check_indices()?;
+        // a mirroring transform flips the winding order
+        if transform.determinant() < 0.0 {
+            for triangle in mesh_data.indices.chunks_exact_mut(3) {
+                triangle.swap(1, 2);
+            }
+        }
+        if !has_normals {
+            mesh_data.compute_normals();
+        }
+        if !has_tangents {
+            mesh_data.compute_tangents();
+        }
+        meshes.push(mesh_data);
+    }
+    Ok(())
+}
+
+fn read_array<const N: usize>(data: &[f32], index: usize) -> [f32; N] {
+    std::array::from_fn(|i| data[index * N + i])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIANGLE_OBJ: &str = "o Triangle\nv 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\n\
+                                f 1/1 2/2 3/3\n";
+
+    /// A binary glTF holding one triangle with the given indices.
+    fn glb(indices: [u16; 3]) -> Vec<u8> {
+        let mut bin: Vec<u8> = [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        bin.extend(indices.iter().flat_map(|index| index.to_le_bytes()));
+        bin.resize(bin.len().next_multiple_of(4), 0);
+        let mut json = format!(
+            r#"{{"asset":{{"version":"2.0"}},"scene":0,"scenes":[{{"nodes":[0]}}],
+            "nodes":[{{"mesh":0}}],
+            "meshes":[{{"name":"Triangle","primitives":[{{"attributes":{{"POSITION":0}},"indices":1}}]}}],
+            "accessors":[
+                {{"bufferView":0,"componentType":5126,"count":3,"type":"VEC3","min":[0,0,0],"max":[1,1,0]}},
+                {{"bufferView":1,"componentType":5123,"count":3,"type":"SCALAR"}}],
+            "bufferViews":[{{"buffer":0,"byteLength":36}},{{"buffer":0,"byteOffset":36,"byteLength":6}}],
+            "buffers":[{{"byteLength":{}}}]}}"#,
+            bin.len()
+        );
+        json.extend(std::iter::repeat_n(
+            ' ',
+            json.len().next_multiple_of(4) - json.len(),
+        ));
+
+        let mut data = Vec::new();
+        data.extend(b"glTF");
+        data.extend(2u32.to_le_bytes());
+        data.extend(((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+        data.extend((json.len() as u32).to_le_bytes());
+        data.extend(b"JSON");
+        data.extend(json.as_bytes());
+        data.extend((bin.len() as u32).to_le_bytes());
+        data.extend(b"BIN\0");
+        data.extend(bin);
+        data
+    }
+
+    #[test]
+    fn obj() {
+        let model = load_obj(TRIANGLE_OBJ.as_bytes(), None).unwrap();
+        assert_eq!(model.meshes.len(), 1);
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.name.as_deref(), Some("Triangle"));
+        assert_eq!(mesh.vertices.len(), 3);
+        assert_eq!(mesh.indices, [0, 1, 2]);
+        // generated normal and flipped V
+        assert_eq!(mesh.vertices[0].normal, [0.0, 0.0, 1.0]);
+        assert_eq!(mesh.vertices[2].tex_pos, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn obj_index_out_of_range() {
+        let err = load_obj(b"v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 9\n", None).unwrap_err();
+        assert!(matches!(err, Error::Asset(_)), "{err}");
+    }
+
+    #[test]
+    fn obj_without_faces() {
+        let err = load_mesh(MeshFormat::Obj, b"o Points\nv 0 0 0\nv 1 0 0\n", None).unwrap_err();
+        assert!(matches!(err, Error::Asset(_)), "{err}");
+    }
+
+    #[test]
+    fn glb_triangle() {
+        let model = load_gltf(&glb([0, 1, 2])).unwrap();
+        assert_eq!(model.meshes.len(), 1);
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.name.as_deref(), Some("Triangle"));
+        assert_eq!(mesh.vertices[1].pos, [1.0, 0.0, 0.0]);
+        assert_eq!(mesh.indices, [0, 1, 2]);
+        assert_eq!(mesh.vertices[0].normal, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn glb_index_out_of_range() {
+        let err = load_gltf(&glb([0, 1, 7])).unwrap_err();
+        assert!(matches!(err, Error::Asset(_)), "{err}");
+    }
+
+    #[test]
+    fn gltf_external_buffer() {
+        let json = r#"{"asset":{"version":"2.0"},"buffers":[{"uri":"model.bin","byteLength":4}]}"#;
+        let err = load_gltf(json.as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("external file"), "{err}");
+    }
+}
@@ -1,77 +1,660 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
 use log::info;
 use wgpu::{
-    BufferAddress, Device, FragmentState, MultisampleState, PipelineCompilationOptions,
+    BindGroupLayout, BufferAddress, ColorTargetState, ColorWrites, CompareFunction, DepthBiasState,
+    DepthStencilState, Device, FragmentState, MultisampleState, PipelineCompilationOptions,
     PipelineLayoutDescriptor, PrimitiveState, RenderPipeline, RenderPipelineDescriptor,
-    ShaderModuleDescriptor, TextureFormat, VertexBufferLayout, VertexState,
+    ShaderModuleDescriptor, TextureFormat, VertexAttribute, VertexBufferLayout, VertexState,
+    naga::{
+        front::wgsl,
+        valid::{Capabilities, ValidationFlags, Validator},
+    },
 };
 
 use crate::{
-    buffer_manager::{BufferManager, Vertex},
+    buffer_manager::{BufferManager, InstanceRaw, Vertex},
+    error::{Error, Result},
+    gpu_context::GpuContext,
+    light::{LightManager, SHADOW_FORMAT},
+    material::{BlendMode, MaterialManager},
+    renderer::DEPTH_FORMAT,
+    resource_cache::ResourceCache,
     texture_manager::TextureManager,
 };
 
+const TEXTURES_PLACEHOLDER: &str = "// {{TEXTURES}}";
+
+// `gamma_encode` override of the PBR and skybox shaders, which encode themselves unless
+// the surface is sRGB
+fn gamma_encode(format: TextureFormat) -> [(&'static str, f64); 1] {
+    [("gamma_encode", if format.is_srgb() { 0.0 } else { 1.0 })]
+}
+
+// matches @location(0) to @location(5) of `VertexInput`
+const VERTEX_ATTRIBUTES: [VertexAttribute; 6] = wgpu::vertex_attr_array![
+    0 => Float32x3,
+    1 => Float32x3,
+    2 => Float32x2,
+    3 => Float32x3,
+    4 => Float32x4,
+    5 => Float32x2,
+];
+
+// model matrix columns, color and texture index of `InstanceInput`
+const INSTANCE_ATTRIBUTES: [VertexAttribute; 6] = wgpu::vertex_attr_array![
+    6 => Float32x4,
+    7 => Float32x4,
+    8 => Float32x4,
+    9 => Float32x4,
+    10 => Float32x4,
+    11 => Uint32,
+];
+
+/// Color target a scene pipeline draws into.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct ColorTarget {
+    format: TextureFormat,
+    sample_count: u32,
+    blend_mode: BlendMode,
+}
+
+/// Everything a pipeline is built from, under which `ResourceCache` shares it between
+/// the canvases on a device. The bind group layouts are the same for every canvas, apart
+/// from the number of textures.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    shader_code: String,
+    // `None` for depth only pipelines
+    target: Option<ColorTarget>,
+    texture_count: usize,
+    // bits of the constant values, floats aren't hashable
+    constants: Vec<(String, u64)>,
+}
+
+impl PipelineKey {
+    fn new(
+        shader_code: &str,
+        target: Option<ColorTarget>,
+        texture_count: usize,
+        constants: &[(&str, f64)],
+    ) -> Self {
+        Self {
+            shader_code: shader_code.to_string(),
+            target,
+            texture_count,
+            constants: constants
+                .iter()
+                .map(|&(name, value)| (name.to_string(), value.to_bits()))
+                .collect(),
+        }
+    }
+}
+
+/// Variants of the default, PBR and custom pipelines for a transparent blend mode.
+struct BlendedPipelines {
+    pipeline: Rc<RenderPipeline>,
+    pbr_pipeline: Rc<RenderPipeline>,
+    custom_pipelines: HashMap<String, Rc<RenderPipeline>>,
+}
+
+/// The pipelines drawing without a custom shader.
+struct BuiltinPipelines {
+    pipeline: Rc<RenderPipeline>,
+    // metallic-roughness shading of meshes with a material
+    pbr_pipeline: Rc<RenderPipeline>,
+    // depth only, renders shadow map layers
+    shadow_pipeline: Rc<RenderPipeline>,
+    // draws the environment of the light manager behind the scene
+    skybox_pipeline: Rc<RenderPipeline>,
+}
+
 pub struct PipelineManager {
-    pub pipeline: RenderPipeline,
+    /// Pipelines of user supplied shaders, by shader name.
+    pub custom_pipelines: HashMap<String, Rc<RenderPipeline>>,
+    pub swapchain_format: TextureFormat,
+    /// Samples per pixel of the scene pass targets.
+    pub sample_count: u32,
+    // `None` once destroyed
+    builtin: Option<BuiltinPipelines>,
+    // created by `prepare_blend_mode` for the blend modes materials use
+    blended: HashMap<BlendMode, BlendedPipelines>,
+    // what custom shaders are built against
+    bind_group_layouts: Vec<BindGroupLayout>,
+    texture_count: usize,
+    // sources of the custom pipelines, to rebuild them for another sample count or blend mode
+    custom_shaders: HashMap<String, String>,
+    // pipelines built by the other canvases on the device
+    cache: Rc<RefCell<ResourceCache>>,
 }
 
 impl PipelineManager {
+    /// Reuses the pipelines other canvases on the device built already.
     pub fn new(
-        device: &Device,
+        gpu: &GpuContext,
         swapchain_format: TextureFormat,
+        sample_count: u32,
+        buffers: &BufferManager,
+        textures: &TextureManager,
+        materials: &MaterialManager,
+        lights: &LightManager,
+    ) -> Self {
+        let layouts = [
+            &buffers.uniform_manager.bind_group_layout,
+            &textures.bind_group_layout,
+            &materials.bind_group_layout,
+            &lights.bind_group_layout,
+        ];
+        let (device, cache) = (&gpu.device, &gpu.cache);
+        let texture_count = textures.textures.len();
+        let target = ColorTarget {
+            format: swapchain_format,
+            sample_count,
+            blend_mode: BlendMode::Opaque,
+        };
+        let (pipeline, pbr_pipeline) =
+            Self::create_shading_pipelines(device, cache, target, &layouts, texture_count);
+        let skybox_pipeline =
+            Self::create_skybox_pipeline(device, cache, target, &layouts, texture_count);
+        let shadow_pipeline =
+            Self::create_shadow_pipeline(device, cache, &lights.shadow_view_layout);
+        info!("Pipeline created successfully!!!");
+        Self {
+            custom_pipelines: HashMap::new(),
+            swapchain_format,
+            sample_count,
+            builtin: Some(BuiltinPipelines {
+                pipeline,
+                pbr_pipeline,
+                shadow_pipeline,
+                skybox_pipeline,
+            }),
+            blended: HashMap::new(),
+            bind_group_layouts: layouts.into_iter().cloned().collect(),
+            texture_count,
+            custom_shaders: HashMap::new(),
+            cache: cache.clone(),
+        }
+    }
+
+    /// The same pipelines, custom ones included, on another device. Blended pipelines
+    /// have to be prepared again.
+    pub fn recreate(
+        &self,
+        gpu: &GpuContext,
         buffers: &BufferManager,
         textures: &TextureManager,
+        materials: &MaterialManager,
+        lights: &LightManager,
     ) -> Self {
-        // shaders
+        let mut pipeline = Self::new(
+            gpu,
+            self.swapchain_format,
+            self.sample_count,
+            buffers,
+            textures,
+            materials,
+            lights,
+        );
+        for (name, shader_code) in &self.custom_shaders {
+            pipeline.add_shader(&gpu.device, name, shader_code);
+        }
+        pipeline
+    }
+
+    /// Rebuilds every pipeline drawing into the scene passes, custom ones included, for
+    /// targets with `sample_count` samples per pixel. Blended pipelines have to be
+    /// prepared again.
+    pub fn set_sample_count(&mut self, device: &Device, sample_count: u32) {
+        self.sample_count = sample_count;
+        self.blended.clear();
+        let layouts: Vec<&BindGroupLayout> = self.bind_group_layouts.iter().collect();
+        let target = self.target(BlendMode::Opaque);
+        let (cache, texture_count) = (&self.cache, self.texture_count);
+        if let Some(builtin) = &mut self.builtin {
+            (builtin.pipeline, builtin.pbr_pipeline) =
+                Self::create_shading_pipelines(device, cache, target, &layouts, texture_count);
+            builtin.skybox_pipeline =
+                Self::create_skybox_pipeline(device, cache, target, &layouts, texture_count);
+        }
+        for (name, shader_code) in std::mem::take(&mut self.custom_shaders) {
+            self.add_shader(device, &name, &shader_code);
+        }
+    }
+
+    /// Creates the pipelines drawing with a transparent blend mode, unless they exist.
+    pub fn prepare_blend_mode(&mut self, device: &Device, blend_mode: BlendMode) {
+        if !blend_mode.is_transparent() || self.blended.contains_key(&blend_mode) {
+            return;
+        }
+        let layouts: Vec<&BindGroupLayout> = self.bind_group_layouts.iter().collect();
+        let target = self.target(blend_mode);
+        let (cache, texture_count) = (&self.cache, self.texture_count);
+        let (pipeline, pbr_pipeline) =
+            Self::create_shading_pipelines(device, cache, target, &layouts, texture_count);
+        let custom_pipelines = self
+            .custom_shaders
+            .iter()
+            .map(|(name, shader_code)| {
+                let pipeline = Self::create_pipeline(
+                    device,
+                    cache,
+                    target,
+                    &layouts,
+                    texture_count,
+                    (name, shader_code),
+                    &[],
+                );
+                (name.clone(), pipeline)
+            })
+            .collect();
+        self.blended.insert(
+            blend_mode,
+            BlendedPipelines {
+                pipeline,
+                pbr_pipeline,
+                custom_pipelines,
+            },
+        );
+    }
+
+    /// Builds a pipeline for a custom shader. It gets the same vertex layout and bind
+    /// groups as `default.wgsl`, including the generated `// {{TEXTURES}}` bindings,
+    /// plus the material bindings of `pbr.wgsl` at group 2 and its lights at group 3.
+    pub fn add_shader(&mut self, device: &Device, name: &str, shader_code: &str) {
+        let layouts: Vec<&BindGroupLayout> = self.bind_group_layouts.iter().collect();
+        let pipeline = Self::create_pipeline(
+            device,
+            &self.cache,
+            self.target(BlendMode::Opaque),
+            &layouts,
+            self.texture_count,
+            (name, shader_code),
+            &[],
+        );
+        self.custom_pipelines.insert(name.to_string(), pipeline);
+        for (&blend_mode, blended) in &mut self.blended {
+            let target = ColorTarget {
+                format: self.swapchain_format,
+                sample_count: self.sample_count,
+                blend_mode,
+            };
+            let pipeline = Self::create_pipeline(
+                device,
+                &self.cache,
+                target,
+                &layouts,
+                self.texture_count,
+                (name, shader_code),
+                &[],
+            );
+            blended.custom_pipelines.insert(name.to_string(), pipeline);
+        }
+        self.custom_shaders
+            .insert(name.to_string(), shader_code.to_string());
+    }
+
+    /// Drops every pipeline, the cache keeps the ones other canvases still use.
+    pub fn destroy(&mut self) {
+        self.builtin = None;
+        self.custom_pipelines.clear();
+        self.custom_shaders.clear();
+        self.blended.clear();
+    }
+
+    /// Parses and validates a custom shader for `texture_count` textures, so mistakes are
+    /// reported before any pipeline is built from it. The device may still reject
+    /// features it lacks.
+    pub fn validate_shader(name: &str, shader_code: &str, texture_count: usize) -> Result<()> {
+        let shader_code =
+            shader_code.replace(TEXTURES_PLACEHOLDER, &Self::texture_bindings(texture_count));
+        let module = wgsl::parse_str(&shader_code).map_err(|err| {
+            Error::ShaderCompile(format!(
+                "Shader {name} failed to parse: {}",
+                err.emit_to_string(&shader_code)
+            ))
+        })?;
+        Validator::new(ValidationFlags::all(), Capabilities::all())
+            .validate(&module)
+            .map_err(|err| {
+                Error::ShaderCompile(format!(
+                    "Shader {name} is invalid: {}",
+                    err.emit_to_string(&shader_code)
+                ))
+            })?;
+        Ok(())
+    }
+
+    /// The named custom pipeline. Without a name, draws with a material use the PBR
+    /// pipeline and the others the default one. Transparent blend modes need
+    /// `prepare_blend_mode` first.
+    pub fn get(
+        &self,
+        name: Option<&str>,
+        has_material: bool,
+        blend_mode: BlendMode,
+    ) -> Option<&RenderPipeline> {
+        let (pipeline, pbr_pipeline, custom_pipelines) = if blend_mode.is_transparent() {
+            let blended = self.blended.get(&blend_mode)?;
+            (
+                &blended.pipeline,
+                &blended.pbr_pipeline,
+                &blended.custom_pipelines,
+            )
+        } else {
+            let builtin = self.builtin.as_ref()?;
+            (
+                &builtin.pipeline,
+                &builtin.pbr_pipeline,
+                &self.custom_pipelines,
+            )
+        };
+        match name {
+            Some(name) => custom_pipelines.get(name).map(Rc::as_ref),
+            None if has_material => Some(pbr_pipeline),
+            None => Some(pipeline),
+        }
+    }
+
+    /// Depth only pipeline rendering shadow map layers, `None` once destroyed.
+    pub fn shadow_pipeline(&self) -> Option<&RenderPipeline> {
+        Some(&self.builtin.as_ref()?.shadow_pipeline)
+    }
+
+    /// Draws the environment of the light manager behind the scene, `None` once destroyed.
+    pub fn skybox_pipeline(&self) -> Option<&RenderPipeline> {
+        Some(&self.builtin.as_ref()?.skybox_pipeline)
+    }
+
+    fn target(&self, blend_mode: BlendMode) -> ColorTarget {
+        ColorTarget {
+            format: self.swapchain_format,
+            sample_count: self.sample_count,
+            blend_mode,
+        }
+    }
+
+    // the default and PBR pipelines
+    fn create_shading_pipelines(
+        device: &Device,
+        cache: &RefCell<ResourceCache>,
+        target: ColorTarget,
+        layouts: &[&BindGroupLayout],
+        texture_count: usize,
+    ) -> (Rc<RenderPipeline>, Rc<RenderPipeline>) {
         info!("Getting shader code");
-        let shader_code = include_str!("./shader/default.wgsl");
+        let pipeline = Self::create_pipeline(
+            device,
+            cache,
+            target,
+            layouts,
+            texture_count,
+            ("Default", include_str!("./shader/default.wgsl")),
+            &[],
+        );
+        let pbr_pipeline = Self::create_pipeline(
+            device,
+            cache,
+            target,
+            layouts,
+            texture_count,
+            ("PBR", include_str!("./shader/pbr.wgsl")),
+            &gamma_encode(target.format),
+        );
+        (pipeline, pbr_pipeline)
+    }
+
+    /// `shader` is the name and code of the shader. `constants` sets pipeline-overridable
+    /// constants, which have to be declared by the shader. Transparent pipelines test
+    /// depth without writing it. A pipeline from the same code and target is taken from
+    /// `cache`.
+    fn create_pipeline(
+        device: &Device,
+        cache: &RefCell<ResourceCache>,
+        target: ColorTarget,
+        bind_group_layouts: &[&BindGroupLayout],
+        texture_count: usize,
+        (name, shader_code): (&str, &str),
+        constants: &[(&str, f64)],
+    ) -> Rc<RenderPipeline> {
+        let key = PipelineKey::new(shader_code, Some(target), texture_count, constants);
+        cache.borrow_mut().pipeline(key, || {
+            Self::build_pipeline(
+                device,
+                target,
+                bind_group_layouts,
+                texture_count,
+                (name, shader_code),
+                constants,
+            )
+        })
+    }
+
+    fn build_pipeline(
+        device: &Device,
+        target: ColorTarget,
+        bind_group_layouts: &[&BindGroupLayout],
+        texture_count: usize,
+        (name, shader_code): (&str, &str),
+        constants: &[(&str, f64)],
+    ) -> RenderPipeline {
+        let shader_code =
+            shader_code.replace(TEXTURES_PLACEHOLDER, &Self::texture_bindings(texture_count));
         let shader = device.create_shader_module(ShaderModuleDescriptor {
-            label: Some("Default Shader"),
-            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(shader_code)),
+            label: Some(&format!("{name} Shader")),
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Owned(shader_code)),
         });
 
         // pipeline
         info!("Creating pipeline");
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("Pipeline Layout"),
-            bind_group_layouts: &[
-                &buffers.uniform_manager.bind_group_layout,
-                &textures.bind_group_layout,
-            ],
+            bind_group_layouts,
             push_constant_ranges: &[],
         });
 
-        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some(&format!("{name} Render Pipeline")),
             layout: Some(&pipeline_layout),
             vertex: VertexState {
                 module: &shader,
                 entry_point: Some("vs_main"),
-                compilation_options: PipelineCompilationOptions::default(),
-                buffers: &[VertexBufferLayout {
-                    array_stride: std::mem::size_of::<Vertex>() as BufferAddress,
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: &wgpu::vertex_attr_array![
-                        // matches @position(0), @position(1), and @position(2)
-                        0 => Float32x3,
-                        1 => Float32x3,
-                        2 => Float32x2,
-                    ],
-                }],
+                compilation_options: PipelineCompilationOptions {
+                    constants,
+                    ..Default::default()
+                },
+                buffers: &Self::vertex_buffer_layouts(),
             },
             fragment: Some(FragmentState {
                 module: &shader,
                 entry_point: Some("fs_main"),
-                compilation_options: PipelineCompilationOptions::default(),
-                targets: &[Some(swapchain_format.into())],
+                compilation_options: PipelineCompilationOptions {
+                    constants,
+                    ..Default::default()
+                },
+                targets: &[Some(ColorTargetState {
+                    format: target.format,
+                    blend: target.blend_mode.blend_state(),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: Some(DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: !target.blend_mode.is_transparent(),
+                depth_compare: CompareFunction::Less,
+                stencil: Default::default(),
+                bias: Default::default(),
             }),
+            multisample: MultisampleState {
+                count: target.sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+            cache: None,
+        })
+    }
+
+    fn create_shadow_pipeline(
+        device: &Device,
+        cache: &RefCell<ResourceCache>,
+        shadow_view_layout: &BindGroupLayout,
+    ) -> Rc<RenderPipeline> {
+        let shader_code = include_str!("./shader/shadow.wgsl");
+        let key = PipelineKey::new(shader_code, None, 0, &[]);
+        cache.borrow_mut().pipeline(key, || {
+            Self::build_shadow_pipeline(device, shader_code, shadow_view_layout)
+        })
+    }
+
+    fn build_shadow_pipeline(
+        device: &Device,
+        shader_code: &str,
+        shadow_view_layout: &BindGroupLayout,
+    ) -> RenderPipeline {
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Shadow Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_code.into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Shadow Pipeline Layout"),
+            bind_group_layouts: &[shadow_view_layout],
+            push_constant_ranges: &[],
+        });
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Shadow Render Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: PipelineCompilationOptions::default(),
+                buffers: &Self::vertex_buffer_layouts(),
+            },
+            fragment: None,
             primitive: PrimitiveState::default(),
-            depth_stencil: None,
+            depth_stencil: Some(DepthStencilState {
+                format: SHADOW_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::Less,
+                stencil: Default::default(),
+                // slope scaled bias against shadow acne on surfaces facing away from the light
+                bias: DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            }),
             multisample: MultisampleState::default(),
             multiview: None,
             cache: None,
+        })
+    }
+
+    // uses the scene bind group layouts, so the pass keeps its bind groups around the draw
+    fn create_skybox_pipeline(
+        device: &Device,
+        cache: &RefCell<ResourceCache>,
+        target: ColorTarget,
+        bind_group_layouts: &[&BindGroupLayout],
+        texture_count: usize,
+    ) -> Rc<RenderPipeline> {
+        let shader_code = include_str!("./shader/skybox.wgsl");
+        let key = PipelineKey::new(shader_code, Some(target), texture_count, &[]);
+        cache.borrow_mut().pipeline(key, || {
+            Self::build_skybox_pipeline(device, shader_code, target, bind_group_layouts)
+        })
+    }
+
+    fn build_skybox_pipeline(
+        device: &Device,
+        shader_code: &str,
+        target: ColorTarget,
+        bind_group_layouts: &[&BindGroupLayout],
+    ) -> RenderPipeline {
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Skybox Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_code.into()),
         });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Skybox Pipeline Layout"),
+            bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Skybox Render Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: PipelineCompilationOptions::default(),
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: PipelineCompilationOptions {
+                    constants: &gamma_encode(target.format),
+                    ..Default::default()
+                },
+                targets: &[Some(target.format.into())],
+            }),
+            primitive: PrimitiveState::default(),
+            // drawn at the far plane, only where the scene left the cleared depth
+            depth_stencil: Some(DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: CompareFunction::LessEqual,
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
+            multisample: MultisampleState {
+                count: target.sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+            cache: None,
+        })
+    }
 
-        info!("Pipeline created successfully!!!");
-        Self { pipeline }
+    fn vertex_buffer_layouts() -> [VertexBufferLayout<'static>; 2] {
+        [
+            VertexBufferLayout {
+                array_stride: std::mem::size_of::<Vertex>() as BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &VERTEX_ATTRIBUTES,
+            },
+            VertexBufferLayout {
+                array_stride: std::mem::size_of::<InstanceRaw>() as BufferAddress,
+                step_mode: wgpu::VertexStepMode::Instance,
+                attributes: &INSTANCE_ATTRIBUTES,
+            },
+        ]
+    }
+
+    /// The texture bind group has one binding per loaded texture, so its declarations
+    /// and the `sample_texture` lookup are generated to match.
+    fn texture_bindings(texture_count: usize) -> String {
+        if texture_count == 0 {
+            return "fn sample_texture(index: u32, uv: vec2<f32>) -> vec4<f32> {\n    return vec4<f32>(1.0);\n}\n".to_string();
+        }
+        let mut code = String::new();
+        for i in 0..texture_count {
+            code += &format!("@group(1) @binding({i})\nvar tex_{i}: texture_2d<f32>;\n\n");
+        }
+        // every texture also has its own sampler, bound after all the textures
+        for i in 0..texture_count {
+            code += &format!(
+                "@group(1) @binding({})\nvar sampler_{i}: sampler;\n\n",
+                texture_count + i
+            );
+        }
+        code += "fn sample_texture(index: u32, uv: vec2<f32>) -> vec4<f32> {\n    var color = vec4<f32>(1.0);\n";
+        // sample everything up front, `textureSample` is only allowed in uniform control flow
+        for i in 0..texture_count {
+            code += &format!(
+                "    let sample_{i} = textureSample(tex_{i}, sampler_{i}, uv);\n    if index == {i}u {{ color = sample_{i}; }}\n"
+            );
+        }
+        code + "    return color;\n}\n"
     }
 }
@@ -0,0 +1,445 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use wgpu::{
+    Buffer, BufferUsages, CommandEncoder, Device, MapMode, QuerySet, QuerySetDescriptor, QueryType,
+    Queue, RenderPassTimestampWrites,
+};
+
+use crate::{
+    sprite::{Sprite, SpriteBatch},
+    text::{TextRenderer, TextStyle},
+};
+
+/// Frames the rolling statistics cover.
+const HISTORY: usize = 120;
+/// Passes timed per frame, later ones go untimed.
+const MAX_TIMED_PASSES: u32 = 32;
+/// Frames of timestamps that can wait for their readback at once.
+const READBACKS: usize = 3;
+
+/// Milliseconds since the page loaded, with sub-millisecond precision where the
+/// browser allows it.
+pub fn now() -> f64 {
+    web_sys::window()
+        .and_then(|window| window.performance())
+        .map(|performance| performance.now())
+        .unwrap_or_else(web_sys::js_sys::Date::now)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TimingSource {
+    /// Wall clock time spent in an `App` method.
+    Cpu,
+    /// Time the GPU spent in a render pass, summed over the passes sharing a name.
+    Gpu,
+}
+
+/// Rolling statistics of one timing over the last `HISTORY` frames, in milliseconds.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimingStats {
+    pub name: String,
+    pub source: TimingSource,
+    pub average: f32,
+    pub p50: f32,
+    pub p95: f32,
+    pub p99: f32,
+    pub max: f32,
+    pub samples: usize,
+}
+
+struct Timing {
+    name: String,
+    source: TimingSource,
+    samples: VecDeque<f32>,
+}
+
+impl Timing {
+    fn stats(&self) -> TimingStats {
+        let mut sorted: Vec<f32> = self.samples.iter().copied().collect();
+        sorted.sort_by(f32::total_cmp);
+        let percentile = |p: f32| {
+            let index = ((sorted.len() - 1) as f32 * p).round() as usize;
+            sorted[index]
+        };
+        TimingStats {
+            name: self.name.clone(),
+            source: self.source,
+            average: sorted.iter().sum::<f32>() / sorted.len() as f32,
+            p50: percentile(0.5),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+            max: sorted[sorted.len() - 1],
+            samples: sorted.len(),
+        }
+    }
+}
+
+/// Copy of a frame's resolved timestamps, mapped once the GPU is done with it.
+struct Readback {
+    buffer: Buffer,
+    // pass name of every pair of timestamps
+    passes: Vec<String>,
+    // set by the map callback, `None` while the buffer is free or mapping
+    mapped: Arc<Mutex<Option<bool>>>,
+    in_flight: bool,
+}
+
+/// Timestamp queries written at the beginning and end of every render pass.
+struct GpuTimer {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readbacks: Vec<Readback>,
+    // nanoseconds per timestamp tick
+    period: f32,
+    // readback of the frame being recorded, `None` when all are still in flight
+    current: Option<usize>,
+    passes: Vec<String>,
+}
+
+impl GpuTimer {
+    fn new(device: &Device, queue: &Queue) -> Self {
+        let size = MAX_TIMED_PASSES as u64 * 2 * 8;
+        let query_set = device.create_query_set(&QuerySetDescriptor {
+            label: Some("Pass Timestamps"),
+            ty: QueryType::Timestamp,
+            count: MAX_TIMED_PASSES * 2,
+        });
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Timestamp Resolve Buffer"),
+            size,
+            usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readbacks = (0..READBACKS)
+            .map(|_| Readback {
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Timestamp Readback Buffer"),
+                    size,
+                    usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                passes: Vec::new(),
+                mapped: Arc::default(),
+                in_flight: false,
+            })
+            .collect();
+        Self {
+            query_set,
+            resolve_buffer,
+            readbacks,
+            period: queue.get_timestamp_period(),
+            current: None,
+            passes: Vec::new(),
+        }
+    }
+}
+
+/// Collects CPU timings of the `App` methods and GPU timings of the render passes,
+/// and optionally draws them as bars on top of the frame.
+pub struct Profiler {
+    pub enabled: bool,
+    /// Font of the overlay labels, bars only without one.
+    pub overlay: Option<Option<usize>>,
+    timings: Vec<Timing>,
+    // `None` without the timestamp query feature
+    gpu: Option<GpuTimer>,
+}
+
+impl Profiler {
+    /// Times passes on the GPU when the device has `TIMESTAMP_QUERY`.
+    pub fn new(device: &Device, queue: &Queue, timestamps: bool) -> Self {
+        if !timestamps {
+            info!("No timestamp queries, only CPU timings are profiled");
+        }
+        Self {
+            enabled: false,
+            overlay: None,
+            timings: Vec::new(),
+            gpu: timestamps.then(|| GpuTimer::new(device, queue)),
+        }
+    }
+
+    /// The same profiler on a replacement device, keeping its samples. Timestamps still
+    /// in flight on the lost device are dropped.
+    pub fn recreate(&mut self, device: &Device, queue: &Queue, timestamps: bool) -> Self {
+        Self {
+            enabled: self.enabled,
+            overlay: self.overlay,
+            timings: std::mem::take(&mut self.timings),
+            gpu: timestamps.then(|| GpuTimer::new(device, queue)),
+        }
+    }
+
+    /// Adds a sample to the named timing, in milliseconds.
+    pub fn record(&mut self, name: &str, source: TimingSource, milliseconds: f32) {
+        if !self.enabled {
+            return;
+        }
+        let index = match self
+            .timings
+            .iter()
+            .position(|timing| timing.source == source && timing.name == name)
+        {
+            Some(index) => index,
+            None => {
+                self.timings.push(Timing {
+                    name: name.to_string(),
+                    source,
+                    samples: VecDeque::with_capacity(HISTORY),
+                });
+                self.timings.len() - 1
+            }
+        };
+        let samples = &mut self.timings[index].samples;
+        if samples.len() == HISTORY {
+            samples.pop_front();
+        }
+        samples.push_back(milliseconds);
+    }
+
+    pub fn stats(&self) -> Vec<TimingStats> {
+        self.timings.iter().map(Timing::stats).collect()
+    }
+
+    /// Forgets all samples.
+    pub fn reset(&mut self) {
+        self.timings.clear();
+    }
+
+    /// Frees the timestamp buffers, turning GPU timing off.
+    pub fn destroy(&mut self) {
+        if let Some(gpu) = self.gpu.take() {
+            gpu.resolve_buffer.destroy();
+            for readback in &gpu.readbacks {
+                readback.buffer.destroy();
+            }
+        }
+    }
+
+    /// Reads the timestamps of finished frames and picks a readback buffer for the
+    /// frame about to be recorded.
+    pub fn begin_frame(&mut self) {
+        let Some(gpu) = &mut self.gpu else {
+            return;
+        };
+        let mut durations: Vec<(String, f32)> = Vec::new();
+        for readback in &mut gpu.readbacks {
+            let mapped = readback
+                .mapped
+                .lock()
+                .expect("map callback panicked")
+                .take();
+            match mapped {
+                Some(true) => {
+                    let data = readback.buffer.slice(..).get_mapped_range();
+                    // the mapped range isn't necessarily aligned for u64
+                    let ticks: Vec<u64> = bytemuck::pod_collect_to_vec(&data);
+                    for (pass, pair) in readback.passes.iter().zip(ticks.chunks_exact(2)) {
+                        let nanoseconds = pair[1].saturating_sub(pair[0]) as f32 * gpu.period;
+                        match durations.iter_mut().find(|(name, _)| name == pass) {
+                            Some((_, duration)) => *duration += nanoseconds / 1e6,
+                            None => durations.push((pass.clone(), nanoseconds / 1e6)),
+                        }
+                    }
+                    drop(data);
+                    readback.buffer.unmap();
+                    readback.in_flight = false;
+                }
+                Some(false) => readback.in_flight = false,
+                None => {}
+            }
+        }
+        gpu.current = if self.enabled {
+            gpu.readbacks
+                .iter()
+                .position(|readback| !readback.in_flight)
+        } else {
+            None
+        };
+        gpu.passes.clear();
+        for (name, duration) in durations {
+            self.record(&name, TimingSource::Gpu, duration);
+        }
+    }
+
+    /// Timestamp writes timing a render pass, `None` when the pass goes untimed.
+    pub fn pass_timestamps(&mut self, name: &str) -> Option<RenderPassTimestampWrites<'_>> {
+        let gpu = self.gpu.as_mut()?;
+        gpu.current?;
+        let index = gpu.passes.len() as u32;
+        if index == MAX_TIMED_PASSES {
+            return None;
+        }
+        gpu.passes.push(name.to_string());
+        Some(RenderPassTimestampWrites {
+            query_set: &gpu.query_set,
+            beginning_of_pass_write_index: Some(index * 2),
+            end_of_pass_write_index: Some(index * 2 + 1),
+        })
+    }
+
+    /// Copies the frame's timestamps into its readback buffer, call before submitting.
+    pub fn resolve(&mut self, encoder: &mut CommandEncoder) {
+        let Some(gpu) = &mut self.gpu else {
+            return;
+        };
+        let Some(current) = gpu.current else {
+            return;
+        };
+        if gpu.passes.is_empty() {
+            return;
+        }
+        let count = gpu.passes.len() as u32 * 2;
+        encoder.resolve_query_set(&gpu.query_set, 0..count, &gpu.resolve_buffer, 0);
+        let readback = &mut gpu.readbacks[current];
+        encoder.copy_buffer_to_buffer(
+            &gpu.resolve_buffer,
+            0,
+            &readback.buffer,
+            0,
+            count as u64 * 8,
+        );
+        readback.passes = std::mem::take(&mut gpu.passes);
+    }
+
+    /// Maps the readback buffer written this frame, call after submitting.
+    pub fn end_frame(&mut self) {
+        let Some(gpu) = &mut self.gpu else {
+            return;
+        };
+        let Some(current) = gpu.current.take() else {
+            return;
+        };
+        let readback = &mut gpu.readbacks[current];
+        if readback.passes.is_empty() {
+            return;
+        }
+        readback.in_flight = true;
+        let mapped = readback.mapped.clone();
+        readback
+            .buffer
+            .slice(..)
+            .map_async(MapMode::Read, move |result| {
+                *mapped.lock().expect("profiler panicked") = Some(result.is_ok());
+            });
+    }
+
+    /// Queues the overlay for the next frame: a bar per timing, as long as its average
+    /// in pixels per 0.1 ms and capped at a frame of 60 Hz, with the 95th percentile
+    /// marked, and labels when the overlay has a font.
+    pub fn draw_overlay(&self, sprites: &mut SpriteBatch, text: &mut TextRenderer) {
+        let Some(font) = self.overlay else {
+            return;
+        };
+        const ROW: f32 = 16.0;
+        const MAX_WIDTH: f32 = 1000.0 / 60.0 * 10.0;
+        for (i, stats) in self.stats().iter().enumerate() {
+            let y = 12.0 + i as f32 * ROW;
+            let color = match stats.source {
+                TimingSource::Cpu => [0.3, 0.6, 1.0, 0.8],
+                TimingSource::Gpu => [1.0, 0.5, 0.2, 0.8],
+            };
+            let width = (stats.average * 10.0).clamp(1.0, MAX_WIDTH);
+            let bar = Sprite {
+                position: [8.0 + width / 2.0, y],
+                scale: [width, ROW - 4.0],
+                tint: color,
+                layer: i32::MAX - 1,
+                ..Default::default()
+            };
+            let p95 = Sprite {
+                position: [8.0 + (stats.p95 * 10.0).clamp(1.0, MAX_WIDTH), y],
+                scale: [2.0, ROW - 2.0],
+                tint: [1.0, 1.0, 1.0, 0.9],
+                layer: i32::MAX,
+                ..Default::default()
+            };
+            // only fails for texture handles, which the bars don't have
+            let _ = sprites.add(bar);
+            let _ = sprites.add(p95);
+            if let Some(font) = font {
+                let source = match stats.source {
+                    TimingSource::Cpu => "cpu",
+                    TimingSource::Gpu => "gpu",
+                };
+                let label = format!(
+                    "{source} {}: {:.2} ms (p95 {:.2})",
+                    stats.name, stats.average, stats.p95
+                );
+                let style = TextStyle {
+                    position: [MAX_WIDTH + 16.0, y - ROW / 2.0],
+                    size: ROW - 4.0,
+                    ..Default::default()
+                };
+                if text.add(font, &label, &style).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profiler() -> Profiler {
+        Profiler {
+            enabled: true,
+            overlay: None,
+            timings: Vec::new(),
+            gpu: None,
+        }
+    }
+
+    #[test]
+    fn average_and_percentiles() {
+        let mut profiler = profiler();
+        // recorded out of order, the percentiles are taken from the sorted samples
+        for sample in (1..=100).rev() {
+            profiler.record("render", TimingSource::Cpu, sample as f32);
+        }
+        let stats = &profiler.stats()[0];
+        assert_eq!(stats.samples, 100);
+        assert_eq!(stats.average, 50.5);
+        assert_eq!(stats.p50, 51.0);
+        assert_eq!(stats.p95, 95.0);
+        assert_eq!(stats.p99, 99.0);
+        assert_eq!(stats.max, 100.0);
+    }
+
+    #[test]
+    fn history_drops_oldest_samples() {
+        let mut profiler = profiler();
+        for sample in 0..HISTORY + 30 {
+            profiler.record("update", TimingSource::Cpu, sample as f32);
+        }
+        let samples = &profiler.timings[0].samples;
+        assert_eq!(samples.len(), HISTORY);
+        assert_eq!(samples.front(), Some(&30.0));
+        assert_eq!(samples.back(), Some(&(HISTORY as f32 + 29.0)));
+    }
+
+    #[test]
+    fn timings_by_name_and_source() {
+        let mut profiler = profiler();
+        profiler.record("main", TimingSource::Cpu, 1.0);
+        profiler.record("main", TimingSource::Gpu, 2.0);
+        profiler.record("main", TimingSource::Cpu, 3.0);
+        let stats = profiler.stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!((stats[0].source, stats[0].samples), (TimingSource::Cpu, 2));
+        assert_eq!((stats[1].source, stats[1].samples), (TimingSource::Gpu, 1));
+
+        profiler.enabled = false;
+        profiler.record("main", TimingSource::Cpu, 4.0);
+        assert_eq!(profiler.stats()[0].samples, 2);
+    }
+}
@@ -1,32 +1,264 @@
-use log::info;
-use wasm_bindgen::JsError;
-use wgpu::{Color, CommandEncoderDescriptor, Operations};
+use std::ops::Range;
+
+use glam::Vec3;
+use log::{Level, info};
+use wgpu::{
+    Adapter, Color, CommandEncoderDescriptor, Device, Extent3d, Operations, SurfaceError, Texture,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView,
+};
 
 use crate::{
-    buffer_manager::BufferManager, gpu_context::GpuContext, pipeline_manager::PipelineManager,
+    buffer_manager::{BufferManager, InstanceBuffer, InstanceRaw},
+    camera::Camera,
+    debug_draw::DebugRenderer,
+    error::{Error, Result},
+    gpu_context::GpuContext,
+    light::LightManager,
+    logging::{log_every, log_once},
+    material::MaterialManager,
+    pipeline_manager::PipelineManager,
+    profiler::Profiler,
+    scene::{Draw, NodeHandle, Scene},
+    sprite::SpriteBatch,
+    text::TextRenderer,
     texture_manager::TextureManager,
 };
 
-pub struct Renderer;
+pub const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+/// Samples per pixel the scene passes can render with, 1 turning multisampling off.
+pub const SAMPLE_COUNTS: [u32; 2] = [1, 4];
+
+/// Whether the adapter can render `format` color targets, and depth, with `sample_count`
+/// samples per pixel.
+pub fn supports_sample_count(adapter: &Adapter, format: TextureFormat, sample_count: u32) -> bool {
+    SAMPLE_COUNTS.contains(&sample_count)
+        && [format, DEPTH_FORMAT].into_iter().all(|format| {
+            adapter
+                .get_texture_format_features(format)
+                .flags
+                .sample_count_supported(sample_count)
+        })
+}
+
+/// A render pass over the scene, see `scene_file::PassDescription`.
+#[derive(Clone, Debug)]
+pub struct PassConfig {
+    pub name: String,
+    /// Custom shader of the pipeline manager, the default shader for `None`.
+    pub shader: Option<String>,
+    pub clear_color: Option<Color>,
+    /// Drawn nodes (with their children), all nodes for `None`.
+    pub nodes: Option<Vec<NodeHandle>>,
+}
+
+impl Default for PassConfig {
+    fn default() -> Self {
+        Self {
+            name: "Render Pass".to_string(),
+            shader: None,
+            clear_color: Some(Color::BLACK),
+            nodes: None,
+        }
+    }
+}
+
+/// Consecutive instances drawn with the same mesh and material.
+struct Batch {
+    mesh: usize,
+    material: Option<usize>,
+    instances: Range<u32>,
+}
+
+/// Draws of a pass. Opaque batches are sorted by material and mesh, transparent ones
+/// back to front and drawn after them.
+#[derive(Default)]
+struct PassBatches {
+    opaque: Vec<Batch>,
+    transparent: Vec<Batch>,
+}
+
+/// The managers a frame draws with, borrowed from the app.
+pub struct FrameResources<'a> {
+    pub buffers: &'a BufferManager,
+    pub textures: &'a TextureManager,
+    pub materials: &'a MaterialManager,
+    pub lights: &'a LightManager,
+    pub pipeline: &'a PipelineManager,
+    /// Transparent draws are sorted by their distance along its view direction.
+    pub camera: &'a Camera,
+    pub sprites: &'a SpriteBatch,
+    pub text: &'a TextRenderer,
+    pub debug: &'a DebugRenderer,
+}
+
+/// An attachment of the scene passes.
+struct Target {
+    texture: Texture,
+    view: TextureView,
+}
+
+pub struct Renderer {
+    pub passes: Vec<PassConfig>,
+    /// Samples per pixel of the scene passes, one of `SAMPLE_COUNTS`.
+    pub sample_count: u32,
+    format: TextureFormat,
+    depth: Target,
+    // multisampled color target of the scene passes, resolved into the frame by the last one
+    msaa: Option<Target>,
+    instances: InstanceBuffer,
+}
 
 impl Renderer {
-    pub fn new() -> Self {
+    pub fn new(
+        device: &Device,
+        format: TextureFormat,
+        sample_count: u32,
+        (width, height): (u32, u32),
+    ) -> Self {
+        let instances = InstanceBuffer::new(device, 1024);
         info!("Renderer created successfully!");
-        Self {}
+        Self {
+            passes: vec![PassConfig::default()],
+            sample_count,
+            format,
+            depth: Self::create_target(device, DEPTH_FORMAT, sample_count, (width, height)),
+            msaa: (sample_count > 1)
+                .then(|| Self::create_target(device, format, sample_count, (width, height))),
+            instances,
+        }
+    }
+
+    /// The attachments have to match the surface size, so recreate them.
+    pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
+        self.set_sample_count(device, self.sample_count, width, height);
+    }
+
+    /// Recreates the attachments with `sample_count` samples per pixel, the pipeline
+    /// manager has to be rebuilt to match.
+    pub fn set_sample_count(
+        &mut self,
+        device: &Device,
+        sample_count: u32,
+        width: u32,
+        height: u32,
+    ) {
+        let size = (width, height);
+        self.sample_count = sample_count;
+        self.depth = Self::create_target(device, DEPTH_FORMAT, sample_count, size);
+        self.msaa = (sample_count > 1)
+            .then(|| Self::create_target(device, self.format, sample_count, size));
+    }
+
+    /// Frees the attachments and the instance buffer. The renderer can't be used
+    /// afterwards.
+    pub fn destroy(&self) {
+        self.depth.texture.destroy();
+        if let Some(msaa) = &self.msaa {
+            msaa.texture.destroy();
+        }
+        self.instances.buffer.destroy();
     }
 
+    fn create_target(
+        device: &Device,
+        format: TextureFormat,
+        sample_count: u32,
+        (width, height): (u32, u32),
+    ) -> Target {
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some(if format == DEPTH_FORMAT {
+                "Depth Texture"
+            } else {
+                "Multisampled Color Texture"
+            }),
+            size: Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Target { texture, view }
+    }
+
+    /// Renders the shadow maps of the shadow casting lights, then runs the passes in
+    /// order, each drawing its visible scene nodes, and finally draws the sprites, text
+    /// and debug lines. The instances of a node's mesh are transformed by the node, and
+    /// nodes sharing a mesh and material are drawn in one call. Nodes with a transparent material are drawn
+    /// after the opaque ones, farthest first. Every pass is timed by `profiler` when it
+    /// has timestamp queries.
     pub fn render(
-        &self,
+        &mut self,
         gpu: &GpuContext,
-        buffers: &BufferManager,
-        textures: &TextureManager,
-        pipeline: &PipelineManager,
-    ) -> Result<(), JsError> {
-        info!("Rendering webgpu");
-        let frame = gpu
-            .surface
-            .get_current_texture()
-            .map_err(|err| JsError::new(&format!("Unable to create frame to render. {:?}", err)))?;
+        resources: &FrameResources,
+        scene: &Scene,
+        profiler: &mut Profiler,
+    ) -> Result<()> {
+        log_once!(Level::Info, "Rendering the first frame");
+        let FrameResources {
+            buffers,
+            textures,
+            materials,
+            lights,
+            pipeline,
+            camera,
+            sprites,
+            text,
+            debug,
+        } = resources;
+        let view_ray = (camera.eye, (camera.target - camera.eye).normalize_or_zero());
+        // the instances of all passes share one buffer, written once per frame
+        let mut instances: Vec<InstanceRaw> = Vec::new();
+        let pass_batches: Vec<PassBatches> = self
+            .passes
+            .iter()
+            .map(|pass| {
+                let nodes = pass.nodes.as_deref();
+                Self::batch_draws(buffers, materials, scene, nodes, view_ray, &mut instances)
+            })
+            .collect::<Result<_>>()?;
+        // shadows are cast by every visible node, whatever pass draws it
+        let shadow_batches = if lights.active_shadow_layers.is_empty() {
+            PassBatches::default()
+        } else {
+            Self::batch_draws(buffers, materials, scene, None, view_ray, &mut instances)?
+        };
+        self.instances.write(&gpu.device, &gpu.queue, &instances);
+
+        let frame = match gpu.surface.get_current_texture() {
+            Ok(frame) => frame,
+            // the next frame renders into the reconfigured surface
+            Err(SurfaceError::Outdated | SurfaceError::Lost) => {
+                log_every!(
+                    1000.0,
+                    Level::Info,
+                    "Surface outdated or lost, reconfiguring it"
+                );
+                gpu.surface.configure(&gpu.device, &gpu.config);
+                return Ok(());
+            }
+            Err(SurfaceError::Timeout) => {
+                log_every!(
+                    1000.0,
+                    Level::Info,
+                    "Timed out waiting for a frame, skipping it"
+                );
+                return Ok(());
+            }
+            Err(err) => {
+                return Err(Error::Render(format!(
+                    "Unable to create frame to render. {:?}",
+                    err
+                )));
+            }
+        };
         let view = frame
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
@@ -35,31 +267,244 @@ impl Renderer {
             .create_command_encoder(&CommandEncoderDescriptor {
                 label: Some("Command Encoder"),
             });
-        // render pass
-        {
+        for &(layer, offset) in &lights.active_shadow_layers {
+            let mut shadow_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &lights.shadow_layer_views[layer],
+                    depth_ops: Some(Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: profiler.pass_timestamps("Shadow Pass"),
+                occlusion_query_set: None,
+            });
+            shadow_pass.set_pipeline(pipeline.shadow_pipeline().ok_or(Error::Destroyed)?);
+            shadow_pass.set_bind_group(0, &lights.shadow_view_bind_group, &[offset]);
+            shadow_pass.set_vertex_buffer(1, self.instances.buffer.slice(..));
+            for batch in shadow_batches
+                .opaque
+                .iter()
+                .chain(&shadow_batches.transparent)
+            {
+                Self::draw_batch(&mut shadow_pass, buffers, batch)?;
+            }
+        }
+        for (i, (pass, batches)) in self.passes.iter().zip(pass_batches).enumerate() {
+            // the first pass always clears, the frame starts out undefined
+            let load = match pass.clear_color {
+                Some(color) => wgpu::LoadOp::Clear(color),
+                None if i == 0 => wgpu::LoadOp::Clear(Color::BLACK),
+                None => wgpu::LoadOp::Load,
+            };
+            let is_last = i + 1 == self.passes.len();
+            // with multisampling the last pass resolves into the frame, and the samples
+            // aren't needed anymore
+            let (target, resolve_target) = match &self.msaa {
+                Some(msaa) => (&msaa.view, is_last.then_some(&view)),
+                None => (&view, None),
+            };
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
+                label: Some(&pass.name),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target,
+                    ops: Operations {
+                        load,
+                        store: if resolve_target.is_some() {
+                            wgpu::StoreOp::Discard
+                        } else {
+                            wgpu::StoreOp::Store
+                        },
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth.view,
+                    depth_ops: Some(Operations {
+                        load: if i == 0 {
+                            wgpu::LoadOp::Clear(1.0)
+                        } else {
+                            wgpu::LoadOp::Load
+                        },
+                        store: if is_last {
+                            wgpu::StoreOp::Discard
+                        } else {
+                            wgpu::StoreOp::Store
+                        },
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: profiler.pass_timestamps(&pass.name),
+                occlusion_query_set: None,
+            });
+            render_pass.set_bind_group(0, &buffers.uniform_manager.bind_group, &[]);
+            render_pass.set_bind_group(1, &textures.bind_group, &[]);
+            render_pass.set_bind_group(3, &lights.bind_group, &[]);
+            render_pass.set_vertex_buffer(1, self.instances.buffer.slice(..));
+            Self::draw_batches(&mut render_pass, resources, pass, &batches.opaque)?;
+            // after the opaque scene, so covered pixels fail the depth test
+            if i == 0 && lights.environment.is_some() {
+                render_pass.set_pipeline(pipeline.skybox_pipeline().ok_or(Error::Destroyed)?);
+                render_pass.set_bind_group(2, &materials.get(None)?.bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            }
+            // blended over everything opaque, including the sky
+            Self::draw_batches(&mut render_pass, resources, pass, &batches.transparent)?;
+        }
+        // sprites, text and debug lines go on top of everything the passes drew, single
+        // sampled into the resolved frame
+        if !sprites.is_empty() || !text.is_empty() || !debug.is_empty() {
+            let mut overlay_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Overlay Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                     view: &view,
                     resolve_target: None,
                     ops: Operations {
-                        load: wgpu::LoadOp::Clear(Color::BLACK),
+                        load: wgpu::LoadOp::Load,
                         store: wgpu::StoreOp::Store,
                     },
                 })],
                 depth_stencil_attachment: None,
-                timestamp_writes: None,
+                timestamp_writes: profiler.pass_timestamps("Overlay Pass"),
                 occlusion_query_set: None,
             });
-            render_pass.set_pipeline(&pipeline.pipeline);
-            render_pass.set_bind_group(0, &buffers.uniform_manager.bind_group, &[]);
-            render_pass.set_bind_group(1, &textures.bind_group, &[]);
-            render_pass.set_vertex_buffer(0, buffers.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(buffers.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..buffers.index_length as u32, 0, 0..1);
+            if !sprites.is_empty() {
+                sprites.draw(&mut overlay_pass, &buffers.uniform_manager.bind_group);
+            }
+            if !text.is_empty() {
+                text.draw(&mut overlay_pass, &buffers.uniform_manager.bind_group);
+            }
+            if !debug.is_empty() {
+                debug.draw(&mut overlay_pass, &buffers.uniform_manager.bind_group);
+            }
         }
+        profiler.resolve(&mut encoder);
         gpu.queue.submit(Some(encoder.finish()));
+        profiler.end_frame();
         frame.present();
         Ok(())
     }
+
+    fn draw_batches(
+        render_pass: &mut wgpu::RenderPass,
+        resources: &FrameResources,
+        pass: &PassConfig,
+        batches: &[Batch],
+    ) -> Result<()> {
+        // opaque batches are sorted by material, so state only changes between materials
+        let mut current_material = None;
+        for batch in batches.iter().filter(|batch| !batch.instances.is_empty()) {
+            if current_material != Some(batch.material) {
+                let material = resources.materials.get(batch.material)?;
+                let pipeline = resources
+                    .pipeline
+                    .get(
+                        pass.shader.as_deref(),
+                        batch.material.is_some(),
+                        material.blend_mode,
+                    )
+                    .ok_or_else(|| Error::Render(format!("Pass {} has no pipeline", pass.name)))?;
+                render_pass.set_pipeline(pipeline);
+                render_pass.set_bind_group(2, &material.bind_group, &[]);
+                current_material = Some(batch.material);
+            }
+            Self::draw_batch(render_pass, resources.buffers, batch)?;
+        }
+        Ok(())
+    }
+
+    fn draw_batch(
+        render_pass: &mut wgpu::RenderPass,
+        buffers: &BufferManager,
+        batch: &Batch,
+    ) -> Result<()> {
+        if batch.instances.is_empty() {
+            return Ok(());
+        }
+        let mesh = buffers.mesh(batch.mesh)?;
+        render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..mesh.index_length, 0, batch.instances.clone());
+        Ok(())
+    }
+
+    /// Appends the instances of the drawn nodes and returns the batches drawing them.
+    /// A node without a material uses the one of its mesh. Transparent instances are
+    /// sorted by their depth along `view_ray`, given as eye and normalized direction.
+    fn batch_draws(
+        buffers: &BufferManager,
+        materials: &MaterialManager,
+        scene: &Scene,
+        nodes: Option<&[NodeHandle]>,
+        (eye, forward): (Vec3, Vec3),
+        instances: &mut Vec<InstanceRaw>,
+    ) -> Result<PassBatches> {
+        let mut draws: Vec<(Option<usize>, Draw)> = scene
+            .draws(nodes)
+            .into_iter()
+            .filter_map(|draw| {
+                let mesh = buffers.mesh(draw.mesh).ok()?;
+                Some((draw.material.or(mesh.material), draw))
+            })
+            .collect();
+        draws.sort_by_key(|(material, draw)| (*material, draw.mesh));
+
+        let mut batches = PassBatches::default();
+        let mut transparent: Vec<(f32, usize, Option<usize>, InstanceRaw)> = Vec::new();
+        for (material, draw) in draws {
+            let mesh = buffers.mesh(draw.mesh)?;
+            let transformed = mesh
+                .instances
+                .iter()
+                .map(|instance| instance.transformed(draw.world));
+            if materials.get(material)?.blend_mode.is_transparent() {
+                transparent.extend(transformed.map(|instance| {
+                    let position = Vec3::from_slice(&instance.model[3][..3]);
+                    ((position - eye).dot(forward), draw.mesh, material, instance)
+                }));
+                continue;
+            }
+            let start = instances.len() as u32;
+            instances.extend(transformed);
+            Self::push_batch(
+                &mut batches.opaque,
+                draw.mesh,
+                material,
+                start..instances.len() as u32,
+            );
+        }
+        transparent.sort_by(|a, b| b.0.total_cmp(&a.0));
+        for (_, mesh, material, instance) in transparent {
+            let start = instances.len() as u32;
+            instances.push(instance);
+            Self::push_batch(&mut batches.transparent, mesh, material, start..start + 1);
+        }
+        Ok(batches)
+    }
+
+    // extends the last batch if it draws the same mesh and material right before `instances`
+    fn push_batch(
+        batches: &mut Vec<Batch>,
+        mesh: usize,
+        material: Option<usize>,
+        instances: Range<u32>,
+    ) {
+        match batches.last_mut() {
+            Some(batch)
+                if batch.mesh == mesh
+                    && batch.material == material
+                    && batch.instances.end == instances.start =>
+            {
+                batch.instances.end = instances.end
+            }
+            _ => batches.push(Batch {
+                mesh,
+                material,
+                instances,
+            }),
+        }
+    }
 }
@@ -0,0 +1,78 @@
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    rc::{Rc, Weak},
+};
+
+use wgpu::RenderPipeline;
+
+use crate::{error::Result, pipeline_manager::PipelineKey, texture_manager::TextureHolder};
+
+/// Pipelines and decoded textures of a device, shared by the canvases drawing with it so
+/// that each is built once. Both are only kept while a manager still uses them.
+#[derive(Default)]
+pub struct ResourceCache {
+    pipelines: HashMap<PipelineKey, Weak<RenderPipeline>>,
+    // by hash of the encoded image, the bytes are compared before a texture is reused
+    textures: HashMap<u64, Vec<CachedTexture>>,
+}
+
+struct CachedTexture {
+    data: Vec<u8>,
+    texture: Weak<TextureHolder>,
+}
+
+impl ResourceCache {
+    /// The pipeline built from `key`, created by `create` unless another pipeline
+    /// manager already holds it.
+    pub fn pipeline(
+        &mut self,
+        key: PipelineKey,
+        create: impl FnOnce() -> RenderPipeline,
+    ) -> Rc<RenderPipeline> {
+        if let Some(pipeline) = self.pipelines.get(&key).and_then(Weak::upgrade) {
+            return pipeline;
+        }
+        self.prune();
+        let pipeline = Rc::new(create());
+        self.pipelines.insert(key, Rc::downgrade(&pipeline));
+        pipeline
+    }
+
+    /// The texture decoded from the image `data`, created by `create` unless another
+    /// texture manager already holds it.
+    pub fn texture(
+        &mut self,
+        data: &[u8],
+        create: impl FnOnce() -> Result<TextureHolder>,
+    ) -> Result<Rc<TextureHolder>> {
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        let hash = hasher.finish();
+        if let Some(texture) = self
+            .textures
+            .get(&hash)
+            .and_then(|entries| entries.iter().find(|entry| entry.data == data))
+            .and_then(|entry| entry.texture.upgrade())
+        {
+            return Ok(texture);
+        }
+        self.prune();
+        let texture = Rc::new(create()?);
+        self.textures.entry(hash).or_default().push(CachedTexture {
+            data: data.to_vec(),
+            texture: Rc::downgrade(&texture),
+        });
+        Ok(texture)
+    }
+
+    /// Forgets the pipelines and textures no manager uses anymore.
+    pub fn prune(&mut self) {
+        self.pipelines
+            .retain(|_, pipeline| pipeline.strong_count() > 0);
+        self.textures.retain(|_, entries| {
+            entries.retain(|entry| entry.texture.strong_count() > 0);
+            !entries.is_empty()
+        });
+    }
+}
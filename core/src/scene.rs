@@ -0,0 +1,296 @@
+use std::collections::HashSet;
+
+use glam::{Mat4, Quat, Vec3};
+
+use crate::error::{Error, Result};
+
+pub type NodeHandle = u32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+        }
+    }
+}
+
+impl Transform {
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub local: Transform,
+    pub parent: Option<NodeHandle>,
+    pub children: Vec<NodeHandle>,
+    pub mesh: Option<usize>,
+    pub material: Option<usize>,
+    /// Hiding a node also hides its children.
+    pub visible: bool,
+    /// Local transform combined with all parents, updated by `Scene::update_world_transforms`.
+    pub world: Mat4,
+    world_visible: bool,
+}
+
+impl Node {
+    fn new(parent: Option<NodeHandle>) -> Self {
+        Self {
+            local: Transform::default(),
+            parent,
+            children: Vec::new(),
+            mesh: None,
+            material: None,
+            visible: true,
+            world: Mat4::IDENTITY,
+            world_visible: true,
+        }
+    }
+}
+
+/// A visible node with a mesh, as collected by `Scene::draws`.
+#[derive(Clone, Copy, Debug)]
+pub struct Draw {
+    pub mesh: usize,
+    pub material: Option<usize>,
+    pub world: Mat4,
+}
+
+/// Node hierarchy. Handles stay valid until their node is removed and are never reused.
+#[derive(Default)]
+pub struct Scene {
+    nodes: Vec<Option<Node>>,
+    roots: Vec<NodeHandle>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_node(&mut self, parent: Option<NodeHandle>) -> Result<NodeHandle> {
+        let handle = self.nodes.len() as NodeHandle;
+        match parent {
+            Some(parent) => self.node_mut(parent)?.children.push(handle),
+            None => self.roots.push(handle),
+        }
+        self.nodes.push(Some(Node::new(parent)));
+        Ok(handle)
+    }
+
+    /// Removes the node together with all its descendants.
+    pub fn remove_node(&mut self, handle: NodeHandle) -> Result<()> {
+        let parent = self.node(handle)?.parent;
+        self.detach(handle, parent);
+        let mut stack = vec![handle];
+        while let Some(handle) = stack.pop() {
+            if let Some(node) = self.nodes[handle as usize].take() {
+                stack.extend(node.children);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_parent(&mut self, handle: NodeHandle, parent: Option<NodeHandle>) -> Result<()> {
+        let old_parent = self.node(handle)?.parent;
+        if let Some(parent) = parent {
+            // walk up from the new parent to make sure no cycle gets created
+            let mut ancestor = Some(parent);
+            while let Some(current) = ancestor {
+                if current == handle {
+                    return Err(Error::InvalidInput(format!(
+                        "Node {parent} is a descendant of node {handle}"
+                    )));
+                }
+                ancestor = self.node(current)?.parent;
+            }
+        }
+        self.detach(handle, old_parent);
+        match parent {
+            Some(parent) => self.node_mut(parent)?.children.push(handle),
+            None => self.roots.push(handle),
+        }
+        self.node_mut(handle)?.parent = parent;
+        Ok(())
+    }
+
+    pub fn node(&self, handle: NodeHandle) -> Result<&Node> {
+        self.nodes
+            .get(handle as usize)
+            .and_then(Option::as_ref)
+            .ok_or_else(|| Error::invalid_handle("node", handle as usize))
+    }
+
+    pub fn node_mut(&mut self, handle: NodeHandle) -> Result<&mut Node> {
+        self.nodes
+            .get_mut(handle as usize)
+            .and_then(Option::as_mut)
+            .ok_or_else(|| Error::invalid_handle("node", handle as usize))
+    }
+
+    /// Propagates local transforms and visibility from the roots down.
+    pub fn update_world_transforms(&mut self) {
+        let mut stack: Vec<(NodeHandle, Mat4, bool)> = self
+            .roots
+            .iter()
+            .map(|&root| (root, Mat4::IDENTITY, true))
+            .collect();
+        while let Some((handle, parent_world, parent_visible)) = stack.pop() {
+            let Some(node) = self.nodes[handle as usize].as_mut() else {
+                continue;
+            };
+            node.world = parent_world * node.local.matrix();
+            node.world_visible = parent_visible && node.visible;
+            stack.extend(
+                node.children
+                    .iter()
+                    .map(|&child| (child, node.world, node.world_visible)),
+            );
+        }
+    }
+
+    /// Visible nodes with a mesh. With `subtrees` only those nodes and their
+    /// descendants are considered.
+    pub fn draws(&self, subtrees: Option<&[NodeHandle]>) -> Vec<Draw> {
+        let included = subtrees.map(|subtrees| {
+            let mut included = HashSet::new();
+            let mut stack = subtrees.to_vec();
+            while let Some(handle) = stack.pop() {
+                if let Ok(node) = self.node(handle)
+                    && included.insert(handle)
+                {
+                    stack.extend(&node.children);
+                }
+            }
+            included
+        });
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(handle, node)| {
+                let node = node.as_ref()?;
+                if !node.world_visible {
+                    return None;
+                }
+                if let Some(included) = &included
+                    && !included.contains(&(handle as NodeHandle))
+                {
+                    return None;
+                }
+                Some(Draw {
+                    mesh: node.mesh?,
+                    material: node.material,
+                    world: node.world,
+                })
+            })
+            .collect()
+    }
+
+    fn detach(&mut self, handle: NodeHandle, parent: Option<NodeHandle>) {
+        let siblings = match parent.and_then(|parent| self.nodes[parent as usize].as_mut()) {
+            Some(parent) => &mut parent.children,
+            None => &mut self.roots,
+        };
+        siblings.retain(|&sibling| sibling != handle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translated(scene: &mut Scene, handle: NodeHandle, translation: Vec3) {
+        scene.node_mut(handle).unwrap().local.translation = translation;
+    }
+
+    fn world_position(scene: &Scene, handle: NodeHandle) -> Vec3 {
+        scene
+            .node(handle)
+            .unwrap()
+            .world
+            .transform_point3(Vec3::ZERO)
+    }
+
+    #[test]
+    fn world_transforms() {
+        let mut scene = Scene::new();
+        let root = scene.add_node(None).unwrap();
+        let child = scene.add_node(Some(root)).unwrap();
+        let grandchild = scene.add_node(Some(child)).unwrap();
+        translated(&mut scene, root, Vec3::X);
+        scene.node_mut(child).unwrap().local = Transform {
+            translation: Vec3::Y,
+            rotation: Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+            scale: Vec3::splat(2.0),
+        };
+        translated(&mut scene, grandchild, Vec3::X);
+        scene.update_world_transforms();
+
+        assert!(world_position(&scene, root).abs_diff_eq(Vec3::X, 1e-6));
+        assert!(world_position(&scene, child).abs_diff_eq(Vec3::new(1.0, 1.0, 0.0), 1e-6));
+        // scaled by 2 and rotated onto +Y by the child
+        assert!(world_position(&scene, grandchild).abs_diff_eq(Vec3::new(1.0, 3.0, 0.0), 1e-6));
+    }
+
+    #[test]
+    fn reparenting() {
+        let mut scene = Scene::new();
+        let a = scene.add_node(None).unwrap();
+        let b = scene.add_node(None).unwrap();
+        let child = scene.add_node(Some(a)).unwrap();
+        translated(&mut scene, a, Vec3::X);
+        translated(&mut scene, b, Vec3::Z);
+        scene.set_parent(child, Some(b)).unwrap();
+        scene.update_world_transforms();
+
+        assert!(world_position(&scene, child).abs_diff_eq(Vec3::Z, 1e-6));
+        assert!(scene.node(a).unwrap().children.is_empty());
+        assert!(matches!(
+            scene.set_parent(b, Some(child)),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn hidden_subtrees() {
+        let mut scene = Scene::new();
+        let root = scene.add_node(None).unwrap();
+        let child = scene.add_node(Some(root)).unwrap();
+        let other = scene.add_node(None).unwrap();
+        for (handle, mesh) in [(root, 0), (child, 1), (other, 2)] {
+            scene.node_mut(handle).unwrap().mesh = Some(mesh);
+        }
+        scene.update_world_transforms();
+        assert_eq!(scene.draws(None).len(), 3);
+        assert_eq!(scene.draws(Some(&[root])).len(), 2);
+
+        scene.node_mut(root).unwrap().visible = false;
+        scene.update_world_transforms();
+        let draws = scene.draws(None);
+        assert_eq!(draws.len(), 1);
+        assert_eq!(draws[0].mesh, 2);
+    }
+
+    #[test]
+    fn removed_nodes() {
+        let mut scene = Scene::new();
+        let root = scene.add_node(None).unwrap();
+        let child = scene.add_node(Some(root)).unwrap();
+        scene.remove_node(root).unwrap();
+        scene.update_world_transforms();
+        assert!(scene.node(child).is_err());
+        assert!(scene.draws(None).is_empty());
+        // handles aren't reused
+        assert_eq!(scene.add_node(None).unwrap(), 2);
+    }
+}
@@ -0,0 +1,735 @@
+use std::collections::{HashMap, HashSet};
+
+use glam::{Quat, Vec3};
+use log::info;
+use serde::{Deserialize, Serialize};
+use wgpu::Color;
+
+use crate::{
+    camera::{CameraManager, Projection},
+    error::{Error, Result},
+    geometry::Primitive,
+    material::{BlendMode, MaterialData, MaterialFactors, MaterialImage, MaterialTexture},
+    mesh::MeshData,
+    mesh_loader::{self, MeshFormat},
+    renderer::PassConfig,
+    scene::{NodeHandle, Scene},
+    texture_manager::{SamplerSettings, TextureSource},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SceneFormat {
+    Json,
+    Ron,
+}
+
+impl SceneFormat {
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension
+            .trim_start_matches('.')
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "json" => Some(Self::Json),
+            "ron" => Some(Self::Ron),
+            _ => None,
+        }
+    }
+}
+
+/// Everything needed to rebuild an app configuration. Binary assets (images, mesh
+/// files, shader files) are referenced by `path` and supplied separately when loading.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneDescription {
+    pub shaders: Vec<ShaderDescription>,
+    pub textures: Vec<TextureDescription>,
+    pub materials: Vec<MaterialDescription>,
+    pub meshes: Vec<MeshDescription>,
+    pub nodes: Vec<NodeDescription>,
+    pub camera: Option<CameraDescription>,
+    pub uniforms: UniformValues,
+    pub passes: Vec<PassDescription>,
+}
+
+/// WGSL shader given inline as `code` or as an asset `path`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShaderDescription {
+    pub name: String,
+    pub code: Option<String>,
+    pub path: Option<String>,
+}
+
+/// Texture bound at index `i` of the texture bind group, in file order.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextureDescription {
+    pub path: String,
+    pub sampler: SamplerSettings,
+}
+
+/// Material whose textures are image asset paths, see `material::MaterialData`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialDescription {
+    pub name: String,
+    pub factors: MaterialFactors,
+    pub base_color_texture: Option<String>,
+    pub metallic_roughness_texture: Option<String>,
+    pub normal_texture: Option<String>,
+    pub occlusion_texture: Option<String>,
+    pub emissive_texture: Option<String>,
+    pub sampler: SamplerSettings,
+    pub blend_mode: BlendMode,
+}
+
+/// Mesh loaded from an OBJ/glTF asset `path` or generated from a `primitive`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MeshDescription {
+    pub name: String,
+    pub path: Option<String>,
+    /// File extension, defaults to the extension of `path`.
+    pub format: Option<String>,
+    /// MTL file of an OBJ mesh.
+    pub material_path: Option<String>,
+    pub primitive: Option<Primitive>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodeDescription {
+    pub name: String,
+    pub parent: Option<String>,
+    pub mesh: Option<String>,
+    /// Overrides the material of the mesh.
+    pub material: Option<String>,
+    pub translation: [f32; 3],
+    /// Quaternion as x, y, z, w.
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+    pub visible: bool,
+}
+
+impl Default for NodeDescription {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            parent: None,
+            mesh: None,
+            material: None,
+            translation: [0.0, 0.0, 0.0],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            scale: [1.0, 1.0, 1.0],
+            visible: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProjectionDescription {
+    /// `fovy` is in degrees.
+    Perspective { fovy: f32, znear: f32, zfar: f32 },
+    Orthographic {
+        width: Option<f32>,
+        height: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CameraDescription {
+    pub eye: [f32; 3],
+    pub target: [f32; 3],
+    #[serde(default = "CameraDescription::default_up")]
+    pub up: [f32; 3],
+    pub projection: ProjectionDescription,
+    /// `"none"`, `"orbit"` or `"fly"`.
+    #[serde(default = "CameraDescription::default_controller")]
+    pub controller: String,
+}
+
+impl CameraDescription {
+    fn default_up() -> [f32; 3] {
+        [0.0, 1.0, 0.0]
+    }
+
+    fn default_controller() -> String {
+        "none".to_string()
+    }
+
+    pub fn from_camera(camera: &CameraManager) -> Self {
+        let projection = match camera.camera.projection {
+            Projection::Perspective { fovy, znear, zfar } => ProjectionDescription::Perspective {
+                fovy: fovy.to_degrees(),
+                znear,
+                zfar,
+            },
+            Projection::Orthographic {
+                width,
+                height,
+                znear,
+                zfar,
+            } => ProjectionDescription::Orthographic {
+                width,
+                height,
+                znear,
+                zfar,
+            },
+        };
+        Self {
+            eye: camera.camera.eye.to_array(),
+            target: camera.camera.target.to_array(),
+            up: camera.camera.up.to_array(),
+            projection,
+            controller: camera.controller_name().to_string(),
+        }
+    }
+
+    pub fn apply(&self, camera: &mut CameraManager) -> Result<()> {
+        camera.camera.eye = Vec3::from(self.eye);
+        camera.camera.target = Vec3::from(self.target);
+        camera.camera.up = Vec3::from(self.up);
+        camera.camera.projection = match self.projection {
+            ProjectionDescription::Perspective { fovy, znear, zfar } => Projection::Perspective {
+                fovy: fovy.to_radians(),
+                znear,
+                zfar,
+            },
+            ProjectionDescription::Orthographic {
+                width,
+                height,
+                znear,
+                zfar,
+            } => Projection::Orthographic {
+                width,
+                height,
+                znear,
+                zfar,
+            },
+        };
+        camera
+            .set_controller(&self.controller)
+            .map_err(Error::InvalidInput)
+    }
+}
+
+/// Values of the per-frame uniforms.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UniformValues {
+    pub time: f32,
+    pub delta_time: f32,
+    pub mouse: [f32; 2],
+}
+
+/// Render pass drawing the scene with a shader. Passes run in file order; a pass
+/// without `clear_color` draws over the previous one.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PassDescription {
+    pub name: String,
+    /// Name of one of the scene shaders, the default shader when not set.
+    pub shader: Option<String>,
+    pub clear_color: Option<[f64; 4]>,
+    /// Nodes drawn (with their children), all nodes when not set.
+    pub nodes: Option<Vec<String>>,
+}
+
+/// CPU side of a loaded scene description, ready to be swapped into the app.
+pub struct LoadedScene {
+    pub shaders: Vec<(String, String)>,
+    pub textures: Vec<TextureSource>,
+    /// Have to be added to the material manager in order, starting at the `first_material`
+    /// handle.
+    pub materials: Vec<MaterialData>,
+    /// Have to be added to the buffer manager in order, starting at the `first_mesh` handle.
+    pub meshes: Vec<MeshData>,
+    pub scene: Scene,
+    pub node_handles: HashMap<String, NodeHandle>,
+    pub passes: Vec<PassConfig>,
+}
+
+impl SceneDescription {
+    /// Nodes, meshes and materials are referenced by name, so names have to be unique.
+    pub fn parse(text: &str, format: SceneFormat) -> Result<Self> {
+        let description: Self = match format {
+            SceneFormat::Json => serde_json::from_str(text)
+                .map_err(|err| Error::Asset(format!("Invalid JSON scene: {err}")))?,
+            SceneFormat::Ron => ron_options()
+                .from_str(text)
+                .map_err(|err| Error::Asset(format!("Invalid RON scene: {err}")))?,
+        };
+        unique_names("node", description.nodes.iter().map(|node| &node.name))?;
+        unique_names("mesh", description.meshes.iter().map(|mesh| &mesh.name))?;
+        unique_names(
+            "material",
+            description.materials.iter().map(|material| &material.name),
+        )?;
+        Ok(description)
+    }
+
+    pub fn serialize(&self, format: SceneFormat) -> Result<String> {
+        match format {
+            SceneFormat::Json => serde_json::to_string_pretty(self)
+                .map_err(|err| Error::Asset(format!("Failed to write JSON scene: {err}"))),
+            SceneFormat::Ron => ron_options()
+                .to_string_pretty(self, ron::ser::PrettyConfig::default())
+                .map_err(|err| Error::Asset(format!("Failed to write RON scene: {err}"))),
+        }
+    }
+
+    /// Resolves the assets and builds the scene without touching the GPU, so a failed
+    /// load leaves the current configuration intact. The meshes and materials of the
+    /// scene get the handles from `first_mesh` and `first_material` on.
+    pub fn load(
+        &self,
+        (first_mesh, first_material): (usize, usize),
+        assets: impl Fn(&str) -> Option<Vec<u8>>,
+    ) -> Result<LoadedScene> {
+        let asset = |path: &str| {
+            assets(path).ok_or_else(|| Error::Asset(format!("Missing scene asset: {path}")))
+        };
+
+        let shaders = self
+            .shaders
+            .iter()
+            .map(|shader| {
+                let code = match (&shader.code, &shader.path) {
+                    (Some(code), _) => code.clone(),
+                    (None, Some(path)) => String::from_utf8(asset(path)?)
+                        .map_err(|_| Error::Asset(format!("Shader {path} is not valid UTF-8")))?,
+                    (None, None) => {
+                        return Err(Error::Asset(format!(
+                            "Shader {} needs either code or a path",
+                            shader.name
+                        )));
+                    }
+                };
+                Ok((shader.name.clone(), code))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let textures = self
+            .textures
+            .iter()
+            .map(|texture| {
+                Ok(TextureSource {
+                    data: asset(&texture.path)?,
+                    sampler: texture.sampler,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut materials = self
+            .materials
+            .iter()
+            .map(|material| {
+                let texture = |path: &Option<String>| -> Result<_> {
+                    let Some(path) = path else {
+                        return Ok(None);
+                    };
+                    let image = MaterialImage::decode(&asset(path)?)?;
+                    Ok(Some(MaterialTexture::new(image)))
+                };
+                Ok(MaterialData {
+                    name: Some(material.name.clone()),
+                    factors: material.factors,
+                    base_color_texture: texture(&material.base_color_texture)?,
+                    metallic_roughness_texture: texture(&material.metallic_roughness_texture)?,
+                    normal_texture: texture(&material.normal_texture)?,
+                    occlusion_texture: texture(&material.occlusion_texture)?,
+                    emissive_texture: texture(&material.emissive_texture)?,
+                    sampler: material.sampler,
+                    blend_mode: material.blend_mode,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut mesh_data = Vec::new();
+        for mesh in &self.meshes {
+            let data = match (&mesh.primitive, &mesh.path) {
+                (Some(primitive), _) => vec![primitive.mesh()],
+                (None, Some(path)) => {
+                    let extension = mesh
+                        .format
+                        .as_deref()
+                        .or_else(|| path.rsplit_once('.').map(|(_, extension)| extension))
+                        .unwrap_or_default();
+                    let format = MeshFormat::from_extension(extension).ok_or_else(|| {
+                        Error::Asset(format!("Unsupported mesh format of {path}"))
+                    })?;
+                    let material_data = mesh.material_path.as_deref().map(asset).transpose()?;
+                    let mut model =
+                        mesh_loader::load_mesh(format, &asset(path)?, material_data.as_deref())?;
+                    // the file's materials follow the ones already collected
+                    let offset = first_material + materials.len();
+                    for mesh in &mut model.meshes {
+                        mesh.material = mesh.material.map(|material| offset + material);
+                    }
+                    materials.extend(model.materials);
+                    model.meshes
+                }
+                (None, None) => {
+                    return Err(Error::Asset(format!(
+                        "Mesh {} needs either a primitive or a path",
+                        mesh.name
+                    )));
+                }
+            };
+            mesh_data.push((mesh.name.as_str(), data));
+        }
+
+        let mut scene = Scene::new();
+        let mut node_handles = HashMap::new();
+        for node in &self.nodes {
+            let parent = node
+                .parent
+                .as_ref()
+                .map(|parent| {
+                    node_handles.get(parent).copied().ok_or_else(|| {
+                        Error::Asset(format!(
+                            "Parent {parent} of node {} has to be declared before it",
+                            node.name
+                        ))
+                    })
+                })
+                .transpose()?;
+            let handle = scene.add_node(parent)?;
+            let scene_node = scene.node_mut(handle)?;
+            scene_node.local.translation = Vec3::from(node.translation);
+            scene_node.local.rotation = Quat::from_array(node.rotation).normalize();
+            scene_node.local.scale = Vec3::from(node.scale);
+            scene_node.visible = node.visible;
+            if let Some(material) = &node.material {
+                let index = self
+                    .materials
+                    .iter()
+                    .position(|candidate| &candidate.name == material)
+                    .ok_or_else(|| {
+                        Error::Asset(format!(
+                            "Node {} uses unknown material {material}",
+                            node.name
+                        ))
+                    })?;
+                scene_node.material = Some(first_material + index);
+            }
+            if let Some(mesh) = &node.mesh
+                && !mesh_data.iter().any(|(name, _)| name == mesh)
+            {
+                return Err(Error::Asset(format!(
+                    "Node {} uses unknown mesh {mesh}",
+                    node.name
+                )));
+            }
+            node_handles.insert(node.name.clone(), handle);
+        }
+
+        let mut meshes = Vec::new();
+        for (name, data) in mesh_data {
+            let handles: Vec<usize> = (0..data.len())
+                .map(|i| first_mesh + meshes.len() + i)
+                .collect();
+            meshes.extend(data);
+            for node in self
+                .nodes
+                .iter()
+                .filter(|node| node.mesh.as_deref() == Some(name))
+            {
+                let handle = node_handles[&node.name];
+                scene.node_mut(handle)?.mesh = handles.first().copied();
+                // files with several meshes get a child node for each additional one
+                for &mesh in handles.iter().skip(1) {
+                    let child = scene.add_node(Some(handle))?;
+                    scene.node_mut(child)?.mesh = Some(mesh);
+                }
+            }
+        }
+
+        let passes = self
+            .passes
+            .iter()
+            .map(|pass| {
+                if let Some(shader) = &pass.shader
+                    && !self
+                        .shaders
+                        .iter()
+                        .any(|candidate| &candidate.name == shader)
+                {
+                    return Err(Error::Asset(format!(
+                        "Pass {} uses unknown shader {shader}",
+                        pass.name
+                    )));
+                }
+                let nodes = pass
+                    .nodes
+                    .as_ref()
+                    .map(|nodes| {
+                        nodes
+                            .iter()
+                            .map(|node| {
+                                node_handles.get(node).copied().ok_or_else(|| {
+                                    Error::Asset(format!(
+                                        "Pass {} uses unknown node {node}",
+                                        pass.name
+                                    ))
+                                })
+                            })
+                            .collect::<Result<Vec<_>>>()
+                    })
+                    .transpose()?;
+                Ok(PassConfig {
+                    name: pass.name.clone(),
+                    shader: pass.shader.clone(),
+                    clear_color: pass.clear_color.map(|[r, g, b, a]| Color { r, g, b, a }),
+                    nodes,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        info!(
+            "Loaded scene with {} shader(s), {} texture(s), {} material(s), {} mesh(es), {} node(s) and {} pass(es)",
+            shaders.len(),
+            textures.len(),
+            materials.len(),
+            self.meshes.len(),
+            self.nodes.len(),
+            passes.len()
+        );
+        Ok(LoadedScene {
+            shaders,
+            textures,
+            materials,
+            meshes,
+            scene,
+            node_handles,
+            passes,
+        })
+    }
+
+    /// Writes the current state of the nodes loaded from this description back into it.
+    pub fn update_nodes(&mut self, scene: &Scene, node_handles: &HashMap<String, NodeHandle>) {
+        for node in &mut self.nodes {
+            let Some(scene_node) = node_handles
+                .get(&node.name)
+                .and_then(|&handle| scene.node(handle).ok())
+            else {
+                continue;
+            };
+            node.translation = scene_node.local.translation.to_array();
+            node.rotation = scene_node.local.rotation.to_array();
+            node.scale = scene_node.local.scale.to_array();
+            node.visible = scene_node.visible;
+        }
+    }
+}
+
+fn unique_names<'a>(kind: &str, names: impl Iterator<Item = &'a String>) -> Result<()> {
+    let mut seen = HashSet::new();
+    for name in names {
+        if !seen.insert(name) {
+            return Err(Error::Asset(format!("Duplicate {kind} name {name}")));
+        }
+    }
+    Ok(())
+}
+
+// optional values can be written without `Some(..)` in RON files
+fn ron_options() -> ron::Options {
+    ron::Options::default().with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn description() -> SceneDescription {
+        SceneDescription {
+            shaders: vec![ShaderDescription {
+                name: "Unlit".to_string(),
+                code: Some("@vertex fn vs_main() {}".to_string()),
+                path: None,
+            }],
+            materials: vec![MaterialDescription {
+                name: "Red".to_string(),
+                factors: MaterialFactors {
+                    base_color: [1.0, 0.0, 0.0, 1.0],
+                    ..Default::default()
+                },
+                blend_mode: BlendMode::Alpha,
+                ..Default::default()
+            }],
+            meshes: vec![MeshDescription {
+                name: "Box".to_string(),
+                primitive: Some(Primitive::Cube { size: Some(2.0) }),
+                ..Default::default()
+            }],
+            nodes: vec![
+                NodeDescription {
+                    name: "Root".to_string(),
+                    translation: [0.0, 1.0, 0.0],
+                    ..Default::default()
+                },
+                NodeDescription {
+                    name: "Child".to_string(),
+                    parent: Some("Root".to_string()),
+                    mesh: Some("Box".to_string()),
+                    material: Some("Red".to_string()),
+                    visible: false,
+                    ..Default::default()
+                },
+            ],
+            camera: Some(CameraDescription {
+                eye: [0.0, 2.0, 5.0],
+                target: [0.0, 0.0, 0.0],
+                up: CameraDescription::default_up(),
+                projection: ProjectionDescription::Orthographic {
+                    width: None,
+                    height: 4.0,
+                    znear: 0.0,
+                    zfar: 10.0,
+                },
+                controller: "orbit".to_string(),
+            }),
+            uniforms: UniformValues {
+                time: 1.5,
+                ..Default::default()
+            },
+            passes: vec![PassDescription {
+                name: "Main".to_string(),
+                shader: Some("Unlit".to_string()),
+                clear_color: Some([0.1, 0.2, 0.3, 1.0]),
+                nodes: Some(vec!["Root".to_string()]),
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn round_trip(format: SceneFormat) {
+        let text = description().serialize(format).unwrap();
+        let parsed = SceneDescription::parse(&text, format).unwrap();
+        assert_eq!(parsed.serialize(format).unwrap(), text);
+        assert_eq!(parsed.nodes[1].parent.as_deref(), Some("Root"));
+        assert_eq!(parsed.passes[0].clear_color, Some([0.1, 0.2, 0.3, 1.0]));
+        assert!(matches!(
+            parsed.meshes[0].primitive,
+            Some(Primitive::Cube { size: Some(2.0) })
+        ));
+    }
+
+    #[test]
+    fn json_round_trip() {
+        round_trip(SceneFormat::Json);
+    }
+
+    #[test]
+    fn ron_round_trip() {
+        round_trip(SceneFormat::Ron);
+    }
+
+    #[test]
+    fn ron_implicit_some() {
+        let text = r#"(
+            nodes: [(name: "Root"), (name: "Child", parent: "Root", mesh: "Box")],
+            meshes: [(name: "Box", path: "box.obj", format: "obj")],
+            camera: (
+                eye: (0.0, 0.0, 5.0),
+                target: (0.0, 0.0, 0.0),
+                projection: perspective(fovy: 45.0, znear: 0.1, zfar: 100.0),
+            ),
+            passes: [(name: "Main", clear_color: (0.0, 0.0, 0.0, 1.0), nodes: ["Child"])],
+        )"#;
+        let description = SceneDescription::parse(text, SceneFormat::Ron).unwrap();
+        assert_eq!(description.nodes[1].parent.as_deref(), Some("Root"));
+        assert_eq!(description.meshes[0].path.as_deref(), Some("box.obj"));
+        let camera = description.camera.as_ref().unwrap();
+        assert_eq!(camera.controller, "none");
+        assert_eq!(
+            description.passes[0].nodes.as_deref(),
+            Some(&["Child".to_string()][..])
+        );
+    }
+
+    #[test]
+    fn load() {
+        let loaded = description().load((5, 3), |_| None).unwrap();
+        assert_eq!(loaded.meshes.len(), 1);
+        assert_eq!(loaded.materials.len(), 1);
+        assert_eq!(loaded.shaders[0].0, "Unlit");
+        let root = loaded.scene.node(loaded.node_handles["Root"]).unwrap();
+        assert_eq!(root.local.translation, Vec3::Y);
+        let child = loaded.scene.node(loaded.node_handles["Child"]).unwrap();
+        assert_eq!(child.parent, Some(loaded.node_handles["Root"]));
+        assert_eq!(child.mesh, Some(5));
+        assert_eq!(child.material, Some(3));
+        assert!(!child.visible);
+        assert_eq!(
+            loaded.passes[0].nodes,
+            Some(vec![loaded.node_handles["Root"]])
+        );
+    }
+
+    #[test]
+    fn update_nodes() {
+        let mut description = description();
+        let mut loaded = description.load((1, 0), |_| None).unwrap();
+        let root = loaded.node_handles["Root"];
+        loaded.scene.node_mut(root).unwrap().local.scale = Vec3::splat(2.0);
+        description.update_nodes(&loaded.scene, &loaded.node_handles);
+        assert_eq!(description.nodes[0].scale, [2.0, 2.0, 2.0]);
+    }
+
+    #[test]
+    fn unknown_material() {
+        let mut description = description();
+        description.nodes[1].material = Some("Blue".to_string());
+        let Err(err) = description.load((1, 0), |_| None) else {
+            panic!("loaded a node with an unknown material");
+        };
+        assert!(err.to_string().contains("unknown material Blue"), "{err}");
+    }
+
+    #[test]
+    fn unknown_mesh() {
+        let mut description = description();
+        description.nodes[1].mesh = Some("Sphere".to_string());
+        let Err(err) = description.load((1, 0), |_| None) else {
+            panic!("loaded a node with an unknown mesh");
+        };
+        assert!(err.to_string().contains("unknown mesh Sphere"), "{err}");
+    }
+
+    #[test]
+    fn missing_mesh_asset() {
+        let mut description = description();
+        description.meshes[0] = MeshDescription {
+            name: "Box".to_string(),
+            path: Some("box.obj".to_string()),
+            ..Default::default()
+        };
+        let Err(err) = description.load((1, 0), |_| None) else {
+            panic!("loaded a mesh without its asset");
+        };
+        assert!(
+            err.to_string().contains("Missing scene asset: box.obj"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn duplicate_node_names() {
+        let mut description = description();
+        description.nodes[1].name = "Root".to_string();
+        let text = description.serialize(SceneFormat::Json).unwrap();
+        let Err(err) = SceneDescription::parse(&text, SceneFormat::Json) else {
+            panic!("parsed duplicate node names");
+        };
+        assert!(
+            err.to_string().contains("Duplicate node name Root"),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,46 @@
+// Debug lines, in world space through the camera or in pixels from the top left
+// corner of the canvas.
+
+struct DebugVertex {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+struct ProgramUniform {
+    screen_width: f32,
+    screen_height: f32
+}
+
+struct CameraUniform {
+    view: mat4x4<f32>,
+    projection: mat4x4<f32>,
+    view_projection: mat4x4<f32>,
+    position: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> program: ProgramUniform;
+@group(0) @binding(2)
+var<uniform> camera: CameraUniform;
+
+@vertex
+fn vs_world(in: DebugVertex) -> VertexOutput {
+    return VertexOutput(camera.view_projection * vec4<f32>(in.position, 1.0), in.color);
+}
+
+@vertex
+fn vs_screen(in: DebugVertex) -> VertexOutput {
+    let screen = vec2<f32>(program.screen_width, program.screen_height);
+    let ndc = in.position.xy / screen * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0);
+    return VertexOutput(vec4<f32>(ndc, 0.0, 1.0), in.color);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
@@ -1,23 +1,50 @@
 struct VertexInput {
     @location(0) pos: vec3<f32>,
     @location(1) color: vec3<f32>,
-    @location(2) tex_pos: vec2<f32>
+    @location(2) tex_pos: vec2<f32>,
+    @location(3) normal: vec3<f32>,
+    @location(4) tangent: vec4<f32>,
+    @location(5) tex_pos_1: vec2<f32>,
+}
+
+struct InstanceInput {
+    @location(6) model_0: vec4<f32>,
+    @location(7) model_1: vec4<f32>,
+    @location(8) model_2: vec4<f32>,
+    @location(9) model_3: vec4<f32>,
+    @location(10) color: vec4<f32>,
+    @location(11) tex_index: u32,
 }
 
 struct VertexOutput {
     @builtin(position) pos: vec4<f32>,
     @location(0) color: vec3<f32>,
-    @location(1) tex_pos: vec2<f32>
+    @location(1) tex_pos: vec2<f32>,
+    @location(2) tint: vec4<f32>,
+    @location(3) @interpolate(flat) tex_index: u32,
 }
 
 // ===== Vertex shader =====
 
+struct CameraUniform {
+    view: mat4x4<f32>,
+    projection: mat4x4<f32>,
+    view_projection: mat4x4<f32>,
+    position: vec4<f32>,
+}
+
+@group(0) @binding(2)
+var<uniform> camera: CameraUniform;
+
 @vertex
-fn vs_main(in: VertexInput) -> VertexOutput {
+fn vs_main(in: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
     var output = VertexOutput(
-        vec4<f32>(in.pos, 1.0),
+        camera.view_projection * model * vec4<f32>(in.pos, 1.0),
         in.color,
-        in.tex_pos
+        in.tex_pos,
+        instance.color,
+        instance.tex_index
     );
     return output;
 }
@@ -40,11 +67,8 @@ var<uniform> program_uniform: ProgramUniform;
 @group(0) @binding(1)
 var<uniform> per_frame_uniform: PerFrameUniform;
 
-@group(1) @binding(0)
-var tex_sampler: sampler;
-
-@group(1) @binding(1)
-var tex: texture_2d<f32>;
+// texture bindings and `sample_texture(index, uv)`, generated by the pipeline manager
+// {{TEXTURES}}
 
 const circle_radius: f32 = 100.0;
 
@@ -57,7 +81,7 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
         in.color.b,
         1.0
     );
-    let sampled = textureSample(tex, tex_sampler, in.tex_pos);
+    let sampled = sample_texture(in.tex_index, in.tex_pos) * in.tint;
     let dist = distance(in.pos.xy, per_frame_uniform.mouse);
 
     let edge_width = 5.0;
@@ -0,0 +1,186 @@
+// Compute passes baking image based lighting from an equirectangular HDR image.
+// Every entry point writes one texel per invocation, with the cube face in z.
+
+struct BakeParams {
+    roughness: f32,
+    sample_count: u32,
+    // face size of the environment cube
+    source_size: f32,
+    // mip of the environment cube the irradiance is gathered from
+    source_lod: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: BakeParams;
+@group(0) @binding(1)
+var source_sampler: sampler;
+@group(0) @binding(2)
+var equirect: texture_2d<f32>;
+@group(0) @binding(3)
+var source_cube: texture_cube<f32>;
+@group(0) @binding(4)
+var source_faces: texture_2d_array<f32>;
+@group(0) @binding(5)
+var target_faces: texture_storage_2d_array<rgba16float, write>;
+@group(0) @binding(6)
+var brdf_target: texture_storage_2d<rgba16float, write>;
+
+const PI: f32 = 3.14159265359;
+
+// direction through texel `id` of a cube face, in the face order and orientation of cube textures
+fn cube_direction(id: vec3<u32>, size: u32) -> vec3<f32> {
+    let uv = (vec2<f32>(id.xy) + 0.5) / f32(size) * 2.0 - 1.0;
+    var direction: vec3<f32>;
+    switch id.z {
+        case 0u: { direction = vec3<f32>(1.0, -uv.y, -uv.x); }
+        case 1u: { direction = vec3<f32>(-1.0, -uv.y, uv.x); }
+        case 2u: { direction = vec3<f32>(uv.x, 1.0, uv.y); }
+        case 3u: { direction = vec3<f32>(uv.x, -1.0, -uv.y); }
+        case 4u: { direction = vec3<f32>(uv.x, -uv.y, 1.0); }
+        default: { direction = vec3<f32>(-uv.x, -uv.y, -1.0); }
+    }
+    return normalize(direction);
+}
+
+// rotates `v` from the tangent space around `n` to world space
+fn tangent_to_world(v: vec3<f32>, n: vec3<f32>) -> vec3<f32> {
+    let up = select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 0.0, 1.0), abs(n.z) < 0.999);
+    let tangent = normalize(cross(up, n));
+    let bitangent = cross(n, tangent);
+    return normalize(tangent * v.x + bitangent * v.y + n * v.z);
+}
+
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(i) / f32(count), f32(reverseBits(i)) * 2.3283064365386963e-10);
+}
+
+// half vector distributed like the GGX lobe of perceptual `roughness` around `n`
+fn importance_sample_ggx(xi: vec2<f32>, n: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return tangent_to_world(vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta), n);
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a2 = pow(roughness, 4.0);
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn equirect_to_cube(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(target_faces).x;
+    if id.x >= size || id.y >= size {
+        return;
+    }
+    let direction = cube_direction(id, size);
+    let uv = vec2<f32>(
+        atan2(direction.z, direction.x) / (2.0 * PI) + 0.5,
+        acos(clamp(direction.y, -1.0, 1.0)) / PI
+    );
+    let color = textureSampleLevel(equirect, source_sampler, uv, 0.0);
+    textureStore(target_faces, id.xy, id.z, vec4<f32>(color.rgb, 1.0));
+}
+
+// box filters the previous mip into the next one
+@compute @workgroup_size(8, 8, 1)
+fn downsample(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(target_faces).x;
+    if id.x >= size || id.y >= size {
+        return;
+    }
+    let source = id.xy * 2u;
+    let color = textureLoad(source_faces, source, id.z, 0)
+        + textureLoad(source_faces, source + vec2<u32>(1u, 0u), id.z, 0)
+        + textureLoad(source_faces, source + vec2<u32>(0u, 1u), id.z, 0)
+        + textureLoad(source_faces, source + vec2<u32>(1u, 1u), id.z, 0);
+    textureStore(target_faces, id.xy, id.z, color * 0.25);
+}
+
+// cosine weighted integral of the environment over the hemisphere around each direction
+@compute @workgroup_size(8, 8, 1)
+fn irradiance(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(target_faces).x;
+    if id.x >= size || id.y >= size {
+        return;
+    }
+    let n = cube_direction(id, size);
+    let delta = PI / 64.0;
+    var sum = vec3<f32>(0.0);
+    var count = 0.0;
+    for (var phi = 0.0; phi < 2.0 * PI; phi += delta) {
+        for (var theta = 0.0; theta < 0.5 * PI; theta += delta) {
+            let v = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let l = tangent_to_world(v, n);
+            sum += textureSampleLevel(source_cube, source_sampler, l, params.source_lod).rgb
+                * cos(theta) * sin(theta);
+            count += 1.0;
+        }
+    }
+    textureStore(target_faces, id.xy, id.z, vec4<f32>(PI * sum / count, 1.0));
+}
+
+// specular radiance convolved with the GGX lobe of `params.roughness`, assuming n = v
+@compute @workgroup_size(8, 8, 1)
+fn prefilter(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(target_faces).x;
+    if id.x >= size || id.y >= size {
+        return;
+    }
+    let n = cube_direction(id, size);
+    let roughness = params.roughness;
+    // solid angle of one texel of the environment cube
+    let texel_angle = 4.0 * PI / (6.0 * params.source_size * params.source_size);
+    var sum = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < params.sample_count; i++) {
+        let h = importance_sample_ggx(hammersley(i, params.sample_count), n, roughness);
+        let l = normalize(2.0 * dot(n, h) * h - n);
+        let n_dot_l = dot(n, l);
+        if n_dot_l > 0.0 {
+            // sampling a blurrier mip where samples are sparse avoids bright speckles
+            let n_dot_h = max(dot(n, h), 0.0);
+            let pdf = distribution_ggx(n_dot_h, roughness) * 0.25 + 1e-4;
+            let sample_angle = 1.0 / (f32(params.sample_count) * pdf + 1e-4);
+            let lod = select(0.5 * log2(sample_angle / texel_angle), 0.0, roughness == 0.0);
+            sum += textureSampleLevel(source_cube, source_sampler, l, lod).rgb * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+    textureStore(target_faces, id.xy, id.z, vec4<f32>(sum / max(weight, 1e-4), 1.0));
+}
+
+// scale and bias applied to F0 by the split sum approximation, by n.v and roughness
+@compute @workgroup_size(8, 8, 1)
+fn brdf_lut(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(brdf_target);
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+    let n_dot_v = (f32(id.x) + 0.5) / f32(size.x);
+    let roughness = (f32(id.y) + 0.5) / f32(size.y);
+    let v = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+    let n = vec3<f32>(0.0, 0.0, 1.0);
+    // Schlick-GGX with the k used for image based lighting
+    let k = roughness * roughness / 2.0;
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < params.sample_count; i++) {
+        let h = importance_sample_ggx(hammersley(i, params.sample_count), n, roughness);
+        let l = normalize(2.0 * dot(v, h) * h - v);
+        let n_dot_l = max(l.z, 0.0);
+        let n_dot_h = max(h.z, 0.0);
+        let v_dot_h = max(dot(v, h), 0.0);
+        if n_dot_l > 0.0 {
+            let g = n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+            let visibility = g * v_dot_h / (n_dot_h * n_dot_v);
+            let fresnel = pow(1.0 - v_dot_h, 5.0);
+            scale += (1.0 - fresnel) * visibility;
+            bias += fresnel * visibility;
+        }
+    }
+    let count = f32(params.sample_count);
+    textureStore(brdf_target, id.xy, vec4<f32>(scale / count, bias / count, 0.0, 1.0));
+}
@@ -0,0 +1,316 @@
+struct VertexInput {
+    @location(0) pos: vec3<f32>,
+    @location(1) color: vec3<f32>,
+    @location(2) tex_pos: vec2<f32>,
+    @location(3) normal: vec3<f32>,
+    @location(4) tangent: vec4<f32>,
+    @location(5) tex_pos_1: vec2<f32>,
+}
+
+struct InstanceInput {
+    @location(6) model_0: vec4<f32>,
+    @location(7) model_1: vec4<f32>,
+    @location(8) model_2: vec4<f32>,
+    @location(9) model_3: vec4<f32>,
+    @location(10) color: vec4<f32>,
+    @location(11) tex_index: u32,
+}
+
+struct VertexOutput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) world_pos: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) tangent: vec4<f32>,
+    @location(3) tex_pos: vec2<f32>,
+    @location(4) tex_pos_1: vec2<f32>,
+    @location(5) color: vec4<f32>,
+}
+
+// ===== Vertex shader =====
+
+struct CameraUniform {
+    view: mat4x4<f32>,
+    projection: mat4x4<f32>,
+    view_projection: mat4x4<f32>,
+    position: vec4<f32>,
+}
+
+@group(0) @binding(2)
+var<uniform> camera: CameraUniform;
+
+@vertex
+fn vs_main(in: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let world_pos = model * vec4<f32>(in.pos, 1.0);
+    let linear = mat3x3<f32>(model[0].xyz, model[1].xyz, model[2].xyz);
+    // the cofactor matrix is the inverse transpose scaled by the determinant, so normals
+    // stay perpendicular under non-uniform scale
+    let cofactor = mat3x3<f32>(
+        cross(linear[1], linear[2]),
+        cross(linear[2], linear[0]),
+        cross(linear[0], linear[1])
+    );
+    let normal = cofactor * in.normal * sign(determinant(linear));
+    // the instance texture index only applies to the group 1 textures of the default shader
+    return VertexOutput(
+        camera.view_projection * world_pos,
+        world_pos.xyz,
+        normal,
+        vec4<f32>(linear * in.tangent.xyz, in.tangent.w),
+        in.tex_pos,
+        in.tex_pos_1,
+        vec4<f32>(in.color, 1.0) * instance.color
+    );
+}
+
+// ===== Fragment shader =====
+
+struct MaterialUniform {
+    base_color: vec4<f32>,
+    emissive: vec3<f32>,
+    normal_scale: f32,
+    metallic: f32,
+    roughness: f32,
+    occlusion_strength: f32,
+    uv_sets: u32,
+}
+
+@group(2) @binding(0)
+var<uniform> material: MaterialUniform;
+@group(2) @binding(1)
+var material_sampler: sampler;
+@group(2) @binding(2)
+var base_color_texture: texture_2d<f32>;
+@group(2) @binding(3)
+var metallic_roughness_texture: texture_2d<f32>;
+@group(2) @binding(4)
+var normal_texture: texture_2d<f32>;
+@group(2) @binding(5)
+var occlusion_texture: texture_2d<f32>;
+@group(2) @binding(6)
+var emissive_texture: texture_2d<f32>;
+
+struct Light {
+    position: vec3<f32>,
+    range: f32,
+    direction: vec3<f32>,
+    // 0 directional, 1 point, 2 spot
+    kind: u32,
+    color: vec3<f32>,
+    intensity: f32,
+    inner_cos: f32,
+    outer_cos: f32,
+    shadow_layer: i32,
+}
+
+struct Lighting {
+    lights: array<Light, 16>,
+    shadow_matrices: array<mat4x4<f32>, 8>,
+    cascade_splits: vec4<f32>,
+    ambient: vec3<f32>,
+    count: u32,
+    cascade_count: u32,
+    depth_bias: f32,
+    normal_bias: f32,
+    pcf_radius: u32,
+    environment_intensity: f32,
+    prefiltered_lod: f32,
+}
+
+@group(3) @binding(0)
+var<uniform> lighting: Lighting;
+@group(3) @binding(1)
+var shadow_maps: texture_depth_2d_array;
+@group(3) @binding(2)
+var shadow_sampler: sampler_comparison;
+@group(3) @binding(4)
+var irradiance_map: texture_cube<f32>;
+@group(3) @binding(5)
+var prefiltered_map: texture_cube<f32>;
+@group(3) @binding(6)
+var brdf_lut: texture_2d<f32>;
+@group(3) @binding(7)
+var environment_sampler: sampler;
+
+// set by the pipeline manager when the surface format doesn't encode sRGB itself
+override gamma_encode: bool = false;
+
+const PI: f32 = 3.14159265359;
+
+// UV set of texture `slot`, in the binding order of the material textures
+fn material_uv(in: VertexOutput, slot: u32) -> vec2<f32> {
+    return select(in.tex_pos, in.tex_pos_1, (material.uv_sets & (1u << slot)) != 0u);
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a2 = pow(roughness, 4.0);
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return g_v * g_l;
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// Cook-Torrance specular plus Lambert diffuse for light arriving from `l`
+fn brdf(
+    n: vec3<f32>,
+    v: vec3<f32>,
+    l: vec3<f32>,
+    base_color: vec3<f32>,
+    metallic: f32,
+    roughness: f32
+) -> vec3<f32> {
+    let h = normalize(v + l);
+    let n_dot_v = max(dot(n, v), 1e-4);
+    let n_dot_l = max(dot(n, l), 0.0);
+    let n_dot_h = max(dot(n, h), 0.0);
+    let f0 = mix(vec3<f32>(0.04), base_color, metallic);
+    let f = fresnel_schlick(max(dot(h, v), 0.0), f0);
+    let specular = distribution_ggx(n_dot_h, roughness) * geometry_smith(n_dot_v, n_dot_l, roughness)
+        * f / (4.0 * n_dot_v * max(n_dot_l, 1e-4));
+    let diffuse = (1.0 - f) * (1.0 - metallic) * base_color / PI;
+    return (diffuse + specular) * n_dot_l;
+}
+
+// diffuse and specular light from the environment, with the split sum approximation
+fn environment_light(
+    n: vec3<f32>,
+    v: vec3<f32>,
+    base_color: vec3<f32>,
+    metallic: f32,
+    roughness: f32
+) -> vec3<f32> {
+    let n_dot_v = max(dot(n, v), 1e-4);
+    let f0 = mix(vec3<f32>(0.04), base_color, metallic);
+    // Fresnel at grazing angles is weaker on rough surfaces
+    let f = f0 + (max(vec3<f32>(1.0 - roughness), f0) - f0) * pow(1.0 - n_dot_v, 5.0);
+    let irradiance = textureSampleLevel(irradiance_map, environment_sampler, n, 0.0).rgb;
+    let prefiltered = textureSampleLevel(
+        prefiltered_map,
+        environment_sampler,
+        reflect(-v, n),
+        roughness * lighting.prefiltered_lod
+    ).rgb;
+    let brdf = textureSampleLevel(brdf_lut, environment_sampler, vec2<f32>(n_dot_v, roughness), 0.0).rg;
+    let diffuse = (1.0 - f) * (1.0 - metallic) * irradiance * base_color;
+    let specular = prefiltered * (f0 * brdf.x + brdf.y);
+    return (diffuse + specular) * lighting.environment_intensity;
+}
+
+// fraction of light reaching `world_pos` from the shadow map `layer`, with PCF
+fn shadow_factor(layer: i32, world_pos: vec3<f32>, normal: vec3<f32>) -> f32 {
+    let offset_pos = world_pos + normal * lighting.normal_bias;
+    let clip = lighting.shadow_matrices[layer] * vec4<f32>(offset_pos, 1.0);
+    let ndc = clip.xyz / clip.w;
+    if clip.w <= 0.0 || ndc.z > 1.0 {
+        return 1.0;
+    }
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    let texel = 1.0 / vec2<f32>(textureDimensions(shadow_maps));
+    let radius = i32(lighting.pcf_radius);
+    var lit = 0.0;
+    for (var y = -radius; y <= radius; y++) {
+        for (var x = -radius; x <= radius; x++) {
+            lit += textureSampleCompareLevel(
+                shadow_maps,
+                shadow_sampler,
+                uv + vec2<f32>(f32(x), f32(y)) * texel,
+                layer,
+                ndc.z - lighting.depth_bias
+            );
+        }
+    }
+    let samples = f32((2 * radius + 1) * (2 * radius + 1));
+    return lit / samples;
+}
+
+// direction towards the light and its attenuated radiance
+fn light_radiance(light: Light, world_pos: vec3<f32>) -> vec4<f32> {
+    if light.kind == 0u {
+        return vec4<f32>(-light.direction, 1.0);
+    }
+    let to_light = light.position - world_pos;
+    let distance = length(to_light);
+    let l = to_light / max(distance, 1e-4);
+    var attenuation = 1.0 / max(distance * distance, 1e-4);
+    if light.range > 0.0 {
+        // smooth cutoff at the range, as in KHR_lights_punctual
+        attenuation *= pow(clamp(1.0 - pow(distance / light.range, 4.0), 0.0, 1.0), 2.0);
+    }
+    if light.kind == 2u {
+        attenuation *= smoothstep(light.outer_cos, light.inner_cos, dot(-l, light.direction));
+    }
+    return vec4<f32>(l, attenuation);
+}
+
+@fragment
+fn fs_main(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> @location(0) vec4<f32> {
+    let base_color = material.base_color * in.color
+        * textureSample(base_color_texture, material_sampler, material_uv(in, 0u));
+    let metallic_roughness = textureSample(metallic_roughness_texture, material_sampler, material_uv(in, 1u));
+    let normal_sample = textureSample(normal_texture, material_sampler, material_uv(in, 2u)).xyz;
+    let occlusion_sample = textureSample(occlusion_texture, material_sampler, material_uv(in, 3u)).r;
+    let emissive_sample = textureSample(emissive_texture, material_sampler, material_uv(in, 4u)).rgb;
+
+    let metallic = clamp(material.metallic * metallic_roughness.b, 0.0, 1.0);
+    let roughness = clamp(material.roughness * metallic_roughness.g, 0.04, 1.0);
+    let occlusion = mix(1.0, occlusion_sample, material.occlusion_strength);
+    let emissive = material.emissive * emissive_sample;
+
+    // back faces of double sided surfaces are lit from their own side
+    var n = normalize(in.normal);
+    if !front_facing {
+        n = -n;
+    }
+    let tangent = in.tangent.xyz - n * dot(n, in.tangent.xyz);
+    if dot(tangent, tangent) > 1e-8 {
+        let t = normalize(tangent);
+        let b = cross(n, t) * in.tangent.w;
+        var tangent_normal = normal_sample * 2.0 - 1.0;
+        tangent_normal = vec3<f32>(tangent_normal.xy * material.normal_scale, tangent_normal.z);
+        n = normalize(mat3x3<f32>(t, b, n) * tangent_normal);
+    }
+    let v = normalize(camera.position.xyz - in.world_pos);
+
+    let geometric_normal = normalize(in.normal) * select(-1.0, 1.0, front_facing);
+    let view_depth = -(camera.view * vec4<f32>(in.world_pos, 1.0)).z;
+
+    var color = (lighting.ambient * base_color.rgb
+        + environment_light(n, v, base_color.rgb, metallic, roughness)) * occlusion + emissive;
+    for (var i = 0u; i < lighting.count; i++) {
+        let light = lighting.lights[i];
+        let radiance = light_radiance(light, in.world_pos);
+        var shadow = 1.0;
+        if light.shadow_layer >= 0 {
+            if light.kind == 0u {
+                // the first cascade that still covers this depth
+                var cascade = 0u;
+                while cascade < lighting.cascade_count && view_depth > lighting.cascade_splits[cascade] {
+                    cascade++;
+                }
+                if cascade < lighting.cascade_count {
+                    shadow = shadow_factor(light.shadow_layer + i32(cascade), in.world_pos, geometric_normal);
+                }
+            } else {
+                shadow = shadow_factor(light.shadow_layer, in.world_pos, geometric_normal);
+            }
+        }
+        color += brdf(n, v, radiance.xyz, base_color.rgb, metallic, roughness)
+            * light.color * light.intensity * radiance.w * shadow;
+    }
+
+    // Reinhard tone mapping
+    color = color / (color + 1.0);
+    if gamma_encode {
+        color = pow(color, vec3<f32>(1.0 / 2.2));
+    }
+    return vec4<f32>(color, base_color.a);
+}
@@ -0,0 +1,17 @@
+// Depth only pass rendering the scene from a light into one shadow map layer.
+
+struct InstanceInput {
+    @location(6) model_0: vec4<f32>,
+    @location(7) model_1: vec4<f32>,
+    @location(8) model_2: vec4<f32>,
+    @location(9) model_3: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> light_view_projection: mat4x4<f32>;
+
+@vertex
+fn vs_main(@location(0) pos: vec3<f32>, instance: InstanceInput) -> @builtin(position) vec4<f32> {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    return light_view_projection * model * vec4<f32>(pos, 1.0);
+}
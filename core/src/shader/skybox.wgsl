@@ -0,0 +1,51 @@
+// Draws the environment cube behind everything, from a fullscreen triangle at the far plane.
+
+struct CameraUniform {
+    view: mat4x4<f32>,
+    projection: mat4x4<f32>,
+    view_projection: mat4x4<f32>,
+    position: vec4<f32>,
+}
+
+@group(0) @binding(2)
+var<uniform> camera: CameraUniform;
+@group(3) @binding(3)
+var environment_map: texture_cube<f32>;
+@group(3) @binding(7)
+var environment_sampler: sampler;
+
+// set by the pipeline manager when the surface format doesn't encode sRGB itself
+override gamma_encode: bool = false;
+
+struct VertexOutput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let ndc = vec2<f32>(f32(index & 1u) * 4.0 - 1.0, f32(index >> 1u) * 4.0 - 1.0);
+    return VertexOutput(vec4<f32>(ndc, 1.0, 1.0), ndc);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // view space ray through the pixel; orthographic cameras look straight ahead
+    let perspective = camera.projection[2][3] != 0.0;
+    let ray = select(
+        vec3<f32>(0.0, 0.0, -1.0),
+        vec3<f32>(in.ndc.x / camera.projection[0][0], in.ndc.y / camera.projection[1][1], -1.0),
+        perspective
+    );
+    // the inverse of the view rotation is its transpose
+    let rotation = mat3x3<f32>(camera.view[0].xyz, camera.view[1].xyz, camera.view[2].xyz);
+    let direction = transpose(rotation) * ray;
+    var color = textureSampleLevel(environment_map, environment_sampler, direction, 0.0).rgb;
+
+    // tone mapped like the PBR shader
+    color = color / (color + 1.0);
+    if gamma_encode {
+        color = pow(color, vec3<f32>(1.0 / 2.2));
+    }
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,37 @@
+// Screen space sprites, positioned in pixels from the top left corner of the canvas.
+
+struct SpriteVertex {
+    @location(0) pos: vec2<f32>,
+    @location(1) tex_pos: vec2<f32>,
+    @location(2) tint: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) tex_pos: vec2<f32>,
+    @location(1) tint: vec4<f32>,
+}
+
+struct ProgramUniform {
+    screen_width: f32,
+    screen_height: f32
+}
+
+@group(0) @binding(0)
+var<uniform> program: ProgramUniform;
+@group(1) @binding(0)
+var sprite_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var sprite_sampler: sampler;
+
+@vertex
+fn vs_main(in: SpriteVertex) -> VertexOutput {
+    let screen = vec2<f32>(program.screen_width, program.screen_height);
+    let ndc = in.pos / screen * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0);
+    return VertexOutput(vec4<f32>(ndc, 0.0, 1.0), in.tex_pos, in.tint);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(sprite_texture, sprite_sampler, in.tex_pos) * in.tint;
+}
@@ -0,0 +1,50 @@
+// Glyphs from a signed distance field atlas, positioned in pixels from the top left
+// corner of the canvas. The field is 0.5 on the outline and grows towards the inside.
+
+struct TextVertex {
+    @location(0) pos: vec2<f32>,
+    @location(1) tex_pos: vec2<f32>,
+    @location(2) color: vec4<f32>,
+    @location(3) outline_color: vec4<f32>,
+    // outline width and edge softness, in distance field units
+    @location(4) style: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) tex_pos: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) outline_color: vec4<f32>,
+    @location(3) style: vec2<f32>,
+}
+
+struct ProgramUniform {
+    screen_width: f32,
+    screen_height: f32
+}
+
+@group(0) @binding(0)
+var<uniform> program: ProgramUniform;
+@group(1) @binding(0)
+var atlas: texture_2d<f32>;
+@group(1) @binding(1)
+var atlas_sampler: sampler;
+
+@vertex
+fn vs_main(in: TextVertex) -> VertexOutput {
+    let screen = vec2<f32>(program.screen_width, program.screen_height);
+    let ndc = in.pos / screen * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0);
+    return VertexOutput(vec4<f32>(ndc, 0.0, 1.0), in.tex_pos, in.color, in.outline_color, in.style);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let distance = textureSample(atlas, atlas_sampler, in.tex_pos).r;
+    // about one pixel of antialiasing at any scale, wider for blurred shadows
+    let edge = max(fwidth(distance) * 0.75, in.style.y);
+    let fill = smoothstep(0.5 - edge, 0.5 + edge, distance);
+    let outer = 0.5 - in.style.x;
+    let coverage = smoothstep(outer - edge, outer + edge, distance);
+    let color = mix(in.outline_color, in.color, fill);
+    return vec4<f32>(color.rgb, color.a * coverage);
+}
@@ -0,0 +1,482 @@
+use std::ops::Range;
+
+use glam::Vec2;
+use log::info;
+use serde::{Deserialize, Serialize};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendState, Buffer, BufferAddress,
+    BufferUsages, ColorTargetState, ColorWrites, Device, Extent3d, FragmentState, MultisampleState,
+    PipelineCompilationOptions, PipelineLayoutDescriptor, PrimitiveState, Queue, RenderPass,
+    RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType, ShaderModuleDescriptor,
+    ShaderStages, Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType,
+    TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension, VertexAttribute,
+    VertexBufferLayout, VertexState,
+    util::{BufferInitDescriptor, DeviceExt},
+};
+
+use crate::{
+    error::{Error, Result},
+    texture_manager::{SamplerSettings, TextureManager},
+};
+
+/// Number of floats per sprite in the typed arrays sent from JS: position, rotation,
+/// scale, UV rect, tint and layer, in the order of the `Sprite` fields.
+pub const SPRITE_FLOATS: usize = 14;
+
+// matches `SpriteVertex` in sprite.wgsl
+const SPRITE_ATTRIBUTES: [VertexAttribute; 3] = wgpu::vertex_attr_array![
+    0 => Float32x2,
+    1 => Float32x2,
+    2 => Float32x4,
+];
+
+/// A textured quad in screen space, e.g. `{ texture: 0, position: [100, 50], rotation: 0.5 }`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Sprite {
+    /// Index into the textures given to `App::setup`, untextured for `None`.
+    pub texture: Option<u32>,
+    /// Center of the sprite in pixels from the top left corner of the canvas.
+    pub position: [f32; 2],
+    /// Clockwise, in radians.
+    pub rotation: f32,
+    /// Multiplies the pixel size of the texture region, so an untextured sprite is
+    /// `scale` pixels large.
+    pub scale: [f32; 2],
+    /// Texture region as x, y, width and height in texture coordinates.
+    pub uv_rect: [f32; 4],
+    pub tint: [f32; 4],
+    /// Higher layers are drawn on top, sprites of the same layer in the order they were added.
+    pub layer: i32,
+}
+
+impl Default for Sprite {
+    fn default() -> Self {
+        Self {
+            texture: None,
+            position: [0.0, 0.0],
+            rotation: 0.0,
+            scale: [1.0, 1.0],
+            uv_rect: [0.0, 0.0, 1.0, 1.0],
+            tint: [1.0, 1.0, 1.0, 1.0],
+            layer: 0,
+        }
+    }
+}
+
+impl Sprite {
+    /// Reads one sprite laid out as described by `SPRITE_FLOATS`.
+    pub fn from_floats(texture: Option<u32>, data: &[f32]) -> Self {
+        Self {
+            texture,
+            position: [data[0], data[1]],
+            rotation: data[2],
+            scale: [data[3], data[4]],
+            uv_rect: [data[5], data[6], data[7], data[8]],
+            tint: [data[9], data[10], data[11], data[12]],
+            layer: data[13] as i32,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SpriteVertex {
+    pos: [f32; 2],
+    tex_pos: [f32; 2],
+    tint: [f32; 4],
+}
+
+/// Consecutive sprites sharing a texture, drawn in one call.
+struct SpriteDraw {
+    texture: Option<usize>,
+    indices: Range<u32>,
+}
+
+/// Collects the sprites of a frame and draws them on top of the scene, batched by
+/// texture within each layer.
+pub struct SpriteBatch {
+    /// Sprites added since the last frame.
+    pub sprites: Vec<Sprite>,
+    pipeline: RenderPipeline,
+    texture_layout: BindGroupLayout,
+    // one per texture of the texture manager, with its size in pixels
+    textures: Vec<(BindGroup, [f32; 2])>,
+    white: Texture,
+    white_bind_group: BindGroup,
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    capacity: usize,
+    draws: Vec<SpriteDraw>,
+}
+
+impl SpriteBatch {
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        swapchain_format: TextureFormat,
+        uniform_layout: &BindGroupLayout,
+        textures: &TextureManager,
+    ) -> Self {
+        let texture_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Sprite Texture Bind Group Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let pipeline =
+            Self::create_pipeline(device, swapchain_format, uniform_layout, &texture_layout);
+
+        let white = device.create_texture(&TextureDescriptor {
+            label: Some("Sprite White Texture"),
+            size: Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba8Unorm,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            white.as_image_copy(),
+            &[255; 4],
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4),
+                rows_per_image: Some(1),
+            },
+            white.size(),
+        );
+        let white_sampler = SamplerSettings::default().create_sampler(device, "Sprite Sampler");
+        let white_bind_group = Self::create_texture_bind_group(
+            device,
+            &texture_layout,
+            &white.create_view(&TextureViewDescriptor::default()),
+            &white_sampler,
+        );
+
+        let (vertex_buffer, index_buffer) = Self::create_buffers(device, 256);
+        let mut batch = Self {
+            sprites: Vec::new(),
+            pipeline,
+            texture_layout,
+            textures: Vec::new(),
+            white,
+            white_bind_group,
+            vertex_buffer,
+            index_buffer,
+            capacity: 256,
+            draws: Vec::new(),
+        };
+        batch.set_textures(device, textures);
+        info!("Sprite batch created successfully!");
+        batch
+    }
+
+    /// Frees the white texture and the buffers, the texture manager's textures are left
+    /// alone. The batch can't be used afterwards.
+    pub fn destroy(&self) {
+        self.white.destroy();
+        self.vertex_buffer.destroy();
+        self.index_buffer.destroy();
+    }
+
+    /// Rebuilds the texture bind groups, call when the texture manager is replaced.
+    pub fn set_textures(&mut self, device: &Device, textures: &TextureManager) {
+        self.textures = textures
+            .textures
+            .iter()
+            .zip(&textures.texture_samplers)
+            .map(|(holder, sampler)| {
+                let size = holder.texture.size();
+                let bind_group = Self::create_texture_bind_group(
+                    device,
+                    &self.texture_layout,
+                    &holder.texture_view,
+                    sampler,
+                );
+                (bind_group, [size.width as f32, size.height as f32])
+            })
+            .collect();
+    }
+
+    pub fn add(&mut self, sprite: Sprite) -> Result<()> {
+        if let Some(texture) = sprite.texture
+            && texture as usize >= self.textures.len()
+        {
+            return Err(Error::invalid_handle("texture", texture as usize));
+        }
+        self.sprites.push(sprite);
+        Ok(())
+    }
+
+    /// Sorts the sprites of this frame into draws and uploads their vertices. The
+    /// sprites are consumed, so the next frame starts out empty.
+    pub fn prepare(&mut self, device: &Device, queue: &Queue) {
+        let mut sprites = std::mem::take(&mut self.sprites);
+        self.draws.clear();
+        if sprites.is_empty() {
+            return;
+        }
+        if sprites.len() > self.capacity {
+            self.capacity = sprites.len().next_power_of_two();
+            (self.vertex_buffer, self.index_buffer) = Self::create_buffers(device, self.capacity);
+        }
+
+        let (vertices, draws) =
+            Self::batch(&mut sprites, |texture| self.textures[texture as usize].1);
+        self.draws = draws;
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.draws.is_empty()
+    }
+
+    /// Draws the prepared sprites, `uniform_bind_group` provides the screen size.
+    pub fn draw(&self, render_pass: &mut RenderPass, uniform_bind_group: &BindGroup) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, uniform_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        for draw in &self.draws {
+            let bind_group = match draw.texture {
+                Some(texture) => &self.textures[texture].0,
+                None => &self.white_bind_group,
+            };
+            render_pass.set_bind_group(1, bind_group, &[]);
+            render_pass.draw_indexed(draw.indices.clone(), 0, 0..1);
+        }
+    }
+
+    // sorts the sprites by layer and groups each layer by texture, in the order the
+    // textures first appear
+    fn batch(
+        sprites: &mut [Sprite],
+        texture_size: impl Fn(u32) -> [f32; 2],
+    ) -> (Vec<SpriteVertex>, Vec<SpriteDraw>) {
+        // stable, so sprites of a layer stay in the order they were added
+        sprites.sort_by_key(|sprite| sprite.layer);
+        let mut vertices = Vec::with_capacity(sprites.len() * 4);
+        let mut draws = Vec::new();
+        let mut start = 0;
+        for layer in sprites.chunk_by(|a, b| a.layer == b.layer) {
+            let mut textures: Vec<Option<u32>> = Vec::new();
+            for sprite in layer {
+                if !textures.contains(&sprite.texture) {
+                    textures.push(sprite.texture);
+                }
+            }
+            for texture in textures {
+                let region = texture.map_or([1.0, 1.0], &texture_size);
+                for sprite in layer.iter().filter(|sprite| sprite.texture == texture) {
+                    vertices.extend(Self::vertices(sprite, region));
+                }
+                let end = (vertices.len() / 4) as u32;
+                draws.push(SpriteDraw {
+                    texture: texture.map(|texture| texture as usize),
+                    indices: start * 6..end * 6,
+                });
+                start = end;
+            }
+        }
+        (vertices, draws)
+    }
+
+    // corners of the rotated quad, `texture_size` is the pixel size of the whole texture
+    fn vertices(sprite: &Sprite, texture_size: [f32; 2]) -> [SpriteVertex; 4] {
+        let [u, v, width, height] = sprite.uv_rect;
+        let size = Vec2::new(width, height) * Vec2::from(texture_size) * Vec2::from(sprite.scale);
+        let center = Vec2::from(sprite.position);
+        // y points down, so a positive angle turns clockwise on screen
+        let rotation = Vec2::from_angle(sprite.rotation);
+        [
+            ([-0.5, -0.5], [u, v]),
+            ([0.5, -0.5], [u + width, v]),
+            ([0.5, 0.5], [u + width, v + height]),
+            ([-0.5, 0.5], [u, v + height]),
+        ]
+        .map(|(corner, tex_pos)| SpriteVertex {
+            pos: (center + rotation.rotate(Vec2::from(corner) * size)).to_array(),
+            tex_pos,
+            tint: sprite.tint,
+        })
+    }
+
+    // the index buffer never changes, it only grows with the vertex buffer
+    fn create_buffers(device: &Device, capacity: usize) -> (Buffer, Buffer) {
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sprite Vertex Buffer"),
+            size: (capacity * 4 * std::mem::size_of::<SpriteVertex>()) as u64,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let indices: Vec<u32> = (0..capacity as u32)
+            .flat_map(|sprite| [0, 1, 2, 0, 2, 3].map(|index| sprite * 4 + index))
+            .collect();
+        let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Sprite Index Buffer"),
+            contents: bytemuck::cast_slice(&indices),
+            usage: BufferUsages::INDEX,
+        });
+        (vertex_buffer, index_buffer)
+    }
+
+    fn create_texture_bind_group(
+        device: &Device,
+        layout: &BindGroupLayout,
+        view: &TextureView,
+        sampler: &Sampler,
+    ) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Sprite Texture Bind Group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+
+    fn create_pipeline(
+        device: &Device,
+        swapchain_format: TextureFormat,
+        uniform_layout: &BindGroupLayout,
+        texture_layout: &BindGroupLayout,
+    ) -> RenderPipeline {
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Sprite Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("./shader/sprite.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Sprite Pipeline Layout"),
+            bind_group_layouts: &[uniform_layout, texture_layout],
+            push_constant_ranges: &[],
+        });
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Sprite Render Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: PipelineCompilationOptions::default(),
+                buffers: &[VertexBufferLayout {
+                    array_stride: std::mem::size_of::<SpriteVertex>() as BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &SPRITE_ATTRIBUTES,
+                }],
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: PipelineCompilationOptions::default(),
+                targets: &[Some(ColorTargetState {
+                    format: swapchain_format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sprite(texture: Option<u32>, layer: i32) -> Sprite {
+        Sprite {
+            texture,
+            layer,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn batches_by_layer_and_texture() {
+        let mut sprites = vec![
+            sprite(Some(0), 1),
+            sprite(Some(1), 0),
+            sprite(None, 1),
+            sprite(Some(0), 1),
+            sprite(Some(1), 0),
+        ];
+        let (vertices, draws) = SpriteBatch::batch(&mut sprites, |_| [16.0, 16.0]);
+        assert_eq!(vertices.len(), 20);
+        let draws: Vec<_> = draws
+            .iter()
+            .map(|draw| (draw.texture, draw.indices.clone()))
+            .collect();
+        assert_eq!(draws, [(Some(1), 0..12), (Some(0), 12..24), (None, 24..30)]);
+    }
+
+    #[test]
+    fn quad_uvs_and_size() {
+        let sprite = Sprite {
+            position: [100.0, 50.0],
+            scale: [2.0, 1.0],
+            uv_rect: [0.5, 0.25, 0.5, 0.25],
+            ..Default::default()
+        };
+        let vertices = SpriteBatch::vertices(&sprite, [64.0, 64.0]);
+        let tex_pos: Vec<_> = vertices.iter().map(|vertex| vertex.tex_pos).collect();
+        assert_eq!(tex_pos, [[0.5, 0.25], [1.0, 0.25], [1.0, 0.5], [0.5, 0.5]]);
+        // half the texture wide and a quarter high, stretched twice horizontally
+        assert_eq!(vertices[0].pos, [68.0, 42.0]);
+        assert_eq!(vertices[2].pos, [132.0, 58.0]);
+    }
+
+    #[test]
+    fn rotated_quad() {
+        let sprite = Sprite {
+            rotation: std::f32::consts::FRAC_PI_2,
+            scale: [4.0, 2.0],
+            ..Default::default()
+        };
+        let vertices = SpriteBatch::vertices(&sprite, [1.0, 1.0]);
+        // the top left corner turns clockwise to the top right
+        let corner = Vec2::from(vertices[0].pos);
+        assert!(corner.abs_diff_eq(Vec2::new(1.0, -2.0), 1e-6), "{corner}");
+    }
+
+    #[test]
+    fn from_floats() {
+        let data: Vec<f32> = (0..SPRITE_FLOATS).map(|i| i as f32).collect();
+        let sprite = Sprite::from_floats(Some(3), &data);
+        assert_eq!(sprite.position, [0.0, 1.0]);
+        assert_eq!(sprite.uv_rect, [5.0, 6.0, 7.0, 8.0]);
+        assert_eq!(sprite.tint, [9.0, 10.0, 11.0, 12.0]);
+        assert_eq!(sprite.layer, 13);
+    }
+}
@@ -0,0 +1,901 @@
+use std::{collections::HashMap, ops::Range};
+
+use glam::Vec2;
+use log::info;
+use serde::{Deserialize, Serialize};
+use ttf_parser::{
+    Face, GlyphId, OutlineBuilder,
+    gpos::{PairAdjustment, PositioningSubtable},
+};
+use wgpu::{
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BlendState,
+    Buffer, BufferAddress, BufferUsages, ColorTargetState, ColorWrites, Device, Extent3d,
+    FilterMode, FragmentState, MultisampleState, PipelineCompilationOptions,
+    PipelineLayoutDescriptor, PrimitiveState, Queue, RenderPass, RenderPipeline,
+    RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor,
+    ShaderModuleDescriptor, ShaderStages, Texture, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureSampleType, TextureUsages, TextureViewDescriptor, TextureViewDimension,
+    VertexAttribute, VertexBufferLayout, VertexState,
+    util::{BufferInitDescriptor, DeviceExt},
+};
+
+use crate::error::{Error, Result};
+
+/// Pixels per em the glyphs are rendered at in the atlas.
+const SDF_SIZE: f32 = 40.0;
+/// Distance in atlas pixels covered by the field on either side of an edge, which
+/// also bounds the outline width.
+const SDF_SPREAD: f32 = 6.0;
+const ATLAS_WIDTH: u32 = 1024;
+// line segments per curve when flattening outlines
+const CURVE_STEPS: usize = 6;
+
+// matches `TextVertex` in text.wgsl
+const TEXT_ATTRIBUTES: [VertexAttribute; 5] = wgpu::vertex_attr_array![
+    0 => Float32x2,
+    1 => Float32x2,
+    2 => Float32x4,
+    3 => Float32x4,
+    4 => Float32x2,
+];
+
+/// Printable ASCII and Latin-1, the characters baked into every atlas.
+fn atlas_chars() -> impl Iterator<Item = char> {
+    (' '..='~').chain('\u{a0}'..='\u{ff}')
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// How a string is laid out and drawn, e.g. `{ position: [20, 20], size: 24,
+/// maxWidth: 300, align: "center", outlineWidth: 2 }`. Sizes are in pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TextStyle {
+    /// Top left corner of the text box, from the top left corner of the canvas.
+    pub position: [f32; 2],
+    /// Font size, the height of an em.
+    pub size: f32,
+    pub color: [f32; 4],
+    /// Lines are wrapped at spaces to fit. The box is as wide as this, or as the
+    /// longest line without it, and lines are aligned within the box.
+    pub max_width: Option<f32>,
+    pub align: TextAlign,
+    /// Multiplies the line spacing of the font.
+    pub line_height: f32,
+    pub outline_width: f32,
+    pub outline_color: [f32; 4],
+    /// The shadow is drawn when its color isn't fully transparent.
+    pub shadow_offset: [f32; 2],
+    pub shadow_color: [f32; 4],
+    pub shadow_blur: f32,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            position: [0.0, 0.0],
+            size: 32.0,
+            color: [1.0, 1.0, 1.0, 1.0],
+            max_width: None,
+            align: TextAlign::Left,
+            line_height: 1.0,
+            outline_width: 0.0,
+            outline_color: [0.0, 0.0, 0.0, 1.0],
+            shadow_offset: [2.0, 2.0],
+            shadow_color: [0.0, 0.0, 0.0, 0.0],
+            shadow_blur: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Glyph {
+    /// In ems.
+    advance: f32,
+    /// Quad around the glyph relative to the pen on the baseline, in ems with y down,
+    /// as left, top, right and bottom. `None` for glyphs without an outline.
+    bounds: Option<[f32; 4]>,
+    /// Same quad in the atlas, in texture coordinates.
+    uv_rect: [f32; 4],
+}
+
+/// Glyph metrics of a font, all that is needed to lay out text.
+#[derive(Clone, Debug)]
+struct FontMetrics {
+    glyphs: HashMap<char, Glyph>,
+    /// Advance adjustment between two characters, in ems.
+    kerning: HashMap<(char, char), f32>,
+    /// Baseline distance from the top of a line, in ems.
+    ascender: f32,
+    line_height: f32,
+}
+
+/// A font baked into a signed distance field atlas.
+pub struct Font {
+    metrics: FontMetrics,
+    // distance field atlas, uploaded again when the device is lost
+    atlas: Vec<u8>,
+    atlas_height: u32,
+    texture: Texture,
+    bind_group: BindGroup,
+}
+
+/// One laid out line, with the pen position of every glyph in ems.
+#[derive(Default)]
+struct Line {
+    glyphs: Vec<(char, f32)>,
+    width: f32,
+}
+
+impl FontMetrics {
+    fn glyph(&self, c: char) -> Option<&Glyph> {
+        self.glyphs.get(&c).or_else(|| self.glyphs.get(&'?'))
+    }
+
+    fn advance(&self, c: char) -> f32 {
+        self.glyph(c).map_or(0.0, |glyph| glyph.advance)
+    }
+
+    // appends a glyph, kerned against the last one of the line
+    fn place(&self, line: &mut Line, c: char) {
+        let kerning = line.glyphs.last().map_or(0.0, |&(previous, _)| {
+            self.kerning.get(&(previous, c)).copied().unwrap_or(0.0)
+        });
+        let pen = line.width + kerning;
+        line.glyphs.push((c, pen));
+        line.width = pen + self.advance(c);
+    }
+
+    /// Breaks the text into lines, at newlines and, with a maximum width in ems, at the
+    /// last space before a glyph that would overflow. Words longer than a line overflow.
+    fn layout(&self, text: &str, max_width: Option<f32>) -> Vec<Line> {
+        let mut lines = Vec::new();
+        for paragraph in text.split('\n') {
+            let mut line = Line::default();
+            let mut break_at = None;
+            for c in paragraph.chars() {
+                if c == ' ' {
+                    break_at = Some(line.glyphs.len()).filter(|&index| index > 0);
+                } else if let Some(max_width) = max_width
+                    && let Some(index) = break_at
+                    && line.width + self.advance(c) > max_width
+                {
+                    // the space is dropped and the word after it starts the next line
+                    let word: Vec<char> =
+                        line.glyphs.drain(index..).skip(1).map(|(c, _)| c).collect();
+                    line.width = line
+                        .glyphs
+                        .last()
+                        .map_or(0.0, |&(c, pen)| pen + self.advance(c));
+                    lines.push(std::mem::take(&mut line));
+                    break_at = None;
+                    for c in word {
+                        self.place(&mut line, c);
+                    }
+                }
+                self.place(&mut line, c);
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    // screen rectangle and atlas rectangle of every visible glyph
+    fn quads(
+        &self,
+        lines: &[Line],
+        style: &TextStyle,
+        box_width: f32,
+    ) -> Vec<([f32; 4], [f32; 4])> {
+        let size = style.size;
+        let mut quads = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            let x = match style.align {
+                TextAlign::Left => 0.0,
+                TextAlign::Center => (box_width - line.width * size) / 2.0,
+                TextAlign::Right => box_width - line.width * size,
+            };
+            let baseline = (self.ascender + i as f32 * self.line_height * style.line_height) * size;
+            for &(c, pen) in &line.glyphs {
+                let Some(glyph) = self.glyph(c) else {
+                    continue;
+                };
+                let Some([left, top, right, bottom]) = glyph.bounds else {
+                    continue;
+                };
+                let origin = Vec2::from(style.position) + Vec2::new(x + pen * size, baseline);
+                quads.push((
+                    [
+                        origin.x + left * size,
+                        origin.y + top * size,
+                        origin.x + right * size,
+                        origin.y + bottom * size,
+                    ],
+                    glyph.uv_rect,
+                ));
+            }
+        }
+        quads
+    }
+}
+
+impl Font {
+    /// Width and height of the text box in pixels.
+    pub fn measure(&self, text: &str, style: &TextStyle) -> [f32; 2] {
+        let metrics = &self.metrics;
+        let lines = metrics.layout(text, style.max_width.map(|width| width / style.size));
+        let widest = lines.iter().map(|line| line.width).fold(0.0, f32::max) * style.size;
+        let height = lines.len() as f32 * metrics.line_height * style.line_height * style.size;
+        [style.max_width.unwrap_or(widest), height]
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct TextVertex {
+    pos: [f32; 2],
+    tex_pos: [f32; 2],
+    color: [f32; 4],
+    outline_color: [f32; 4],
+    // outline width and edge softness, in distance field units
+    style: [f32; 2],
+}
+
+/// Consecutive glyph quads of one font, drawn in one call.
+struct TextDraw {
+    font: usize,
+    quads: Range<u32>,
+}
+
+/// Loads fonts and draws the strings queued during a frame on top of the scene.
+pub struct TextRenderer {
+    pub fonts: Vec<Font>,
+    pipeline: RenderPipeline,
+    atlas_layout: BindGroupLayout,
+    sampler: Sampler,
+    // queued since the last frame
+    vertices: Vec<TextVertex>,
+    queued: Vec<TextDraw>,
+    draws: Vec<TextDraw>,
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    capacity: usize,
+}
+
+impl TextRenderer {
+    pub fn new(
+        device: &Device,
+        swapchain_format: TextureFormat,
+        uniform_layout: &BindGroupLayout,
+    ) -> Self {
+        let atlas_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Font Atlas Bind Group Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let pipeline =
+            Self::create_pipeline(device, swapchain_format, uniform_layout, &atlas_layout);
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Font Atlas Sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+        let (vertex_buffer, index_buffer) = Self::create_buffers(device, 1024);
+        info!("Text renderer created successfully!");
+        Self {
+            fonts: Vec::new(),
+            pipeline,
+            atlas_layout,
+            sampler,
+            vertices: Vec::new(),
+            queued: Vec::new(),
+            draws: Vec::new(),
+            vertex_buffer,
+            index_buffer,
+            capacity: 1024,
+        }
+    }
+
+    /// Parses a TrueType or OpenType font, bakes its atlas and returns the font handle.
+    pub fn load_font(&mut self, device: &Device, queue: &Queue, data: &[u8]) -> Result<usize> {
+        let face = Face::parse(data, 0)
+            .map_err(|err| Error::Asset(format!("Failed to parse font: {err}")))?;
+        let units_per_em = face.units_per_em() as f32;
+        let scale = SDF_SIZE / units_per_em;
+
+        // render every glyph's distance field, then pack them into rows
+        let mut fields = Vec::new();
+        let mut advances = HashMap::new();
+        for c in atlas_chars() {
+            let Some(id) = face.glyph_index(c) else {
+                continue;
+            };
+            let advance = face.glyph_hor_advance(id).unwrap_or(0) as f32 / units_per_em;
+            advances.insert(c, (id, advance));
+            let mut outline = Outline::default();
+            if let Some(rect) = face.outline_glyph(id, &mut outline) {
+                fields.push((c, glyph_field(&outline.segments, rect, scale)));
+            }
+        }
+        let mut placements = Vec::with_capacity(fields.len());
+        let (mut x, mut y, mut row_height) = (0, 0, 0);
+        for (_, field) in &fields {
+            if x + field.width > ATLAS_WIDTH {
+                (x, y) = (0, y + row_height + 1);
+                row_height = 0;
+            }
+            placements.push((x, y));
+            x += field.width + 1;
+            row_height = row_height.max(field.height);
+        }
+        let atlas_height = (y + row_height).max(1).next_power_of_two();
+        if atlas_height > device.limits().max_texture_dimension_2d {
+            return Err(Error::TextureDecode(
+                "Font atlas exceeds the texture size limit".to_string(),
+            ));
+        }
+        let mut atlas = vec![0u8; (ATLAS_WIDTH * atlas_height) as usize];
+        let mut glyphs: HashMap<char, Glyph> = advances
+            .iter()
+            .map(|(&c, &(_, advance))| {
+                let glyph = Glyph {
+                    advance,
+                    bounds: None,
+                    uv_rect: [0.0; 4],
+                };
+                (c, glyph)
+            })
+            .collect();
+        for ((c, field), (x, y)) in fields.iter().zip(placements) {
+            for row in 0..field.height {
+                let start = ((y + row) * ATLAS_WIDTH + x) as usize;
+                let source = (row * field.width) as usize;
+                atlas[start..start + field.width as usize]
+                    .copy_from_slice(&field.values[source..source + field.width as usize]);
+            }
+            let glyph = glyphs.get_mut(c).expect("every field has an advance");
+            glyph.bounds = Some(field.bounds.map(|bound| bound / SDF_SIZE));
+            glyph.uv_rect = [
+                x as f32 / ATLAS_WIDTH as f32,
+                y as f32 / atlas_height as f32,
+                (x + field.width) as f32 / ATLAS_WIDTH as f32,
+                (y + field.height) as f32 / atlas_height as f32,
+            ];
+        }
+
+        let mut kerning = HashMap::new();
+        for (&left, &(left_id, _)) in &advances {
+            for (&right, &(right_id, _)) in &advances {
+                if let Some(value) = pair_kerning(&face, left_id, right_id)
+                    && value != 0
+                {
+                    kerning.insert((left, right), value as f32 / units_per_em);
+                }
+            }
+        }
+
+        let (texture, bind_group) = self.atlas_bind_group(device, queue, &atlas, atlas_height);
+        info!(
+            "Font with {} glyphs baked into a {ATLAS_WIDTH}x{atlas_height} atlas",
+            glyphs.len()
+        );
+
+        let ascender = face.ascender() as f32 / units_per_em;
+        let descender = face.descender() as f32 / units_per_em;
+        self.fonts.push(Font {
+            metrics: FontMetrics {
+                glyphs,
+                kerning,
+                ascender,
+                line_height: ascender - descender + face.line_gap() as f32 / units_per_em,
+            },
+            atlas,
+            atlas_height,
+            texture,
+            bind_group,
+        });
+        Ok(self.fonts.len() - 1)
+    }
+
+    /// The same fonts on another device, without the text queued for the next frame.
+    pub fn recreate(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        swapchain_format: TextureFormat,
+        uniform_layout: &BindGroupLayout,
+    ) -> Self {
+        let mut text = Self::new(device, swapchain_format, uniform_layout);
+        text.fonts = self
+            .fonts
+            .iter()
+            .map(|font| {
+                let (texture, bind_group) =
+                    text.atlas_bind_group(device, queue, &font.atlas, font.atlas_height);
+                Font {
+                    metrics: font.metrics.clone(),
+                    atlas: font.atlas.clone(),
+                    atlas_height: font.atlas_height,
+                    texture,
+                    bind_group,
+                }
+            })
+            .collect();
+        text
+    }
+
+    /// Frees the font atlases and buffers. The renderer can't be used afterwards.
+    pub fn destroy(&mut self) {
+        for font in self.fonts.drain(..) {
+            font.texture.destroy();
+        }
+        self.vertex_buffer.destroy();
+        self.index_buffer.destroy();
+    }
+
+    pub fn font(&self, font: usize) -> Result<&Font> {
+        self.fonts
+            .get(font)
+            .ok_or_else(|| Error::invalid_handle("font", font))
+    }
+
+    /// Lays out the text and queues its glyphs for the next frame.
+    pub fn add(&mut self, font: usize, text: &str, style: &TextStyle) -> Result<()> {
+        let metrics = &self.font(font)?.metrics;
+        let size = style.size.max(1e-3);
+        let lines = metrics.layout(text, style.max_width.map(|width| width / size));
+        let box_width = style
+            .max_width
+            .unwrap_or_else(|| lines.iter().map(|line| line.width).fold(0.0, f32::max) * size);
+        let quads = metrics.quads(&lines, &TextStyle { size, ..*style }, box_width);
+
+        // one unit of distance is this many pixels on screen
+        let field_pixels = 2.0 * SDF_SPREAD * size / SDF_SIZE;
+        let outline = (style.outline_width / field_pixels).clamp(0.0, 0.49);
+        let start = (self.vertices.len() / 4) as u32;
+        if style.shadow_color[3] > 0.0 {
+            let blur = style.shadow_blur / field_pixels;
+            let offset = Vec2::from(style.shadow_offset);
+            for (rect, uv_rect) in &quads {
+                let rect = [
+                    rect[0] + offset.x,
+                    rect[1] + offset.y,
+                    rect[2] + offset.x,
+                    rect[3] + offset.y,
+                ];
+                self.vertices.extend(Self::vertices(
+                    rect,
+                    *uv_rect,
+                    style.shadow_color,
+                    style.shadow_color,
+                    [outline, blur],
+                ));
+            }
+        }
+        for (rect, uv_rect) in &quads {
+            self.vertices.extend(Self::vertices(
+                *rect,
+                *uv_rect,
+                style.color,
+                style.outline_color,
+                [outline, 0.0],
+            ));
+        }
+        let end = (self.vertices.len() / 4) as u32;
+        match self.queued.last_mut() {
+            Some(draw) if draw.font == font && draw.quads.end == start => draw.quads.end = end,
+            _ => self.queued.push(TextDraw {
+                font,
+                quads: start..end,
+            }),
+        }
+        Ok(())
+    }
+
+    /// Uploads the text queued since the last frame, which starts the next one empty.
+    pub fn prepare(&mut self, device: &Device, queue: &Queue) {
+        self.draws = std::mem::take(&mut self.queued);
+        let quads = self.vertices.len() / 4;
+        if quads > self.capacity {
+            self.capacity = quads.next_power_of_two();
+            (self.vertex_buffer, self.index_buffer) = Self::create_buffers(device, self.capacity);
+        }
+        if quads > 0 {
+            queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+        }
+        self.vertices.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.draws.is_empty()
+    }
+
+    /// Draws the prepared text, `uniform_bind_group` provides the screen size.
+    pub fn draw(&self, render_pass: &mut RenderPass, uniform_bind_group: &BindGroup) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, uniform_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        for draw in &self.draws {
+            render_pass.set_bind_group(1, &self.fonts[draw.font].bind_group, &[]);
+            render_pass.draw_indexed(draw.quads.start * 6..draw.quads.end * 6, 0, 0..1);
+        }
+    }
+
+    fn vertices(
+        [left, top, right, bottom]: [f32; 4],
+        [u0, v0, u1, v1]: [f32; 4],
+        color: [f32; 4],
+        outline_color: [f32; 4],
+        style: [f32; 2],
+    ) -> [TextVertex; 4] {
+        [
+            ([left, top], [u0, v0]),
+            ([right, top], [u1, v0]),
+            ([right, bottom], [u1, v1]),
+            ([left, bottom], [u0, v1]),
+        ]
+        .map(|(pos, tex_pos)| TextVertex {
+            pos,
+            tex_pos,
+            color,
+            outline_color,
+            style,
+        })
+    }
+
+    // the index buffer never changes, it only grows with the vertex buffer
+    fn atlas_bind_group(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        atlas: &[u8],
+        atlas_height: u32,
+    ) -> (Texture, BindGroup) {
+        let texture = device.create_texture_with_data(
+            queue,
+            &TextureDescriptor {
+                label: Some("Font Atlas"),
+                size: Extent3d {
+                    width: ATLAS_WIDTH,
+                    height: atlas_height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::R8Unorm,
+                usage: TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            atlas,
+        );
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Font Atlas Bind Group"),
+            layout: &self.atlas_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+        (texture, bind_group)
+    }
+
+    fn create_buffers(device: &Device, capacity: usize) -> (Buffer, Buffer) {
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Text Vertex Buffer"),
+            size: (capacity * 4 * std::mem::size_of::<TextVertex>()) as u64,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let indices: Vec<u32> = (0..capacity as u32)
+            .flat_map(|quad| [0, 1, 2, 0, 2, 3].map(|index| quad * 4 + index))
+            .collect();
+        let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Text Index Buffer"),
+            contents: bytemuck::cast_slice(&indices),
+            usage: BufferUsages::INDEX,
+        });
+        (vertex_buffer, index_buffer)
+    }
+
+    fn create_pipeline(
+        device: &Device,
+        swapchain_format: TextureFormat,
+        uniform_layout: &BindGroupLayout,
+        atlas_layout: &BindGroupLayout,
+    ) -> RenderPipeline {
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Text Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("./shader/text.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Text Pipeline Layout"),
+            bind_group_layouts: &[uniform_layout, atlas_layout],
+            push_constant_ranges: &[],
+        });
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Text Render Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: PipelineCompilationOptions::default(),
+                buffers: &[VertexBufferLayout {
+                    array_stride: std::mem::size_of::<TextVertex>() as BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &TEXT_ATTRIBUTES,
+                }],
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: PipelineCompilationOptions::default(),
+                targets: &[Some(ColorTargetState {
+                    format: swapchain_format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        })
+    }
+}
+
+/// Horizontal kerning of a glyph pair in font units, from the GPOS pair adjustments
+/// or else the legacy kern table.
+fn pair_kerning(face: &Face, left: GlyphId, right: GlyphId) -> Option<i16> {
+    if let Some(gpos) = face.tables().gpos {
+        for lookup in gpos.lookups {
+            for i in 0..lookup.subtables.len() {
+                let Some(PositioningSubtable::Pair(pair)) =
+                    lookup.subtables.get::<PositioningSubtable>(i)
+                else {
+                    continue;
+                };
+                let Some(index) = pair.coverage().get(left) else {
+                    continue;
+                };
+                let record = match pair {
+                    PairAdjustment::Format1 { sets, .. } => {
+                        sets.get(index).and_then(|set| set.get(right))
+                    }
+                    PairAdjustment::Format2 {
+                        classes, matrix, ..
+                    } => matrix.get((classes.0.get(left), classes.1.get(right))),
+                };
+                if let Some((first, _)) = record {
+                    return Some(first.x_advance);
+                }
+            }
+        }
+    }
+    let kern = face.tables().kern?;
+    kern.subtables
+        .into_iter()
+        .filter(|subtable| subtable.horizontal && !subtable.variable)
+        .find_map(|subtable| subtable.glyphs_kerning(left, right))
+}
+
+/// Glyph outline flattened to line segments, in font units.
+#[derive(Default)]
+struct Outline {
+    segments: Vec<(Vec2, Vec2)>,
+    start: Vec2,
+    current: Vec2,
+}
+
+impl Outline {
+    fn push(&mut self, to: Vec2) {
+        self.segments.push((self.current, to));
+        self.current = to;
+    }
+}
+
+impl OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.start = Vec2::new(x, y);
+        self.current = self.start;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.push(Vec2::new(x, y));
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (from, control, to) = (self.current, Vec2::new(x1, y1), Vec2::new(x, y));
+        for step in 1..=CURVE_STEPS {
+            let t = step as f32 / CURVE_STEPS as f32;
+            self.push(from.lerp(control, t).lerp(control.lerp(to, t), t));
+        }
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let from = self.current;
+        let (c1, c2, to) = (Vec2::new(x1, y1), Vec2::new(x2, y2), Vec2::new(x, y));
+        for step in 1..=CURVE_STEPS {
+            let t = step as f32 / CURVE_STEPS as f32;
+            let a = from.lerp(c1, t).lerp(c1.lerp(c2, t), t);
+            let b = c1.lerp(c2, t).lerp(c2.lerp(to, t), t);
+            self.push(a.lerp(b, t));
+        }
+    }
+
+    fn close(&mut self) {
+        if self.current != self.start {
+            self.push(self.start);
+        }
+    }
+}
+
+/// Distance field of one glyph, 0.5 on the outline and growing towards the inside.
+struct GlyphField {
+    width: u32,
+    height: u32,
+    values: Vec<u8>,
+    /// Left, top, right and bottom edge relative to the pen, in atlas pixels with y down.
+    bounds: [f32; 4],
+}
+
+fn glyph_field(segments: &[(Vec2, Vec2)], rect: ttf_parser::Rect, scale: f32) -> GlyphField {
+    let left = (rect.x_min as f32 * scale - SDF_SPREAD).floor();
+    let top = (rect.y_max as f32 * scale + SDF_SPREAD).ceil();
+    let width = ((rect.x_max as f32 * scale + SDF_SPREAD).ceil() - left) as u32;
+    let height = (top - (rect.y_min as f32 * scale - SDF_SPREAD).floor()) as u32;
+    let segments: Vec<(Vec2, Vec2)> = segments
+        .iter()
+        .map(|&(a, b)| (a * scale, b * scale))
+        .collect();
+
+    let mut values = Vec::with_capacity((width * height) as usize);
+    for row in 0..height {
+        for column in 0..width {
+            let p = Vec2::new(left + column as f32 + 0.5, top - row as f32 - 0.5);
+            let mut distance = f32::MAX;
+            let mut winding = 0;
+            for &(a, b) in &segments {
+                let edge = b - a;
+                let t = ((p - a).dot(edge) / edge.length_squared().max(1e-6)).clamp(0.0, 1.0);
+                distance = distance.min(p.distance(a + edge * t));
+                // nonzero winding rule, as TrueType and CFF outlines use
+                let side = edge.perp_dot(p - a);
+                if a.y <= p.y {
+                    if b.y > p.y && side > 0.0 {
+                        winding += 1;
+                    }
+                } else if b.y <= p.y && side < 0.0 {
+                    winding -= 1;
+                }
+            }
+            let signed = if winding != 0 { distance } else { -distance };
+            let value = (0.5 + signed / (2.0 * SDF_SPREAD)).clamp(0.0, 1.0);
+            values.push((value * 255.0).round() as u8);
+        }
+    }
+    GlyphField {
+        width,
+        height,
+        values,
+        bounds: [left, -top, left + width as f32, height as f32 - top],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // monospaced, half an em per glyph, with a kerned "AV" pair
+    fn metrics() -> FontMetrics {
+        let glyph = Glyph {
+            advance: 0.5,
+            bounds: Some([0.0, -0.7, 0.5, 0.0]),
+            uv_rect: [0.0, 0.0, 0.1, 0.1],
+        };
+        let mut glyphs: HashMap<char, Glyph> = ('A'..='Z').map(|c| (c, glyph)).collect();
+        glyphs.insert('?', glyph);
+        glyphs.insert(
+            ' ',
+            Glyph {
+                bounds: None,
+                ..glyph
+            },
+        );
+        FontMetrics {
+            glyphs,
+            kerning: HashMap::from([(('A', 'V'), -0.1)]),
+            ascender: 0.8,
+            line_height: 1.2,
+        }
+    }
+
+    fn line_text(line: &Line) -> String {
+        line.glyphs.iter().map(|&(c, _)| c).collect()
+    }
+
+    #[test]
+    fn pen_positions() {
+        let lines = metrics().layout("AVA", None);
+        assert_eq!(lines.len(), 1);
+        let pens: Vec<f32> = lines[0].glyphs.iter().map(|&(_, pen)| pen).collect();
+        assert_eq!(pens, [0.0, 0.4, 0.9]);
+        assert_eq!(lines[0].width, 1.4);
+    }
+
+    #[test]
+    fn wraps_at_spaces() {
+        let lines = metrics().layout("AB CD EF\nG", Some(3.0));
+        let text: Vec<String> = lines.iter().map(line_text).collect();
+        assert_eq!(text, ["AB CD", "EF", "G"]);
+        assert_eq!(lines[0].width, 2.5);
+        assert_eq!(lines[1].glyphs[0].1, 0.0);
+    }
+
+    #[test]
+    fn long_words_overflow() {
+        let lines = metrics().layout("ABCDEFGH IJ", Some(2.0));
+        let text: Vec<String> = lines.iter().map(line_text).collect();
+        assert_eq!(text, ["ABCDEFGH", "IJ"]);
+        assert!(lines[0].width > 2.0);
+    }
+
+    #[test]
+    fn aligned_quads() {
+        let metrics = metrics();
+        let lines = metrics.layout("AB\nC", None);
+        let style = TextStyle {
+            position: [10.0, 20.0],
+            size: 10.0,
+            align: TextAlign::Right,
+            ..Default::default()
+        };
+        let quads = metrics.quads(&lines, &style, 10.0);
+        let rects: Vec<[f32; 4]> = quads.iter().map(|&(rect, _)| rect).collect();
+        assert_eq!(
+            rects,
+            [
+                [10.0, 21.0, 15.0, 28.0],
+                [15.0, 21.0, 20.0, 28.0],
+                [15.0, 33.0, 20.0, 40.0],
+            ]
+        );
+    }
+}
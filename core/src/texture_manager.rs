@@ -1,6 +1,9 @@
+use std::{cell::RefCell, rc::Rc};
+
 use image::GenericImageView;
 use log::info;
-use wasm_bindgen::{JsCast, JsError, JsValue};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{JsCast, JsValue};
 use web_sys::js_sys::{Array, Uint8Array};
 use wgpu::{
     AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
@@ -10,21 +13,80 @@ use wgpu::{
     TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension,
 };
 
+use crate::{
+    error::{Error, Result},
+    resource_cache::ResourceCache,
+};
+
 pub struct TextureHolder {
     pub texture: Texture,
     pub texture_view: TextureView,
 }
 
+/// Sampling of a single texture, e.g. `{ "address_mode_u": "repeat", "mag_filter": "nearest" }`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SamplerSettings {
+    pub address_mode_u: AddressMode,
+    pub address_mode_v: AddressMode,
+    pub mag_filter: FilterMode,
+    pub min_filter: FilterMode,
+    pub mipmap_filter: FilterMode,
+}
+
+impl Default for SamplerSettings {
+    fn default() -> Self {
+        Self {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: FilterMode::Nearest,
+        }
+    }
+}
+
+impl SamplerSettings {
+    pub fn create_sampler(&self, device: &Device, label: &str) -> Sampler {
+        device.create_sampler(&SamplerDescriptor {
+            label: Some(label),
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            ..Default::default()
+        })
+    }
+}
+
+/// Encoded image bytes together with how the texture should be sampled.
+#[derive(Clone)]
+pub struct TextureSource {
+    pub data: Vec<u8>,
+    pub sampler: SamplerSettings,
+}
+
 pub struct TextureManager {
-    pub textures: Vec<TextureHolder>,
-    pub sampler: Sampler,
+    // shared with the other canvases on the device that use the same images
+    pub textures: Vec<Rc<TextureHolder>>,
+    // one per texture, bound after the textures
+    pub texture_samplers: Vec<Sampler>,
     pub bind_group_layout: BindGroupLayout,
     pub bind_group: BindGroup,
+    // decoded again when the device is lost
+    sources: Vec<TextureSource>,
 }
 
 impl TextureManager {
-    pub fn new(device: &Device, queue: &Queue, textures_data: Array) -> Result<Self, JsError> {
-        let mut textures = Vec::new();
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        cache: &RefCell<ResourceCache>,
+        textures_data: Array,
+    ) -> Result<Self> {
+        let mut sources = Vec::new();
 
         // Include predefined texture
         // let predefined = include_bytes!("./textures/download20250505175626.png");
@@ -40,21 +102,42 @@ impl TextureManager {
             let mut buffer = vec![0u8; u8_array.length() as usize];
             u8_array.copy_to(&mut buffer[..]);
 
-            let texture = Self::create_texture(device, queue, &buffer)?;
-            textures.push(texture);
+            sources.push(TextureSource {
+                data: buffer,
+                sampler: SamplerSettings::default(),
+            });
         }
 
-        // Create sampler
-        let sampler = device.create_sampler(&SamplerDescriptor {
-            label: Some("Texture Sampler"),
-            address_mode_u: AddressMode::ClampToEdge,
-            address_mode_v: AddressMode::ClampToEdge,
-            address_mode_w: AddressMode::ClampToEdge,
-            mag_filter: FilterMode::Linear,
-            min_filter: FilterMode::Nearest,
-            mipmap_filter: FilterMode::Nearest,
-            ..Default::default()
-        });
+        Self::from_sources(device, queue, cache, &sources)
+    }
+
+    /// Images another texture manager on the device decoded already are reused from
+    /// `cache`.
+    pub fn from_sources(
+        device: &Device,
+        queue: &Queue,
+        cache: &RefCell<ResourceCache>,
+        sources: &[TextureSource],
+    ) -> Result<Self> {
+        let textures = sources
+            .iter()
+            .map(|source| {
+                cache.borrow_mut().texture(&source.data, || {
+                    Self::create_texture(device, queue, &source.data)
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Create samplers
+        let texture_samplers: Vec<Sampler> = sources
+            .iter()
+            .enumerate()
+            .map(|(i, source)| {
+                source
+                    .sampler
+                    .create_sampler(device, &format!("Texture Sampler {i}"))
+            })
+            .collect();
 
         // Create bind group layout
         let mut layout_entries: Vec<BindGroupLayoutEntry> = vec![];
@@ -63,18 +146,10 @@ impl TextureManager {
             entries: if textures.is_empty() {
                 &layout_entries
             } else {
-                layout_entries.push(
-                    BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: ShaderStages::FRAGMENT,
-                        ty: BindingType::Sampler(SamplerBindingType::Filtering),
-                        count: None,
-                    }
-                );
                 layout_entries.extend(
                     (0..textures.len()).map(|index| {
                         BindGroupLayoutEntry {
-                            binding: 1 + index as u32,
+                            binding: index as u32,
                             visibility: ShaderStages::FRAGMENT,
                             ty: BindingType::Texture {
                                 multisampled: false,
@@ -85,6 +160,16 @@ impl TextureManager {
                         }
                     })
                 );
+                layout_entries.extend(
+                    (0..textures.len()).map(|index| {
+                        BindGroupLayoutEntry {
+                            binding: (textures.len() + index) as u32,
+                            visibility: ShaderStages::FRAGMENT,
+                            ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                            count: None,
+                        }
+                    })
+                );
                 &layout_entries
             }
         });
@@ -97,33 +182,59 @@ impl TextureManager {
             entries: if textures.is_empty() {
                 &entries
             } else {
-                entries.push(
-                    BindGroupEntry {
-                        binding: 0,
-                        resource: BindingResource::Sampler(&sampler),
-                    }
-                );
                 entries.extend(
                     textures.iter().enumerate().map(|(i, texture)| BindGroupEntry {
-                        binding: 1 + i as u32, // binding index increases for each texture
+                        binding: i as u32, // binding index increases for each texture
                         resource: BindingResource::TextureView(&texture.texture_view),
                     })
                 );
+                entries.extend(
+                    texture_samplers.iter().enumerate().map(|(i, sampler)| BindGroupEntry {
+                        binding: (textures.len() + i) as u32,
+                        resource: BindingResource::Sampler(sampler),
+                    })
+                );
                 &entries
             }
         });
 
         Ok(Self {
             textures,
-            sampler,
+            texture_samplers,
             bind_group_layout,
             bind_group,
+            sources: sources.to_vec(),
         })
     }
 
-    fn create_texture(device: &Device, queue: &Queue, data: &[u8]) -> Result<TextureHolder, JsError> {
+    /// Uploads the same textures on another device.
+    pub fn recreate(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        cache: &RefCell<ResourceCache>,
+    ) -> Result<Self> {
+        Self::from_sources(device, queue, cache, &self.sources)
+    }
+
+    /// Frees the textures no other canvas on the device uses. The manager can't be used
+    /// afterwards.
+    pub fn destroy(&mut self) {
+        for texture in self.textures.drain(..) {
+            if let Some(texture) = Rc::into_inner(texture) {
+                texture.texture.destroy();
+            }
+        }
+    }
+
+    /// Where the textures were decoded from.
+    pub fn sources(&self) -> &[TextureSource] {
+        &self.sources
+    }
+
+    fn create_texture(device: &Device, queue: &Queue, data: &[u8]) -> Result<TextureHolder> {
         let img = image::load_from_memory(data)
-            .map_err(|e| JsError::new(&format!("Failed to load image: {e}")))?;
+            .map_err(|e| Error::TextureDecode(format!("Failed to load image: {e}")))?;
 
         let rgba = img.to_rgba8();
         let (width, height) = img.dimensions();
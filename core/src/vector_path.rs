@@ -0,0 +1,308 @@
+use lyon::{
+    algorithms::aabb::bounding_box,
+    extra::parser::{ParserOptions, PathParser, Source},
+    geom::{Angle, Box2D, point, vector},
+    path::{Path, Polygon, Winding, builder::BorderRadii},
+    tessellation::{
+        BuffersBuilder, FillOptions, FillTessellator, FillVertex, LineCap, LineJoin, StrokeOptions,
+        StrokeTessellator, StrokeVertex, VertexBuffers,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    buffer_manager::Vertex,
+    error::{Error, Result},
+    mesh::MeshData,
+};
+
+/// Outline of a 2D vector shape. Shapes with a size are centered on the origin.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum VectorShape {
+    /// SVG path data, e.g. `M 0 0 L 10 0 Q 10 10 0 10 Z`.
+    Path {
+        data: String,
+    },
+    Polygon {
+        points: Vec<[f32; 2]>,
+        closed: Option<bool>,
+    },
+    /// Rectangle with corners rounded by `radius`.
+    Rect {
+        size: [f32; 2],
+        radius: Option<f32>,
+    },
+    Ellipse {
+        radii: [f32; 2],
+    },
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FillRule {
+    #[default]
+    NonZero,
+    EvenOdd,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StrokeJoin {
+    #[default]
+    Miter,
+    Round,
+    Bevel,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StrokeCap {
+    #[default]
+    Butt,
+    Round,
+    Square,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct StrokeStyle {
+    pub color: [f32; 3],
+    pub width: f32,
+    pub join: StrokeJoin,
+    pub cap: StrokeCap,
+    /// Miter joins sharper than this ratio of miter length to width are beveled.
+    pub miter_limit: f32,
+}
+
+impl Default for StrokeStyle {
+    fn default() -> Self {
+        Self {
+            color: [0.0, 0.0, 0.0],
+            width: 1.0,
+            join: StrokeJoin::Miter,
+            cap: StrokeCap::Butt,
+            miter_limit: 4.0,
+        }
+    }
+}
+
+/// A filled and/or stroked vector shape, e.g. `{ type: "rect", size: [4, 2], radius: 0.5,
+/// fill: [1, 0.5, 0], stroke: { width: 0.1, join: "round" } }`. Pass `fill: null` to
+/// only stroke the outline.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VectorPath {
+    #[serde(flatten)]
+    pub shape: VectorShape,
+    #[serde(default = "white")]
+    pub fill: Option<[f32; 3]>,
+    #[serde(default)]
+    pub stroke: Option<StrokeStyle>,
+    #[serde(default)]
+    pub fill_rule: FillRule,
+    /// Maximum distance between curves and the line segments approximating them.
+    #[serde(default)]
+    pub tolerance: Option<f32>,
+}
+
+fn white() -> Option<[f32; 3]> {
+    Some([1.0, 1.0, 1.0])
+}
+
+impl VectorPath {
+    /// Tessellates the shape into a flat mesh facing +Z. Path units become world units,
+    /// with y flipped so SVG paths appear upright, and texture coordinates span the
+    /// bounds of the outline. Edges are as smooth as the render target's antialiasing.
+    pub fn mesh(&self) -> Result<MeshData> {
+        let path = self.shape.path()?;
+        let bounds = bounding_box(path.iter());
+        let tolerance = self.tolerance.unwrap_or(FillOptions::DEFAULT_TOLERANCE);
+        let mut geometry: VertexBuffers<Vertex, u32> = VertexBuffers::new();
+        if let Some(color) = self.fill {
+            let rule = match self.fill_rule {
+                FillRule::NonZero => lyon::tessellation::FillRule::NonZero,
+                FillRule::EvenOdd => lyon::tessellation::FillRule::EvenOdd,
+            };
+            let options = FillOptions::tolerance(tolerance).with_fill_rule(rule);
+            FillTessellator::new()
+                .tessellate_path(
+                    &path,
+                    &options,
+                    &mut BuffersBuilder::new(&mut geometry, |vertex: FillVertex| {
+                        path_vertex(vertex.position(), color, &bounds, 0.0)
+                    }),
+                )
+                .map_err(|err| Error::Asset(format!("Failed to fill path: {err}")))?;
+        }
+        // a stroke without width only yields slivers
+        if let Some(stroke) = self.stroke.filter(|stroke| stroke.width > 0.0) {
+            let join = match stroke.join {
+                StrokeJoin::Miter => LineJoin::Miter,
+                StrokeJoin::Round => LineJoin::Round,
+                StrokeJoin::Bevel => LineJoin::Bevel,
+            };
+            let cap = match stroke.cap {
+                StrokeCap::Butt => LineCap::Butt,
+                StrokeCap::Round => LineCap::Round,
+                StrokeCap::Square => LineCap::Square,
+            };
+            let options = StrokeOptions::tolerance(tolerance)
+                .with_line_width(stroke.width)
+                .with_line_join(join)
+                .with_line_cap(cap)
+                .with_miter_limit(stroke.miter_limit.max(StrokeOptions::MINIMUM_MITER_LIMIT));
+            // slightly in front of the fill so the two don't fight over depth
+            let lift = bounds.size().to_vector().length().max(1.0) * 1e-4;
+            StrokeTessellator::new()
+                .tessellate_path(
+                    &path,
+                    &options,
+                    &mut BuffersBuilder::new(&mut geometry, |vertex: StrokeVertex| {
+                        path_vertex(vertex.position(), stroke.color, &bounds, lift)
+                    }),
+                )
+                .map_err(|err| Error::Asset(format!("Failed to stroke path: {err}")))?;
+        }
+
+        if geometry.indices.is_empty() {
+            return Err(Error::InvalidInput(
+                "Path has no area to fill or outline to stroke".to_string(),
+            ));
+        }
+        let mut mesh = MeshData::new(geometry.vertices, geometry.indices);
+        mesh.name = Some("Path".to_string());
+        Ok(mesh)
+    }
+}
+
+impl VectorShape {
+    fn path(&self) -> Result<Path> {
+        let mut builder = Path::builder();
+        match self {
+            VectorShape::Path { data } => {
+                let mut builder = Path::builder_with_attributes(0);
+                PathParser::new()
+                    .parse(
+                        &ParserOptions::DEFAULT,
+                        &mut Source::new(data.chars()),
+                        &mut builder,
+                    )
+                    .map_err(|err| Error::InvalidInput(format!("Invalid SVG path: {err}")))?;
+                return Ok(builder.build());
+            }
+            VectorShape::Polygon { points, closed } => {
+                let points: Vec<_> = points.iter().map(|&[x, y]| point(x, y)).collect();
+                builder.add_polygon(Polygon {
+                    points: &points,
+                    closed: closed.unwrap_or(true),
+                });
+            }
+            VectorShape::Rect { size, radius } => {
+                let half = vector(size[0], size[1]) / 2.0;
+                let radius = radius.unwrap_or(0.0).clamp(0.0, half.x.min(half.y));
+                builder.add_rounded_rectangle(
+                    &Box2D::new(half.to_point() * -1.0, half.to_point()),
+                    &BorderRadii::new(radius),
+                    Winding::Positive,
+                );
+            }
+            VectorShape::Ellipse { radii } => {
+                builder.add_ellipse(
+                    point(0.0, 0.0),
+                    vector(radii[0], radii[1]),
+                    Angle::zero(),
+                    Winding::Positive,
+                );
+            }
+        }
+        Ok(builder.build())
+    }
+}
+
+fn path_vertex(
+    position: lyon::math::Point,
+    color: [f32; 3],
+    bounds: &Box2D<f32>,
+    z: f32,
+) -> Vertex {
+    let size = bounds.size();
+    let tex_pos = [
+        (position.x - bounds.min.x) / size.width.max(f32::EPSILON),
+        (position.y - bounds.min.y) / size.height.max(f32::EPSILON),
+    ];
+    Vertex {
+        pos: [position.x, -position.y, z],
+        color,
+        tex_pos,
+        normal: [0.0, 0.0, 1.0],
+        tangent: [1.0, 0.0, 0.0, 1.0],
+        tex_pos_1: tex_pos,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filled(shape: VectorShape) -> VectorPath {
+        VectorPath {
+            shape,
+            fill: white(),
+            stroke: None,
+            fill_rule: FillRule::NonZero,
+            tolerance: None,
+        }
+    }
+
+    #[test]
+    fn rect() {
+        let mesh = filled(VectorShape::Rect {
+            size: [4.0, 2.0],
+            radius: None,
+        })
+        .mesh()
+        .unwrap();
+        assert!(!mesh.is_empty());
+        assert!(
+            mesh.vertices
+                .iter()
+                .all(|vertex| vertex.normal == [0.0, 0.0, 1.0])
+        );
+    }
+
+    #[test]
+    fn empty_path() {
+        let err = filled(VectorShape::Path {
+            data: String::new(),
+        })
+        .mesh()
+        .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput(_)), "{err}");
+    }
+
+    #[test]
+    fn degenerate_shapes() {
+        let flat = filled(VectorShape::Rect {
+            size: [4.0, 0.0],
+            radius: None,
+        });
+        assert!(matches!(flat.mesh(), Err(Error::InvalidInput(_))));
+        let zero_width = VectorPath {
+            fill: None,
+            stroke: Some(StrokeStyle {
+                width: 0.0,
+                ..Default::default()
+            }),
+            ..filled(VectorShape::Polygon {
+                points: vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0]],
+                closed: None,
+            })
+        };
+        assert!(matches!(zero_width.mesh(), Err(Error::InvalidInput(_))));
+    }
+}
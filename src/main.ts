@@ -3,6 +3,8 @@ import wasmInit, * as wasmCore from 'wasm-core';
 
 let mouseX = 0;
 let mouseY = 0;
+let mouseButtons = 0;
+let mouseWheel = 0;
 
 async function loadTexture(url: string): Promise<ArrayBuffer> {
     const response = await fetch(url);
@@ -24,13 +26,28 @@ async function setup(): Promise<wasmCore.App> {
 
     const app = wasmCore.App.setup(
         canvas,
-        textures.map(tex => new Uint8Array(tex))
+        textures.map(tex => new Uint8Array(tex)),
+        { format: 'linear', presentMode: 'autoVsync', alphaMode: 'auto' }
     );
 
     canvas.onmousemove = (event) => {
         mouseX = event.clientX - canvas.offsetLeft;
         mouseY = event.clientY - canvas.offsetTop;
+        mouseButtons = event.buttons;
     }
+    canvas.onmousedown = (event) => mouseButtons = event.buttons;
+    canvas.onmouseup = (event) => mouseButtons = event.buttons;
+    canvas.oncontextmenu = (event) => event.preventDefault();
+    canvas.onwheel = (event) => {
+        event.preventDefault();
+        mouseWheel += event.deltaY;
+    }
+
+    new ResizeObserver(() => {
+        canvas.width = canvas.clientWidth;
+        canvas.height = canvas.clientHeight;
+        app.then(app => app.resize(canvas.width, canvas.height));
+    }).observe(canvas);
     return app;
 }
 
@@ -38,8 +55,9 @@ function update(app: wasmCore.App, time: number, delta: number) {
     app.update(
         time,
         delta,
-        { x: mouseX, y: mouseY },
+        { x: mouseX, y: mouseY, buttons: mouseButtons, wheel: mouseWheel },
     )
+    mouseWheel = 0;
 }
 
 function render(app: wasmCore.App) {